@@ -0,0 +1,105 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::Rng;
+
+use hw2_code::batch::QueryOrder;
+use hw2_code::rank_support::RankSupport;
+use hw2_code::select_support::SelectSupport;
+
+mod common;
+use common::{label, random_bit_vector, random_sparse_array, rng};
+
+// The batches only pay off once the tables no longer fit in the cache,
+// so these go well past the sizes of the other query benches
+const SIZES: [usize; 2] = [1 << 20, 1 << 26];
+const SPARSE_SIZES: [usize; 2] = [1 << 20, 1 << 24];
+const DENSITIES: [f64; 2] = [0.01, 0.5];
+const SPARSE_DENSITIES: [f64; 2] = [0.01, 0.1];
+const BATCH_SIZE: usize = 1 << 16;
+
+fn batch_queries(range: std::ops::Range<u64>) -> Vec<u64> {
+    let mut rng = rng(range.end ^ BATCH_SIZE as u64);
+    (0..BATCH_SIZE).map(|_| rng.gen_range(range.clone())).collect()
+}
+
+const ORDERS: [(&str, QueryOrder); 2] = [("batch", QueryOrder::AsGiven), ("batch_sorted", QueryOrder::Sorted)];
+
+fn rank1(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch/rank1");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    for size in SIZES {
+        for density in DENSITIES {
+            let bit_vector = random_bit_vector(size, density);
+            let rank_support = RankSupport::new(&bit_vector);
+            let positions: Vec<usize> = batch_queries(0..size as u64 + 1).into_iter().map(|i| i as usize).collect();
+            let mut out = vec![0; BATCH_SIZE];
+            group.bench_function(BenchmarkId::new("loop", label(size, density)), |b| {
+                b.iter(|| {
+                    for (slot, &i) in positions.iter().enumerate() {
+                        out[slot] = rank_support.rank1(i);
+                    }
+                })
+            });
+            for (name, order) in ORDERS {
+                group.bench_function(BenchmarkId::new(name, label(size, density)), |b| {
+                    b.iter(|| rank_support.rank1_batch(&positions, &mut out, order))
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+fn select1(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch/select1");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    for size in SIZES {
+        for density in DENSITIES {
+            let bit_vector = random_bit_vector(size, density);
+            let rank_support = RankSupport::new(&bit_vector);
+            let select_support = SelectSupport::new(&rank_support);
+            let ranks = batch_queries(1..bit_vector.count_ones() as u64 + 1);
+            let mut out = vec![0; BATCH_SIZE];
+            group.bench_function(BenchmarkId::new("loop", label(size, density)), |b| {
+                b.iter(|| {
+                    for (slot, &i) in ranks.iter().enumerate() {
+                        out[slot] = select_support.select1(i);
+                    }
+                })
+            });
+            for (name, order) in ORDERS {
+                group.bench_function(BenchmarkId::new(name, label(size, density)), |b| {
+                    b.iter(|| select_support.select1_batch(&ranks, &mut out, order))
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+fn get_at_index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch/get_at_index");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    for size in SPARSE_SIZES {
+        for density in SPARSE_DENSITIES {
+            let sparse_array = random_sparse_array(size, density);
+            let indices = batch_queries(0..size as u64);
+            let mut out = vec![None; BATCH_SIZE];
+            group.bench_function(BenchmarkId::new("loop", label(size, density)), |b| {
+                b.iter(|| {
+                    for (slot, &i) in indices.iter().enumerate() {
+                        out[slot] = sparse_array.get(i);
+                    }
+                })
+            });
+            for (name, order) in ORDERS {
+                group.bench_function(BenchmarkId::new(name, label(size, density)), |b| {
+                    b.iter(|| sparse_array.get_at_index_batch(&indices, &mut out, order))
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, rank1, select1, get_at_index);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use hw2_code::bit_vector::BitVector;
+
+mod common;
+use common::{label, queries, random_bit_vector, DENSITIES, NUM_QUERIES, SIZES};
+
+fn get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bit_vector/get");
+    group.throughput(Throughput::Elements(NUM_QUERIES as u64));
+    for size in SIZES {
+        let bit_vector = random_bit_vector(size, 0.5);
+        let positions = queries(0..size as u64);
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| positions.iter().filter(|&&i| bit_vector.get(i as usize)).count())
+        });
+    }
+    group.finish();
+}
+
+fn set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bit_vector/set");
+    group.throughput(Throughput::Elements(NUM_QUERIES as u64));
+    for size in SIZES {
+        let mut bit_vector = BitVector::new(size);
+        let positions = queries(0..size as u64);
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                for (k, &i) in positions.iter().enumerate() {
+                    bit_vector.set(i as usize, k % 2 == 0);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// Whole-vector work: filling a new vector bit by bit, counting the ones
+// and reading it back chunk by chunk the way rank does
+fn bulk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bit_vector/bulk");
+    for size in SIZES {
+        group.throughput(Throughput::Elements(size as u64));
+        for density in DENSITIES {
+            let source = random_bit_vector(size, density);
+            group.bench_function(BenchmarkId::new("fill", label(size, density)), |b| {
+                b.iter(|| {
+                    let mut bit_vector = BitVector::new(size);
+                    for i in 0..size {
+                        if source.get(i) {
+                            bit_vector.set(i, true);
+                        }
+                    }
+                    bit_vector
+                })
+            });
+            group.bench_function(BenchmarkId::new("count_ones", label(size, density)), |b| {
+                b.iter(|| black_box(&source).count_ones())
+            });
+            group.bench_function(BenchmarkId::new("read_chunks", label(size, density)), |b| {
+                b.iter(|| (0..size).step_by(10).map(|i| source.interpret_as_u64_int(i, (i + 10).min(size))).fold(0, u64::wrapping_add))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get, set, bulk);
+criterion_main!(benches);
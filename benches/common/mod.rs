@@ -0,0 +1,55 @@
+// Seeded inputs shared by the benches, so every run and every commit
+// measures the same bit vectors and the same queries
+#![allow(dead_code)]
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::bit_vector::BitVector;
+use hw2_code::sparse_array::SparseArray;
+
+pub const SEED: u64 = 0x5EED;
+
+// Sizes and densities every query bench runs over
+pub const SIZES: [usize; 3] = [1 << 12, 1 << 16, 1 << 20];
+pub const DENSITIES: [f64; 2] = [0.01, 0.5];
+
+// Queries per iteration, the throughput is reported per query
+pub const NUM_QUERIES: usize = 1024;
+
+pub fn rng(stream: u64) -> StdRng {
+    StdRng::seed_from_u64(SEED ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+pub fn random_bit_vector(size: usize, density: f64) -> BitVector {
+    let mut rng = rng(size as u64 ^ density.to_bits());
+    let mut bit_vector = BitVector::new(size);
+    for i in 0..size {
+        if rng.gen_bool(density) {
+            bit_vector.set(i, true);
+        }
+    }
+    bit_vector
+}
+
+pub fn random_sparse_array(size: usize, density: f64) -> SparseArray {
+    let bit_vector = random_bit_vector(size, density);
+    let mut sparse_array = SparseArray::create(size as u64);
+    for i in 0..size {
+        if bit_vector.get(i) {
+            sparse_array.append(format!("value {}", i), i as u64);
+        }
+    }
+    sparse_array.finalize();
+    sparse_array
+}
+
+// NUM_QUERIES values uniform in range, the same ones every run
+pub fn queries(range: std::ops::Range<u64>) -> Vec<u64> {
+    let mut rng = rng(range.start ^ range.end.rotate_left(32));
+    (0..NUM_QUERIES).map(|_| rng.gen_range(range.clone())).collect()
+}
+
+pub fn label(size: usize, density: f64) -> String {
+    format!("n={}/d={}", size, density)
+}
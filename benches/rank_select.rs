@@ -0,0 +1,63 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use hw2_code::rank_support::{BlockSizes, RankSupport};
+use hw2_code::select_support::SelectSupport;
+
+mod common;
+use common::{label, queries, random_bit_vector, DENSITIES, NUM_QUERIES, SIZES};
+
+fn construction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rank_support/new");
+    for size in SIZES {
+        group.throughput(Throughput::Elements(size as u64));
+        for density in DENSITIES {
+            let bit_vector = random_bit_vector(size, density);
+            group.bench_function(BenchmarkId::new("sequential", label(size, density)), |b| {
+                b.iter(|| RankSupport::new(&bit_vector))
+            });
+            // One thread per core, which only helps on a machine with several
+            group.bench_function(BenchmarkId::new("threads", label(size, density)), |b| {
+                b.iter(|| RankSupport::build_with_threads(&bit_vector, BlockSizes::Theoretical, 0).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn rank1(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rank_support/rank1");
+    group.throughput(Throughput::Elements(NUM_QUERIES as u64));
+    for size in SIZES {
+        for density in DENSITIES {
+            let bit_vector = random_bit_vector(size, density);
+            let positions = queries(0..size as u64 + 1);
+            for (preset, block_sizes) in [("theoretical", BlockSizes::Theoretical), ("speed", BlockSizes::Speed)] {
+                let rank_support = RankSupport::with_block_sizes(&bit_vector, block_sizes).unwrap();
+                group.bench_function(BenchmarkId::new(preset, label(size, density)), |b| {
+                    b.iter(|| positions.iter().map(|&i| rank_support.rank1(i as usize)).sum::<u64>())
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+fn select1(c: &mut Criterion) {
+    let mut group = c.benchmark_group("select_support/select1");
+    group.throughput(Throughput::Elements(NUM_QUERIES as u64));
+    for size in SIZES {
+        for density in DENSITIES {
+            let bit_vector = random_bit_vector(size, density);
+            let rank_support = RankSupport::new(&bit_vector);
+            let select_support = SelectSupport::new(&rank_support);
+            let ranks = queries(1..bit_vector.count_ones() as u64 + 1);
+            group.bench_function(BenchmarkId::from_parameter(label(size, density)), |b| {
+                b.iter(|| ranks.iter().map(|&i| select_support.select1(i)).fold(0, u64::wrapping_add))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, construction, rank1, select1);
+criterion_main!(benches);
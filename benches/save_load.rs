@@ -0,0 +1,60 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::path::PathBuf;
+
+use hw2_code::bit_vector::BitVector;
+use hw2_code::rank_support::RankSupport;
+use hw2_code::sparse_array::SparseArray;
+
+mod common;
+use common::{random_bit_vector, random_sparse_array, SIZES};
+
+fn temp_file(name: &str) -> String {
+    let path: PathBuf = std::env::temp_dir().join(format!("hw2_code_bench_{}_{}", std::process::id(), name));
+    path.to_string_lossy().into_owned()
+}
+
+fn bit_vector(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_load/bit_vector");
+    let file = temp_file("bit_vector");
+    for size in SIZES {
+        group.throughput(Throughput::Bytes(size as u64 / 8));
+        let bit_vector = random_bit_vector(size, 0.5);
+        group.bench_function(BenchmarkId::new("save", size), |b| b.iter(|| bit_vector.save(&file).unwrap()));
+        bit_vector.save(&file).unwrap();
+        group.bench_function(BenchmarkId::new("load", size), |b| b.iter(|| BitVector::load(&file).unwrap()));
+    }
+    let _ = std::fs::remove_file(&file);
+    group.finish();
+}
+
+fn rank_support(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_load/rank_support");
+    let file = temp_file("rank_support");
+    for size in SIZES {
+        let bit_vector = random_bit_vector(size, 0.5);
+        let rank_support = RankSupport::new(&bit_vector);
+        group.bench_function(BenchmarkId::new("save", size), |b| b.iter(|| rank_support.save(&file).unwrap()));
+        rank_support.save(&file).unwrap();
+        group.bench_function(BenchmarkId::new("load", size), |b| {
+            b.iter(|| RankSupport::load(&bit_vector, &file).unwrap())
+        });
+    }
+    let _ = std::fs::remove_file(&file);
+    group.finish();
+}
+
+fn sparse_array(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_load/sparse_array");
+    let file = temp_file("sparse_array");
+    for size in SIZES {
+        let sparse_array = random_sparse_array(size, 0.1);
+        group.bench_function(BenchmarkId::new("save", size), |b| b.iter(|| sparse_array.save(&file).unwrap()));
+        sparse_array.save(&file).unwrap();
+        group.bench_function(BenchmarkId::new("load", size), |b| b.iter(|| SparseArray::load(&file).unwrap()));
+    }
+    let _ = std::fs::remove_file(&file);
+    group.finish();
+}
+
+criterion_group!(benches, bit_vector, rank_support, sparse_array);
+criterion_main!(benches);
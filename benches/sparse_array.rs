@@ -0,0 +1,98 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::sparse_str_array::SparseStrArray;
+
+mod common;
+use common::{label, queries, random_sparse_array, DENSITIES, NUM_QUERIES, SIZES};
+
+// Benches one query answered for every index in the group's queries
+fn bench_queries<F>(c: &mut Criterion, name: &str, by_rank: bool, query: F)
+where
+    F: Fn(&SparseArray, u64) -> u64,
+{
+    let mut group = c.benchmark_group(format!("sparse_array/{}", name));
+    group.throughput(Throughput::Elements(NUM_QUERIES as u64));
+    for size in SIZES {
+        for density in DENSITIES {
+            let sparse_array = random_sparse_array(size, density);
+            let arguments = if by_rank {
+                queries(0..sparse_array.num_elem())
+            } else {
+                queries(0..size as u64)
+            };
+            group.bench_function(BenchmarkId::from_parameter(label(size, density)), |b| {
+                b.iter(|| arguments.iter().map(|&i| query(&sparse_array, i)).fold(0, u64::wrapping_add))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn by_index(c: &mut Criterion) {
+    bench_queries(c, "get", false, |a, i| a.get(i).map_or(0, |s| s.len() as u64));
+    bench_queries(c, "contains", false, |a, i| a.contains(i) as u64);
+    bench_queries(c, "get_at_index", false, |a, i| {
+        let mut s = String::new();
+        a.get_at_index(i, &mut s) as u64
+    });
+    bench_queries(c, "num_elem_at", false, |a, i| a.num_elem_at(i));
+    bench_queries(c, "get_rank1", false, |a, i| a.get_rank1(i as usize));
+    bench_queries(c, "next_present", false, |a, i| a.next_present(i).unwrap_or(0));
+    bench_queries(c, "prev_present", false, |a, i| a.prev_present(i).unwrap_or(0));
+    bench_queries(c, "count_in_range", false, |a, i| a.count_in_range(i / 2..i));
+}
+
+fn by_rank(c: &mut Criterion) {
+    bench_queries(c, "get_at_rank", true, |a, r| {
+        let mut s = String::new();
+        a.get_at_rank(r, &mut s) as u64
+    });
+    bench_queries(c, "get_index_of", true, |a, r| a.get_index_of(r));
+    bench_queries(c, "get_select1", true, |a, r| a.get_select1(r + 1));
+}
+
+// Walking the elements rather than looking them up one by one
+fn iteration(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sparse_array/iterate");
+    for size in SIZES {
+        for density in DENSITIES {
+            let sparse_array = random_sparse_array(size, density);
+            group.throughput(Throughput::Elements(sparse_array.num_elem()));
+            group.bench_function(BenchmarkId::new("iter", label(size, density)), |b| {
+                b.iter(|| sparse_array.iter().map(|(i, _)| i).fold(0, u64::wrapping_add))
+            });
+            group.bench_function(BenchmarkId::new("range", label(size, density)), |b| {
+                b.iter(|| sparse_array.range(size as u64 / 4..size as u64 / 2).map(|(i, _)| i).fold(0, u64::wrapping_add))
+            });
+            group.bench_function(BenchmarkId::new("to_dense", label(size, density)), |b| {
+                b.iter(|| sparse_array.to_dense())
+            });
+        }
+    }
+    group.finish();
+}
+
+// The same lookups on the string arena copy, which hands out &str
+// instead of cloning into a String
+fn arena(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sparse_array/arena");
+    group.throughput(Throughput::Elements(NUM_QUERIES as u64));
+    for size in SIZES {
+        for density in DENSITIES {
+            let str_array = SparseStrArray::from_sparse_array(&random_sparse_array(size, density));
+            let indices = queries(0..size as u64);
+            let ranks = queries(0..str_array.num_elem());
+            group.bench_function(BenchmarkId::new("get", label(size, density)), |b| {
+                b.iter(|| indices.iter().map(|&i| str_array.get(i).map_or(0, |s| s.len() as u64)).fold(0, u64::wrapping_add))
+            });
+            group.bench_function(BenchmarkId::new("get_at_rank", label(size, density)), |b| {
+                b.iter(|| ranks.iter().map(|&r| str_array.get_at_rank(r).map_or(0, |s| s.len() as u64)).fold(0, u64::wrapping_add))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, by_index, by_rank, iteration, arena);
+criterion_main!(benches);
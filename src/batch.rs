@@ -0,0 +1,78 @@
+// Answering many queries at once. A single rank1 or select1 spends most
+// of its time waiting on cache misses in the tables, so the batch methods
+// ask the CPU to start loading what a query a few places ahead will need
+// while the current one is answered, and can sort the queries first so
+// neighbouring queries share cache lines.
+
+// How far ahead of the query being answered the prefetches are issued
+pub(crate) const PREFETCH_DISTANCE: usize = 8;
+
+// The order a batch of queries is answered in. The answers always go to
+// the slot of their query in the output, whichever order is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryOrder {
+    // In the order given, which costs nothing extra
+    #[default]
+    AsGiven,
+    // Sorted first. Worth it for big batches of scattered queries over
+    // structures much bigger than the cache, where the sort costs less
+    // than the misses it saves, which is mostly the case for select1.
+    Sorted,
+}
+
+// Hints that data[index] is about to be read. Does nothing for an index
+// past the end, or on CPUs without a prefetch instruction.
+pub(crate) fn prefetch<T>(data: &[T], index: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if let Some(item) = data.get(index) {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            // SAFETY: prefetching never faults and the address is in bounds anyway
+            #[allow(unused_unsafe)]
+            unsafe {
+                _mm_prefetch::<_MM_HINT_T0>(item as *const T as *const i8);
+            }
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = (data, index);
+    }
+}
+
+// Calls answer(slot, query) for every query in the given order, calling
+// prefetch with the query PREFETCH_DISTANCE places further along first
+pub(crate) fn for_each_query<Q, A, P>(queries: &[Q], order: QueryOrder, mut answer: A, mut prefetch: P)
+where
+    Q: Copy + Ord,
+    A: FnMut(usize, Q),
+    P: FnMut(Q),
+{
+    match order {
+        QueryOrder::AsGiven => {
+            for (slot, &query) in queries.iter().enumerate() {
+                if let Some(&ahead) = queries.get(slot + PREFETCH_DISTANCE) {
+                    prefetch(ahead);
+                }
+                answer(slot, query);
+            }
+        }
+        QueryOrder::Sorted => {
+            // The queries are sorted along with their slots, which keeps
+            // the sort from chasing the slots back into queries
+            let mut sorted: Vec<(Q, usize)> = queries.iter().copied().zip(0..).collect();
+            sorted.sort_unstable();
+            for (k, &(query, slot)) in sorted.iter().enumerate() {
+                if let Some(&(ahead, _)) = sorted.get(k + PREFETCH_DISTANCE) {
+                    prefetch(ahead);
+                }
+                answer(slot, query);
+            }
+        }
+    }
+}
+
+// The output of a batch has one slot per query
+pub(crate) fn check_lengths(num_queries: usize, num_outputs: usize) {
+    assert_eq!(num_queries, num_outputs, "a batch needs one output slot per query");
+}
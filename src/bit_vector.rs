@@ -1,7 +1,11 @@
 
-use std::io::{Read, Write};
+use std::cmp;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
 use std::fs::File;
+use crate::broadword;
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
 
+#[derive(Clone)]
 pub struct BitVector {
     pub data: Vec<u64>,
     pub size: usize,
@@ -78,43 +82,52 @@ impl BitVector {
     // Saves the bit vector to a file with filename
     pub fn save(&self, file_name: &str) -> std::io::Result<()> { 
         // Create the file
-        let mut file = File::create(file_name)?;
-        // Write the size
-        file.write_all(&(self.size as u64).to_le_bytes())?;
-        // Write all the values from data
+        let mut file = BufWriter::new(File::create(file_name)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    // Writes the size and then all the values from data, the layout save uses
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&(self.size as u64).to_le_bytes())?;
         for &value in &self.data {
-            file.write_all(&value.to_le_bytes())?;
+            writer.write_all(&value.to_le_bytes())?;
         }
         Ok(())
     }
 
 
-    // Loads the bit vector from a file with file name
-    pub fn load(file_name: &str) -> std::io::Result<Self> {
-
-
-        // Open the file with file_name
-        let mut file = File::open(file_name)?;
-
-        // Initialize a buffer you'll read the size from
+    // Reads a bit vector written by save from the front of reader, so
+    // more data can follow it in the same file
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        // Read the size
         let mut size_buf = [0u8; 8];
-        // Read the size into the buffer
-        file.read_exact(&mut size_buf)?;
-        // Convert it into u64
+        reader.read_exact(&mut size_buf)?;
         let size = u64::from_le_bytes(size_buf) as usize;
 
-        // Initialize the data Vector
-        let mut data = Vec::new();
-        // Initilize the buffer you'll read the values into
+        // new always makes (size + 64) / 64 words and save writes all of them
+        let num_words = size / 64 + 1;
+        // A corrupt size shouldn't allocate everything up front, read_exact fails first
+        let mut data = Vec::with_capacity(cmp::min(num_words, 1 << 20));
         let mut value_buf = [0u8; 8];
-        // While you can read, push the values onto the data
-        while file.read_exact(&mut value_buf).is_ok() {
+        for _ in 0..num_words {
+            reader.read_exact(&mut value_buf)?;
             data.push(u64::from_le_bytes(value_buf));
         }
-
-        // Return an instance of Self with data and size
         Ok(Self { data, size })
-        
+    }
+
+    // Loads the bit vector from a file with file name
+    pub fn load(file_name: &str) -> std::io::Result<Self> {
+        // Open the file with file_name
+        let mut file = BufReader::new(File::open(file_name)?);
+        let bit_vector = Self::read_from(&mut file)?;
+
+        // Anything after the words means the file isn't a saved bit vector
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the bit vector"));
+        }
+        Ok(bit_vector)
     }
 
     // for debugging purposes print the bits of the vector
@@ -122,110 +135,70 @@ impl BitVector {
         for i in 0..self.size {
             let bit = self.get(i);
             print!("{}", if bit { "1" } else { "0" });
-            if (i % step_size == step_size-1) {
+            if i % step_size == step_size-1 {
                 println!(" | {i}");
             }
         }
         println!();
     }
 
-    // Gets the i-th rank
+    // Gets the i-th rank, the number of ones below bit i of number
     pub fn get_i_th_rank(&self, number:u64, i:usize) -> u64 {
-        if i==0 {
-            return 0;
-        }
-        // If we're looking at rank for i=1 we need only look at the even/odd bit
-        if i == 1 {
-            return number % 2;
-        }
-
-        // The number with the (i-1)-th bit leading
-        let i_lead = number << (64-i);
-        // Count the number of ones
-        let num_ones_before_i = i_lead.count_ones();
-        // that's the result
-        return num_ones_before_i.into();
+        broadword::rank_in_word(number, i)
     }
 
     // Get's the first x bits
     pub fn get_first_x_bits(&self, number: u64, x: usize) -> u64 {
-        let mask = (1u64 << x) - 1;
-        number & mask
+        number & broadword::low_mask(x)
     }
 
+    // Get's the last x bits, moved down to the first x
     pub fn get_first_x_bits_from_left(&self, number: u64, x: usize) -> u64 {
-        // If you want 64 or more we return the number
-        if x >= 64 {
-            return number;
-        }
-
-        // If you want 0 we give you a 0
-        if x == 0 {
-            return 0;
-        }
-    
-        let shift_amount = 64 - x;
-        let mask = (1u64 << x) - 1;
-        (number >> shift_amount) & mask
+        broadword::high_bits(number, x)
     }
 
+    // Reads bits i to j (exclusive) as a number, bit i is its lowest bit
     pub fn interpret_as_u64_int(&self, i:usize, j:usize) -> u64{
         assert!(j > i);
         assert!(j - i <= 64);
-        // println!("passed the assert");
-        let i_array_index:usize = i/64;
-        let i_array_offset:usize = i % 64;
-        let j_array_index:usize = j/64;
-        let j_array_offset:usize = j % 64;
-
-        let mut result:u64 = 0;
-
-        if i_array_index == j_array_index {
-            // They share the number
-            let full_number:u64 = self.data[i_array_index];
-            // The mask is 0000111111100000 (2^j - 2^i gives us exclusive with j)
-            let mask = (1u64 << j_array_offset) - (1u64 << i_array_offset);
-            // And it and give the offset
-            result = (full_number & mask) >> i_array_offset;
+        broadword::read_bits(&self.data, i, j - i)
+    }
 
-        } else {
-            // for j we want the first j_array_index numbers
-            let x = self.data[j_array_index];
-            let first_number:u64 = self.get_first_x_bits(self.data[j_array_index], j_array_offset);
-            // println!("passed first one {}", first_number);
-            // Get the leftmost 64 - i_array_offset bits
-            let second_number:u64 = self.get_first_x_bits_from_left(self.data[i_array_index], 64 - i_array_offset);
-            // println!("Passed second one: {}", second_number);
-            // Result it once you shift the j number by the appropriate number of bits so 
-            // we can just add them where they need to be added
-
-            // if the i_array_offset is different than 0 
-            if i_array_offset != 0 {
-                // We shift the first number by 64 - i_array_offset bits because that's
-                // How many we're taking from the i_array_index number
-                result = second_number + (first_number << (64 - i_array_offset));
-            } else {
-                // if it's 0 that means j is 0 and that we're getting the full number from
-                // the i_array_index which is just the second number
-                result = second_number;
-            }
-            
-        }
+    // Returns the number of 1s
+    pub fn count_ones(&self) -> usize {
+        self.data.iter().map(|word| word.count_ones() as usize).sum()
+    }
 
-        return result;
+    // Checks that the bit vector looks like one new and set would make:
+    // the right number of words and no bits set past the size
+    pub fn verify(&self) -> std::io::Result<()> {
+        let num_words = (self.size + 64) / 64;
+        if self.data.len() != num_words {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "bit vector of size {} should have {} words but has {}", self.size, num_words, self.data.len()
+            )));
+        }
+        let last_word_bits = self.size % 64;
+        let padding = self.data[self.size / 64] >> last_word_bits;
+        if padding != 0 || self.data[self.size / 64 + 1..].iter().any(|&word| word != 0) {
+            return Err(Error::new(ErrorKind::InvalidData, "bits are set past the end of the bit vector"));
+        }
+        Ok(())
     }
 
-    
+    // Bytes the bit vector takes, the total of its space report
     pub fn memory_usage(&self) -> usize {
-        // Calculate memory usage of the data Vec<u64>
-        let data_memory = std::mem::size_of::<u64>() * self.data.len();
-
-        // Calculate memory usage of the size usize
-        let size_memory = std::mem::size_of::<usize>();
-
-        // Return total memory usage
-        data_memory + size_memory
+        self.space_report().bytes() as usize
     }
 
 
-}
\ No newline at end of file
+}
+
+impl SpaceUsage for BitVector {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("BitVector", vec![
+            SpaceNode::leaf("header", inline_bits::<BitVector>()),
+            SpaceNode::leaf("data", vec_bits(&self.data)),
+        ])
+    }
+}
@@ -0,0 +1,491 @@
+use crate::bit_vector::BitVector;
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+use std::f64::consts::LN_2;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+// Probabilistic membership filters on a BitVector, to check ahead of an
+// exact index lookup. contains never misses an inserted item and says yes
+// to others with about the false positive rate the filter was sized for.
+//
+// Items are hashed with FNV-1a over what their Hash implementation feeds
+// in, integers as little endian, so a saved filter gives the same answers
+// on every platform. The k bit positions of an item come from two hashes,
+// h1 + i * h2, mapped onto the bits with a multiply and a shift.
+
+// Bits in a cache line, the block of a BlockedBloomFilter
+const BLOCK_BITS: u64 = 512;
+
+// Counters of a CountingBloomFilter stop at this value and never go down
+// again, since how far past it they went is lost
+const MAX_COUNT: u64 = 15;
+const COUNTER_BITS: usize = 4;
+
+struct FilterHasher(u64);
+
+impl Hasher for FilterHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    // As a u64 so 32 and 64 bit platforms agree
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// The splitmix64 finalizer, to spread the bits of the FNV hash
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// Two independent looking hashes of an item, the second one odd
+fn hash_pair<T: Hash + ?Sized>(item: &T) -> (u64, u64) {
+    let mut hasher = FilterHasher(0xcbf2_9ce4_8422_2325);
+    item.hash(&mut hasher);
+    let h1 = mix(hasher.finish());
+    (h1, mix(h1 ^ 0x9e37_79b9_7f4a_7c15) | 1)
+}
+
+// Maps a hash onto [0, n)
+fn reduce(hash: u64, n: u64) -> u64 {
+    ((hash as u128 * n as u128) >> 64) as u64
+}
+
+// The positions out of n of the k bits of an item
+fn positions(hashes: (u64, u64), k: u32, n: u64) -> impl Iterator<Item = u64> {
+    let (h1, h2) = hashes;
+    (0..k as u64).map(move |i| reduce(h1.wrapping_add(i.wrapping_mul(h2)), n))
+}
+
+// The number of bits and hashes for a filter of expected_items with the
+// given false positive rate: m = -n ln(p) / ln(2)^2 bits and
+// k = m / n * ln(2) hashes
+pub fn optimal_parameters(expected_items: u64, false_positive_rate: f64) -> (u64, u32) {
+    assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0, "the false positive rate has to be between 0 and 1");
+    let n = expected_items.max(1) as f64;
+    let num_bits = (-n * false_positive_rate.ln() / (LN_2 * LN_2)).ceil().max(1.0);
+    let num_hashes = (num_bits / n * LN_2).round().clamp(1.0, 64.0);
+    (num_bits as u64, num_hashes as u32)
+}
+
+// Number of distinct items inserted into m bits with k hashes, estimated
+// from the number of ones x as -m / k * ln(1 - x / m). Infinite once
+// every bit is set.
+fn estimate_cardinality(num_bits: u64, num_hashes: u32, num_ones: u64) -> f64 {
+    let m = num_bits as f64;
+    -m / num_hashes as f64 * (1.0 - num_ones as f64 / m).ln()
+}
+
+fn check_num_hashes(num_hashes: u32) {
+    assert!((1..=64).contains(&num_hashes), "a filter needs between 1 and 64 hashes, not {}", num_hashes);
+}
+
+// The file layout shared by all the filters: an 8 byte tag naming the
+// kind of filter, the number of hashes and the bit vector
+fn write_filter<W: Write>(writer: &mut W, tag: &[u8; 8], num_hashes: u32, bits: &BitVector) -> std::io::Result<()> {
+    writer.write_all(tag)?;
+    writer.write_all(&(num_hashes as u64).to_le_bytes())?;
+    bits.write_to(writer)
+}
+
+fn read_filter<R: Read>(reader: &mut R, tag: &[u8; 8]) -> std::io::Result<(u32, BitVector)> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    if &buf != tag {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "expected a {} filter but found {:?}", String::from_utf8_lossy(tag).trim_end_matches('\0'), String::from_utf8_lossy(&buf)
+        )));
+    }
+    reader.read_exact(&mut buf)?;
+    let num_hashes = u64::from_le_bytes(buf);
+    if !(1..=64).contains(&num_hashes) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} is not a valid number of hashes", num_hashes)));
+    }
+    let bits = BitVector::read_from(reader)?;
+    bits.verify()?;
+    if bits.size() == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "a filter needs at least one bit"));
+    }
+    Ok((num_hashes as u32, bits))
+}
+
+fn save_filter(fname: &str, tag: &[u8; 8], num_hashes: u32, bits: &BitVector) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(fname)?);
+    write_filter(&mut file, tag, num_hashes, bits)?;
+    file.flush()
+}
+
+fn load_filter(fname: &str, tag: &[u8; 8]) -> std::io::Result<(u32, BitVector)> {
+    let mut file = BufReader::new(File::open(fname)?);
+    let filter = read_filter(&mut file, tag)?;
+    if file.read(&mut [0u8; 1])? != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the filter"));
+    }
+    Ok(filter)
+}
+
+fn filter_space_report<F>(name: &str, bits: &BitVector) -> SpaceNode {
+    SpaceNode::with_children(name, vec![
+        SpaceNode::leaf("header", inline_bits::<F>() - inline_bits::<BitVector>()),
+        bits.space_report().renamed("bits"),
+    ])
+}
+
+// A Bloom filter, every item sets k bits anywhere in the bit vector
+#[derive(Clone)]
+pub struct BloomFilter {
+    bits: BitVector,
+    num_hashes: u32,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<BloomFilter>;
+
+impl BloomFilter {
+    pub fn new(num_bits: u64, num_hashes: u32) -> Self {
+        assert!(num_bits > 0, "a filter needs at least one bit");
+        check_num_hashes(num_hashes);
+        BloomFilter {
+            bits: BitVector::new(num_bits as usize),
+            num_hashes,
+        }
+    }
+
+    // Sized for expected_items with the given false positive rate
+    pub fn with_rate(expected_items: u64, false_positive_rate: f64) -> Self {
+        let (num_bits, num_hashes) = optimal_parameters(expected_items, false_positive_rate);
+        Self::new(num_bits, num_hashes)
+    }
+
+    pub fn num_bits(&self) -> u64 {
+        self.bits.size() as u64
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    pub fn count_ones(&self) -> u64 {
+        self.bits.count_ones() as u64
+    }
+
+    pub fn bit_vector(&self) -> &BitVector {
+        &self.bits
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        for pos in positions(hash_pair(item), self.num_hashes, self.num_bits()) {
+            self.bits.data[pos as usize / 64] |= 1 << (pos % 64);
+        }
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        positions(hash_pair(item), self.num_hashes, self.num_bits()).all(|pos| self.bits.data[pos as usize / 64] >> (pos % 64) & 1 == 1)
+    }
+
+    // Adds everything in other, which has to have the same number of bits
+    // and hashes. The result is the filter of both sets of items.
+    pub fn merge(&mut self, other: &BloomFilter) {
+        assert!(self.num_bits() == other.num_bits() && self.num_hashes == other.num_hashes, "filters of different shapes");
+        for (word, &other_word) in self.bits.data.iter_mut().zip(&other.bits.data) {
+            *word |= other_word;
+        }
+    }
+
+    // Number of distinct items inserted, estimated from the number of ones
+    pub fn estimated_cardinality(&self) -> f64 {
+        estimate_cardinality(self.num_bits(), self.num_hashes, self.count_ones())
+    }
+
+    // The chance that an item that was never inserted is reported, given
+    // the bits set so far
+    pub fn estimated_false_positive_rate(&self) -> f64 {
+        (self.count_ones() as f64 / self.num_bits() as f64).powi(self.num_hashes as i32)
+    }
+
+    pub fn save(&self, fname: &str) -> std::io::Result<()> {
+        save_filter(fname, b"bloom\0\0\0", self.num_hashes, &self.bits)
+    }
+
+    // The tag, the number of hashes and the bit vector
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_filter(writer, b"bloom\0\0\0", self.num_hashes, &self.bits)
+    }
+
+    pub fn load(fname: &str) -> std::io::Result<Self> {
+        let (num_hashes, bits) = load_filter(fname, b"bloom\0\0\0")?;
+        Ok(BloomFilter { bits, num_hashes })
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let (num_hashes, bits) = read_filter(reader, b"bloom\0\0\0")?;
+        Ok(BloomFilter { bits, num_hashes })
+    }
+}
+
+// A Bloom filter that keeps the k bits of an item in one 512 bit block,
+// so an insert or a lookup touches one cache line instead of k. Bits pile
+// up unevenly over the blocks, which puts the false positive rate
+// somewhat above that of a BloomFilter with the same bits and hashes.
+#[derive(Clone)]
+pub struct BlockedBloomFilter {
+    bits: BitVector,
+    num_hashes: u32,
+}
+
+impl BlockedBloomFilter {
+    // num_bits is rounded up to whole blocks
+    pub fn new(num_bits: u64, num_hashes: u32) -> Self {
+        assert!(num_bits > 0, "a filter needs at least one bit");
+        check_num_hashes(num_hashes);
+        BlockedBloomFilter {
+            bits: BitVector::new(num_bits.div_ceil(BLOCK_BITS) as usize * BLOCK_BITS as usize),
+            num_hashes,
+        }
+    }
+
+    // Sized like a BloomFilter for expected_items with the given false
+    // positive rate, which the blocks make a little worse
+    pub fn with_rate(expected_items: u64, false_positive_rate: f64) -> Self {
+        let (num_bits, num_hashes) = optimal_parameters(expected_items, false_positive_rate);
+        Self::new(num_bits, num_hashes)
+    }
+
+    pub fn num_bits(&self) -> u64 {
+        self.bits.size() as u64
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    pub fn count_ones(&self) -> u64 {
+        self.bits.count_ones() as u64
+    }
+
+    pub fn bit_vector(&self) -> &BitVector {
+        &self.bits
+    }
+
+    // The first word of the item's block and the positions of its bits
+    // in the block. h1 picks the block and its bits come from h2 and a
+    // rotation of h1, taking the top 9 bits for a position.
+    fn block_positions<T: Hash + ?Sized>(&self, item: &T) -> (usize, impl Iterator<Item = u64>) {
+        let (h1, h2) = hash_pair(item);
+        let block = reduce(h1, self.num_bits() / BLOCK_BITS) as usize;
+        let step = h1.rotate_left(32) | 1;
+        let bits = (0..self.num_hashes as u64).map(move |i| h2.wrapping_add(i.wrapping_mul(step)) >> 55);
+        (block * (BLOCK_BITS as usize / 64), bits)
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        let (first_word, bits) = self.block_positions(item);
+        for bit in bits {
+            self.bits.data[first_word + bit as usize / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let (first_word, mut bits) = self.block_positions(item);
+        let block = &self.bits.data[first_word..first_word + BLOCK_BITS as usize / 64];
+        bits.all(|bit| block[bit as usize / 64] >> (bit % 64) & 1 == 1)
+    }
+
+    pub fn merge(&mut self, other: &BlockedBloomFilter) {
+        assert!(self.num_bits() == other.num_bits() && self.num_hashes == other.num_hashes, "filters of different shapes");
+        for (word, &other_word) in self.bits.data.iter_mut().zip(&other.bits.data) {
+            *word |= other_word;
+        }
+    }
+
+    // The same estimate as BloomFilter, which ignores the blocks and
+    // comes out a little low when the filter is close to full
+    pub fn estimated_cardinality(&self) -> f64 {
+        estimate_cardinality(self.num_bits(), self.num_hashes, self.count_ones())
+    }
+
+    pub fn save(&self, fname: &str) -> std::io::Result<()> {
+        save_filter(fname, b"blocked\0", self.num_hashes, &self.bits)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_filter(writer, b"blocked\0", self.num_hashes, &self.bits)
+    }
+
+    pub fn load(fname: &str) -> std::io::Result<Self> {
+        let (num_hashes, bits) = load_filter(fname, b"blocked\0")?;
+        Self::from_parts(num_hashes, bits)
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let (num_hashes, bits) = read_filter(reader, b"blocked\0")?;
+        Self::from_parts(num_hashes, bits)
+    }
+
+    fn from_parts(num_hashes: u32, bits: BitVector) -> std::io::Result<Self> {
+        if !(bits.size() as u64).is_multiple_of(BLOCK_BITS) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} bits are not whole blocks of {}", bits.size(), BLOCK_BITS)));
+        }
+        Ok(BlockedBloomFilter { bits, num_hashes })
+    }
+}
+
+// A Bloom filter with a 4 bit counter in place of every bit, so items can
+// be removed again. The counters are packed 16 to a word of a BitVector.
+// A counter that reaches 15 stays there, so removing items that share it
+// never causes a miss, at worst a false positive.
+//
+// It uses the same positions as a BloomFilter with as many bits as it has
+// counters, so to_bloom_filter gives the filter of the items inserted and
+// not removed.
+#[derive(Clone)]
+pub struct CountingBloomFilter {
+    counters: BitVector,
+    num_hashes: u32,
+}
+
+impl CountingBloomFilter {
+    pub fn new(num_counters: u64, num_hashes: u32) -> Self {
+        assert!(num_counters > 0, "a filter needs at least one counter");
+        check_num_hashes(num_hashes);
+        CountingBloomFilter {
+            counters: BitVector::new(num_counters as usize * COUNTER_BITS),
+            num_hashes,
+        }
+    }
+
+    pub fn with_rate(expected_items: u64, false_positive_rate: f64) -> Self {
+        let (num_counters, num_hashes) = optimal_parameters(expected_items, false_positive_rate);
+        Self::new(num_counters, num_hashes)
+    }
+
+    pub fn num_counters(&self) -> u64 {
+        (self.counters.size() / COUNTER_BITS) as u64
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    // The value of counter i
+    pub fn count(&self, i: u64) -> u64 {
+        let bit = i as usize * COUNTER_BITS;
+        self.counters.data[bit / 64] >> (bit % 64) & MAX_COUNT
+    }
+
+    fn add(&mut self, i: u64, delta: i64) {
+        let bit = i as usize * COUNTER_BITS;
+        let count = self.count(i).saturating_add_signed(delta);
+        let word = &mut self.counters.data[bit / 64];
+        *word = (*word & !(MAX_COUNT << (bit % 64))) | (count << (bit % 64));
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        for pos in positions(hash_pair(item), self.num_hashes, self.num_counters()) {
+            if self.count(pos) < MAX_COUNT {
+                self.add(pos, 1);
+            }
+        }
+    }
+
+    // Takes the item out again if it looks present, and returns whether
+    // it did. Removing an item that was never inserted but shows up as a
+    // false positive takes another item's counts away.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        for pos in positions(hash_pair(item), self.num_hashes, self.num_counters()) {
+            if self.count(pos) < MAX_COUNT {
+                self.add(pos, -1);
+            }
+        }
+        true
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        positions(hash_pair(item), self.num_hashes, self.num_counters()).all(|pos| self.count(pos) > 0)
+    }
+
+    // A BloomFilter with a 1 for every counter above 0
+    pub fn to_bloom_filter(&self) -> BloomFilter {
+        let mut bloom_filter = BloomFilter::new(self.num_counters(), self.num_hashes);
+        for i in (0..self.num_counters()).filter(|&i| self.count(i) > 0) {
+            bloom_filter.bits.data[i as usize / 64] |= 1 << (i % 64);
+        }
+        bloom_filter
+    }
+
+    pub fn estimated_cardinality(&self) -> f64 {
+        let nonzero = (0..self.num_counters()).filter(|&i| self.count(i) > 0).count() as u64;
+        estimate_cardinality(self.num_counters(), self.num_hashes, nonzero)
+    }
+
+    pub fn save(&self, fname: &str) -> std::io::Result<()> {
+        save_filter(fname, b"counting", self.num_hashes, &self.counters)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_filter(writer, b"counting", self.num_hashes, &self.counters)
+    }
+
+    pub fn load(fname: &str) -> std::io::Result<Self> {
+        let (num_hashes, counters) = load_filter(fname, b"counting")?;
+        Self::from_parts(num_hashes, counters)
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let (num_hashes, counters) = read_filter(reader, b"counting")?;
+        Self::from_parts(num_hashes, counters)
+    }
+
+    fn from_parts(num_hashes: u32, counters: BitVector) -> std::io::Result<Self> {
+        if !counters.size().is_multiple_of(COUNTER_BITS) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} bits are not whole {} bit counters", counters.size(), COUNTER_BITS)));
+        }
+        Ok(CountingBloomFilter { counters, num_hashes })
+    }
+}
+
+impl SpaceUsage for BloomFilter {
+    fn space_report(&self) -> SpaceNode {
+        filter_space_report::<BloomFilter>("BloomFilter", &self.bits)
+    }
+}
+
+impl SpaceUsage for BlockedBloomFilter {
+    fn space_report(&self) -> SpaceNode {
+        filter_space_report::<BlockedBloomFilter>("BlockedBloomFilter", &self.bits)
+    }
+}
+
+impl SpaceUsage for CountingBloomFilter {
+    fn space_report(&self) -> SpaceNode {
+        filter_space_report::<CountingBloomFilter>("CountingBloomFilter", &self.counters)
+    }
+}
@@ -0,0 +1,220 @@
+// Bit tricks on single 64 bit words that the rank and select structures
+// are built from. Bit 0 of a word is its lowest bit and comes first,
+// the same order BitVector stores its bits in.
+//
+// select_in_word, extract_bits and deposit_bits use the BMI2 pdep/pext
+// instructions when the CPU has them and the broadword versions in
+// `portable` otherwise. Both give the same answers for every input.
+
+const L8: u64 = 0x0101_0101_0101_0101;
+const H8: u64 = 0x8080_8080_8080_8080;
+
+// Mask with the lowest len bits set, len can be anything up to 64
+pub fn low_mask(len: usize) -> u64 {
+    if len >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << len) - 1
+    }
+}
+
+// Number of ones below bit i of the word, i can be anything up to 64
+pub fn rank_in_word(word: u64, i: usize) -> u64 {
+    (word & low_mask(i)).count_ones() as u64
+}
+
+// Position of the one that has k ones below it, so k = 0 gives the lowest
+// one. Returns 64 if the word has k or fewer ones.
+pub fn select_in_word(word: u64, k: usize) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_bmi2() {
+            if k >= 64 {
+                return 64;
+            }
+            // SAFETY: the CPU supports BMI2
+            return unsafe { bmi2::select_in_word(word, k) };
+        }
+    }
+    portable::select_in_word(word, k)
+}
+
+// Gathers the bits of word under the ones of mask into the low bits of
+// the result, in order (pext)
+pub fn extract_bits(word: u64, mask: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_bmi2() {
+            // SAFETY: the CPU supports BMI2
+            return unsafe { bmi2::extract_bits(word, mask) };
+        }
+    }
+    portable::extract_bits(word, mask)
+}
+
+// Spreads the low bits of word over the ones of mask, in order, which
+// undoes extract_bits for the bits under the mask (pdep)
+pub fn deposit_bits(word: u64, mask: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_bmi2() {
+            // SAFETY: the CPU supports BMI2
+            return unsafe { bmi2::deposit_bits(word, mask) };
+        }
+    }
+    portable::deposit_bits(word, mask)
+}
+
+// Reverses the order of the lowest len bits of the word and drops the rest
+pub fn reverse_bits(word: u64, len: usize) -> u64 {
+    if len == 0 {
+        return 0;
+    }
+    word.reverse_bits() >> (64 - len.min(64))
+}
+
+// The highest len bits of the word, moved down to the low bits
+pub fn high_bits(word: u64, len: usize) -> u64 {
+    if len == 0 {
+        return 0;
+    }
+    word >> (64 - len.min(64))
+}
+
+// Reads the len bits starting at bit start of a sequence of words, where
+// bit i is bit i % 64 of word i / 64. len is at most 64 and the bits can
+// straddle two words.
+pub fn read_bits(words: &[u64], start: usize, len: usize) -> u64 {
+    assert!(len <= 64, "can't read {} bits into one word", len);
+    if len == 0 {
+        return 0;
+    }
+    let index = start / 64;
+    let offset = start % 64;
+    let mut bits = words[index] >> offset;
+    if offset + len > 64 {
+        bits |= words[index + 1] << (64 - offset);
+    }
+    bits & low_mask(len)
+}
+
+// True if the BMI2 instructions can be used. The standard library caches
+// the answer, so this is cheap enough to ask on every call.
+pub fn has_bmi2() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        std::arch::is_x86_feature_detected!("bmi2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod bmi2 {
+    use std::arch::x86_64::{_pdep_u64, _pext_u64};
+
+    // pdep moves the single bit of 1 << k onto the k-th one of the word
+    // (counting from 0), and tzcnt gives its position. Without a k-th one
+    // pdep gives 0, which has 64 trailing zeros.
+    #[target_feature(enable = "bmi2")]
+    pub unsafe fn select_in_word(word: u64, k: usize) -> usize {
+        _pdep_u64(1u64 << k, word).trailing_zeros() as usize
+    }
+
+    #[target_feature(enable = "bmi2")]
+    pub unsafe fn extract_bits(word: u64, mask: u64) -> u64 {
+        _pext_u64(word, mask)
+    }
+
+    #[target_feature(enable = "bmi2")]
+    pub unsafe fn deposit_bits(word: u64, mask: u64) -> u64 {
+        _pdep_u64(word, mask)
+    }
+}
+
+// Versions without any special instructions, public so they can be
+// checked against the BMI2 ones on CPUs that have both
+pub mod portable {
+    use super::{H8, L8};
+
+    // SELECT_IN_BYTE[byte * 8 + k] is the position of the one of byte
+    // with k ones below it, or 8 if there is none
+    static SELECT_IN_BYTE: [u8; 256 * 8] = build_select_in_byte();
+
+    const fn build_select_in_byte() -> [u8; 256 * 8] {
+        let mut table = [8u8; 256 * 8];
+        let mut byte = 0;
+        while byte < 256 {
+            let mut ones = 0;
+            let mut bit = 0;
+            while bit < 8 {
+                if byte >> bit & 1 == 1 {
+                    table[byte * 8 + ones] = bit as u8;
+                    ones += 1;
+                }
+                bit += 1;
+            }
+            byte += 1;
+        }
+        table
+    }
+
+    // For every byte, 0x80 if the byte of x is at most the byte of y and
+    // 0 otherwise. Every byte of x has to be below 0x80.
+    fn bytes_at_most(x: u64, y: u64) -> u64 {
+        (((y | H8) - (x & !H8)) ^ x ^ y) & H8
+    }
+
+    // Vigna's broadword select: the popcounts of every byte are summed
+    // into running totals so the byte with the one is found with a few
+    // word operations, and the one within the byte with a table lookup
+    pub fn select_in_word(word: u64, k: usize) -> usize {
+        if k >= word.count_ones() as usize {
+            return 64;
+        }
+        let mut counts = word - ((word >> 1) & 0x5555_5555_5555_5555);
+        counts = (counts & 0x3333_3333_3333_3333) + ((counts >> 2) & 0x3333_3333_3333_3333);
+        // Byte j holds the number of ones in bytes 0 to j
+        let totals = ((counts + (counts >> 4)) & 0x0F0F_0F0F_0F0F_0F0F).wrapping_mul(L8);
+
+        // The bytes whose running total is at most k all come before the
+        // one we want, so counting them gives the byte it is in
+        let k = k as u64;
+        let byte_offset = ((bytes_at_most(totals, k.wrapping_mul(L8)) >> 7).wrapping_mul(L8) >> 53) & !7;
+        let ones_before_byte = ((totals << 8) >> byte_offset) & 0xFF;
+        let byte = (word >> byte_offset) & 0xFF;
+        byte_offset as usize + SELECT_IN_BYTE[(byte * 8 + k - ones_before_byte) as usize] as usize
+    }
+
+    pub fn extract_bits(word: u64, mask: u64) -> u64 {
+        let mut result = 0;
+        let mut out_bit = 1u64;
+        let mut rest = mask;
+        while rest != 0 {
+            let lowest = rest & rest.wrapping_neg();
+            if word & lowest != 0 {
+                result |= out_bit;
+            }
+            out_bit <<= 1;
+            rest &= rest - 1;
+        }
+        result
+    }
+
+    pub fn deposit_bits(word: u64, mask: u64) -> u64 {
+        let mut result = 0;
+        let mut in_bit = 1u64;
+        let mut rest = mask;
+        while rest != 0 {
+            let lowest = rest & rest.wrapping_neg();
+            if word & in_bit != 0 {
+                result |= lowest;
+            }
+            in_bit <<= 1;
+            rest &= rest - 1;
+        }
+        result
+    }
+}
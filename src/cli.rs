@@ -0,0 +1,488 @@
+// Command line interface: build, inspect, query and verify saved structures
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+
+use hw2_code::bit_vector::BitVector;
+use hw2_code::rank_select::RankSelect;
+use hw2_code::rank_support::{BlockSizes, RankSupport};
+use hw2_code::space_report::SpaceUsage;
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::text_formats;
+
+use crate::experiments;
+
+const USAGE: &str = "\
+usage:
+  hw2_code build <kind> <input> <output> [--format positions|bits|tsv] [--size N]
+                [--blocks theoretical|speed|<chunk>/<superchunk>] [--threads N]
+  hw2_code info <kind> <file> [--report text|json|html]
+  hw2_code query <kind> <file> [<op> <arg>...]
+  hw2_code verify <kind> <file>
+  hw2_code experiment run|compare ...   (see hw2_code experiment)
+
+kinds:
+  bitvector   a plain bit vector
+  rank        a bit vector with its rank tables, answers rank and select too
+  sparse      a sparse array of strings
+
+input formats:
+  positions   one position of a 1 per line
+  bits        a string of 0s and 1s, whitespace is ignored
+  tsv         index<TAB>value per line, the only format for sparse arrays
+
+rank block sizes:
+  theoretical .5*log(n) bit chunks and (log n)^2 bit superchunks, the default
+  speed       64 bit chunks and 512 bit superchunks
+  <c>/<s>     c bit chunks (at most 64) and s bit superchunks, a multiple of c
+  --threads N builds the rank tables with N threads, 0 for one per core
+
+query ops:
+  bitvector   access <i>
+  rank        access <i>, rank <i>, select <i>
+  sparse      get <i>, at-rank <r>, index-of <r>, num-at <i>, range <start> <end>
+  without an op, queries are read from stdin as one '<op> <arg>...' per line";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    BitVector,
+    Rank,
+    Sparse,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Positions,
+    Bits,
+    Tsv,
+}
+
+#[derive(Clone, Copy)]
+enum Report {
+    Text,
+    Json,
+    Html,
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let Some(command) = args.first() else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+    match command.as_str() {
+        "build" => build(&args[1..]),
+        "info" => info(&args[1..]),
+        "query" => query(&args[1..]),
+        "verify" => verify(&args[1..]),
+        "experiment" => experiments::run(&args[1..]),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(usage_error(&format!("unknown command {}", command))),
+    }
+}
+
+fn usage_error(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("{}\n\n{}", msg, USAGE))
+}
+
+fn invalid_input(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+fn parse_kind(arg: &str) -> io::Result<Kind> {
+    match arg {
+        "bitvector" => Ok(Kind::BitVector),
+        "rank" => Ok(Kind::Rank),
+        "sparse" => Ok(Kind::Sparse),
+        _ => Err(usage_error(&format!("unknown kind {}", arg))),
+    }
+}
+
+fn parse_number(arg: &str) -> io::Result<u64> {
+    arg.parse().map_err(|_| invalid_input(format!("{} is not a non-negative integer", arg)))
+}
+
+fn parse_block_sizes(arg: &str) -> io::Result<BlockSizes> {
+    match arg {
+        "theoretical" => Ok(BlockSizes::Theoretical),
+        "speed" => Ok(BlockSizes::Speed),
+        _ => {
+            let Some((chunk_size, superchunk_size)) = arg.split_once('/') else {
+                return Err(usage_error(&format!("unknown block sizes {}", arg)));
+            };
+            Ok(BlockSizes::Custom {
+                chunk_size: parse_number(chunk_size)? as usize,
+                superchunk_size: parse_number(superchunk_size)? as usize,
+            })
+        }
+    }
+}
+
+// (name, value) of every --name value option
+type Options<'a> = Vec<(&'a str, &'a str)>;
+
+// Splits the arguments into the positional ones and the values of the
+// --name value options in allowed
+fn split_options<'a>(args: &'a [String], allowed: &[&str]) -> io::Result<(Vec<&'a str>, Options<'a>)> {
+    let mut positional = Vec::new();
+    let mut options = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(name) = arg.strip_prefix("--") {
+            if !allowed.contains(&name) {
+                return Err(usage_error(&format!("unknown option {}", arg)));
+            }
+            let value = iter.next().ok_or_else(|| usage_error(&format!("{} needs a value", arg)))?;
+            options.push((name, value.as_str()));
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    Ok((positional, options))
+}
+
+//
+// build
+//
+
+fn build(args: &[String]) -> io::Result<()> {
+    let (positional, options) = split_options(args, &["format", "size", "blocks", "threads"])?;
+    let [kind, input, output] = positional[..] else {
+        return Err(usage_error("build needs a kind, an input file and an output file"));
+    };
+    let kind = parse_kind(kind)?;
+
+    let mut format = if kind == Kind::Sparse { Format::Tsv } else { Format::Positions };
+    let mut size = None;
+    let mut block_sizes = None;
+    let mut num_threads = 1;
+    for (name, value) in options {
+        match name {
+            "format" => {
+                format = match value {
+                    "positions" => Format::Positions,
+                    "bits" => Format::Bits,
+                    "tsv" => Format::Tsv,
+                    _ => return Err(usage_error(&format!("unknown format {}", value))),
+                }
+            }
+            "blocks" => block_sizes = Some(parse_block_sizes(value)?),
+            "threads" => num_threads = parse_number(value)? as usize,
+            _ => size = Some(parse_number(value)?),
+        }
+    }
+    if block_sizes.is_some() && kind != Kind::Rank {
+        return Err(usage_error("--blocks only applies to rank files"));
+    }
+
+    let reader = BufReader::new(File::open(input)?);
+    if kind == Kind::Sparse {
+        if !matches!(format, Format::Tsv) {
+            return Err(usage_error("sparse arrays can only be built from a tsv file"));
+        }
+        let sparse_array = text_formats::sparse_array_from_tsv(reader, size, num_threads)?;
+        check_not_empty(sparse_array.size(), size)?;
+        sparse_array.save(output)?;
+        println!("built a sparse array of size {} with {} elements", sparse_array.size(), sparse_array.num_elem());
+        return Ok(());
+    }
+
+    let bit_vector = match format {
+        Format::Bits => text_formats::bit_vector_from_bits(reader, size)?,
+        Format::Positions => text_formats::bit_vector_from_positions(reader, size)?,
+        Format::Tsv => text_formats::bit_vector_from_tsv(reader, size)?,
+    };
+    if !matches!(format, Format::Bits) {
+        check_not_empty(bit_vector.size() as u64, size)?;
+    }
+
+    let mut file = BufWriter::new(File::create(output)?);
+    bit_vector.write_to(&mut file)?;
+    if kind == Kind::Rank {
+        let block_sizes = block_sizes.unwrap_or(BlockSizes::Theoretical);
+        RankSupport::build_with_threads(&bit_vector, block_sizes, num_threads)?.write_to(&mut file)?;
+    }
+    file.flush()?;
+    println!("built a bit vector of size {} with {} ones", bit_vector.size(), bit_vector.count_ones());
+    Ok(())
+}
+
+// Without --size the size is one past the largest position, so an input
+// with no positions needs one
+fn check_not_empty(built_size: u64, size: Option<u64>) -> io::Result<()> {
+    if built_size == 0 && size.is_none() {
+        return Err(invalid_input("the input has no positions, give the size with --size".to_string()));
+    }
+    Ok(())
+}
+
+//
+// Loading saved files
+//
+
+// Names the file in load errors, a file that ends early is most likely truncated
+fn load_error(file_name: &str, e: Error) -> Error {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        Error::new(ErrorKind::InvalidData, format!("{}: the file ends early, it may be truncated", file_name))
+    } else {
+        Error::new(e.kind(), format!("{}: {}", file_name, e))
+    }
+}
+
+fn load_bit_vector(file_name: &str) -> io::Result<BitVector> {
+    BitVector::load(file_name).map_err(|e| load_error(file_name, e))
+}
+
+fn load_sparse_array(file_name: &str) -> io::Result<SparseArray> {
+    SparseArray::load(file_name).map_err(|e| load_error(file_name, e))
+}
+
+// Reads a bit vector followed by its rank tables, as written by build rank
+fn load_rank_file(file_name: &str) -> io::Result<RankSelect> {
+    RankSelect::load(file_name).map_err(|e| load_error(file_name, e))
+}
+
+//
+// info
+//
+
+fn info(args: &[String]) -> io::Result<()> {
+    let (positional, options) = split_options(args, &["report"])?;
+    let [kind, file] = positional[..] else {
+        return Err(usage_error("info needs a kind and a file"));
+    };
+    let kind = parse_kind(kind)?;
+    let mut report = Report::Text;
+    for (_, value) in options {
+        report = match value {
+            "text" => Report::Text,
+            "json" => Report::Json,
+            "html" => Report::Html,
+            _ => return Err(usage_error(&format!("unknown report {}", value))),
+        };
+    }
+
+    let space = match kind {
+        Kind::BitVector => {
+            let bit_vector = load_bit_vector(file)?;
+            print_summary(report, &[
+                ("kind", "bitvector".to_string()),
+                ("size", bit_vector.size().to_string()),
+                ("popcount", bit_vector.count_ones().to_string()),
+                ("words", bit_vector.data.len().to_string()),
+            ]);
+            bit_vector.space_report()
+        }
+        Kind::Rank => {
+            let rank_select = load_rank_file(file)?;
+            print_summary(report, &[
+                ("kind", "rank".to_string()),
+                ("size", rank_select.size().to_string()),
+                ("popcount", rank_select.num_ones().to_string()),
+                ("chunk_size", rank_select.chunk_size().to_string()),
+                ("superchunk_size", rank_select.superchunk_size().to_string()),
+                ("overhead_bits", rank_select.overhead().to_string()),
+            ]);
+            rank_select.space_report()
+        }
+        Kind::Sparse => {
+            let sparse_array = load_sparse_array(file)?;
+            print_summary(report, &[
+                ("kind", "sparse".to_string()),
+                ("size", sparse_array.size().to_string()),
+                ("elements", sparse_array.num_elem().to_string()),
+                ("index", format!("{:?}", sparse_array.index_kind())),
+            ]);
+            sparse_array.space_report()
+        }
+    };
+
+    match report {
+        Report::Text => print!("\n{}", space.to_text()),
+        Report::Json => println!("{}", space.to_json()),
+        Report::Html => print!("{}", space.to_html()),
+    }
+    Ok(())
+}
+
+// The summary goes to stderr for the machine readable reports so that
+// stdout is only the report itself
+fn print_summary(report: Report, fields: &[(&str, String)]) {
+    for (name, value) in fields {
+        match report {
+            Report::Text => println!("{:<16} {}", name, value),
+            Report::Json | Report::Html => eprintln!("{:<16} {}", name, value),
+        }
+    }
+}
+
+//
+// query
+//
+
+fn query(args: &[String]) -> io::Result<()> {
+    let [kind, file, ops @ ..] = args else {
+        return Err(usage_error("query needs a kind and a file"));
+    };
+    let kind = parse_kind(kind)?;
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    match kind {
+        Kind::BitVector => {
+            let bit_vector = load_bit_vector(file)?;
+            answer_queries(ops, &mut out, |op, args, out| match op {
+                "access" => {
+                    let i = index_arg(args, bit_vector.size() as u64)?;
+                    writeln!(out, "{}", bit_vector.get(i as usize) as u8)
+                }
+                _ => Err(invalid_input(format!("unknown bitvector query {}", op))),
+            })?;
+        }
+        Kind::Rank => {
+            let rank_select = load_rank_file(file)?;
+            let size = rank_select.size() as u64;
+            answer_queries(ops, &mut out, |op, args, out| match op {
+                "access" => {
+                    let i = index_arg(args, size)?;
+                    writeln!(out, "{}", rank_select.get(i as usize) as u8)
+                }
+                "rank" => {
+                    let i = index_arg(args, size + 1)?;
+                    writeln!(out, "{}", rank_select.rank1(i as usize))
+                }
+                "select" => {
+                    let i = single_arg(args)?;
+                    writeln!(out, "{}", format_position(rank_select.select1(i)))
+                }
+                _ => Err(invalid_input(format!("unknown rank query {}", op))),
+            })?;
+        }
+        Kind::Sparse => {
+            let sparse_array = load_sparse_array(file)?;
+            let size = sparse_array.size();
+            answer_queries(ops, &mut out, |op, args, out| match op {
+                "get" => {
+                    let i = index_arg(args, size)?;
+                    writeln!(out, "{}", sparse_array.get(i).map_or("-", |s| s.as_str()))
+                }
+                "at-rank" => {
+                    let r = single_arg(args)?;
+                    let mut value = String::new();
+                    if sparse_array.get_at_rank(r, &mut value) {
+                        writeln!(out, "{}", value)
+                    } else {
+                        writeln!(out, "-")
+                    }
+                }
+                "index-of" => {
+                    let r = single_arg(args)?;
+                    writeln!(out, "{}", format_position(sparse_array.get_index_of(r)))
+                }
+                "num-at" => {
+                    let i = index_arg(args, size)?;
+                    writeln!(out, "{}", sparse_array.num_elem_at(i))
+                }
+                "range" => {
+                    let [start, end] = args else {
+                        return Err(invalid_input("range needs a start and an end".to_string()));
+                    };
+                    let (start, end) = (parse_number(start)?, parse_number(end)?);
+                    for (i, value) in sparse_array.range(start..end) {
+                        writeln!(out, "{}\t{}", i, value)?;
+                    }
+                    Ok(())
+                }
+                _ => Err(invalid_input(format!("unknown sparse query {}", op))),
+            })?;
+        }
+    }
+    out.flush()
+}
+
+// Runs the queries given as arguments, or one query per line of stdin when
+// there are none. From arguments every single argument op is asked once
+// per argument, so `rank 1 2 3` answers three ranks.
+fn answer_queries<W, F>(ops: &[String], out: &mut W, mut answer: F) -> io::Result<()>
+where
+    W: Write,
+    F: FnMut(&str, &[&str], &mut W) -> io::Result<()>,
+{
+    if let [op, args @ ..] = ops {
+        if args.is_empty() {
+            return Err(invalid_input(format!("{} needs an argument", op)));
+        }
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        if op == "range" {
+            return answer(op, &args, out);
+        }
+        for arg in args {
+            answer(op, &[arg], out)?;
+        }
+        return Ok(());
+    }
+
+    for (line_number, line) in io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((op, args)) = words.split_first() else {
+            continue;
+        };
+        answer(op, args, out).map_err(|e| Error::new(e.kind(), format!("line {}: {}", line_number + 1, e)))?;
+    }
+    Ok(())
+}
+
+fn single_arg(args: &[&str]) -> io::Result<u64> {
+    match args {
+        [arg] => parse_number(arg),
+        _ => Err(invalid_input(format!("expected one argument but got {}", args.len()))),
+    }
+}
+
+// An index that has to be below limit
+fn index_arg(args: &[&str], limit: u64) -> io::Result<u64> {
+    let i = single_arg(args)?;
+    if i >= limit {
+        return Err(invalid_input(format!("index {} is out of range, it has to be below {}", i, limit)));
+    }
+    Ok(i)
+}
+
+// select and get_index_of return u64::MAX when there is no such element
+fn format_position(pos: u64) -> String {
+    if pos == u64::MAX {
+        "-".to_string()
+    } else {
+        pos.to_string()
+    }
+}
+
+//
+// verify
+//
+
+fn verify(args: &[String]) -> io::Result<()> {
+    let [kind, file] = args else {
+        return Err(usage_error("verify needs a kind and a file"));
+    };
+    match parse_kind(kind)? {
+        Kind::BitVector => {
+            let bit_vector = load_bit_vector(file)?;
+            bit_vector.verify()?;
+            println!("ok: bit vector of size {} with {} ones", bit_vector.size(), bit_vector.count_ones());
+        }
+        Kind::Rank => {
+            let rank_select = load_rank_file(file)?;
+            rank_select.verify()?;
+            println!("ok: rank index of size {} with {} ones", rank_select.size(), rank_select.num_ones());
+        }
+        Kind::Sparse => {
+            let sparse_array = load_sparse_array(file)?;
+            sparse_array.verify()?;
+            println!("ok: sparse array of size {} with {} elements", sparse_array.size(), sparse_array.num_elem());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,595 @@
+use crate::bit_vector::BitVector;
+use crate::broadword::{low_mask, rank_in_word, select_in_word};
+use crate::rank_support::RankSupport;
+use crate::select_support::SelectSupport;
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+use std::cmp;
+
+// Leaves never hold more bits than fit in one word, and never fewer than
+// half of that unless the leaf is the whole tree
+const LEAF_BITS: usize = 64;
+const MIN_LEAF_BITS: usize = LEAF_BITS / 2;
+
+// A bit vector that supports inserting and deleting bits in the middle.
+// The bits live in word sized leaves of a height balanced (AVL) tree and
+// every internal node keeps the number of bits and ones below it, so
+// get, set, insert, delete, rank1 and select1 all walk one root to leaf
+// path and take O(log n). The tree is rebalanced in place, so only
+// splitting a full leaf allocates. A leaf that drops below half a word
+// borrows bits from its neighbour, or is merged into it, so deletes
+// don't leave the tree full of nearly empty leaves.
+pub struct DynamicBitVector {
+    root: Box<Node>,
+}
+
+enum Node {
+    // The lowest len bits of bits, bit 0 comes first
+    Leaf {
+        bits: u64,
+        len: usize,
+    },
+    Internal {
+        left: Box<Node>,
+        right: Box<Node>,
+        // Number of bits and ones in the whole subtree
+        len: usize,
+        ones: usize,
+        height: usize,
+    },
+}
+
+impl Node {
+    // What a node holds while its children are moved around
+    const EMPTY: Node = Node::Leaf { bits: 0, len: 0 };
+
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf { len, .. } => *len,
+            Node::Internal { len, .. } => *len,
+        }
+    }
+
+    fn ones(&self) -> usize {
+        match self {
+            Node::Leaf { bits, .. } => bits.count_ones() as usize,
+            Node::Internal { ones, .. } => *ones,
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            Node::Leaf { .. } => 0,
+            Node::Internal { height, .. } => *height,
+        }
+    }
+
+    // An internal node over two subtrees with the right counts
+    fn internal(left: Box<Node>, right: Box<Node>) -> Node {
+        let len = left.len() + right.len();
+        let ones = left.ones() + right.ones();
+        let height = 1 + cmp::max(left.height(), right.height());
+        Node::Internal { left, right, len, ones, height }
+    }
+
+    // Recomputes the counts of an internal node after its children changed
+    fn update(&mut self) {
+        if let Node::Internal { left, right, len, ones, height } = self {
+            *len = left.len() + right.len();
+            *ones = left.ones() + right.ones();
+            *height = 1 + cmp::max(left.height(), right.height());
+        }
+    }
+
+    // Builds a perfectly balanced tree over the leaves
+    fn build(mut leaves: Vec<Node>) -> Box<Node> {
+        if leaves.len() == 1 {
+            return Box::new(leaves.pop().unwrap());
+        }
+        let right_leaves = leaves.split_off(leaves.len() / 2);
+        let left = Node::build(leaves);
+        let right = Node::build(right_leaves);
+        Box::new(Node::internal(left, right))
+    }
+
+    //     node              right
+    //    /    \            /     \
+    //   a    right  ->   node     c
+    //        /   \      /    \
+    //       b     c    a      b
+    //
+    // The two nodes keep their boxes, only what they point to changes
+    fn rotate_left(&mut self) {
+        let Node::Internal { left: a, right: mut node, .. } = std::mem::replace(self, Node::EMPTY) else {
+            unreachable!("a leaf can't be rotated")
+        };
+        let Node::Internal { left: b, right: c, .. } = std::mem::replace(&mut *node, Node::EMPTY) else {
+            unreachable!("a leaf can't be rotated")
+        };
+        *node = Node::internal(a, b);
+        *self = Node::internal(node, c);
+    }
+
+    //       node          left
+    //      /    \        /    \
+    //    left    c  ->  a     node
+    //   /    \               /    \
+    //  a      b             b      c
+    fn rotate_right(&mut self) {
+        let Node::Internal { left: mut node, right: c, .. } = std::mem::replace(self, Node::EMPTY) else {
+            unreachable!("a leaf can't be rotated")
+        };
+        let Node::Internal { left: a, right: b, .. } = std::mem::replace(&mut *node, Node::EMPTY) else {
+            unreachable!("a leaf can't be rotated")
+        };
+        *node = Node::internal(b, c);
+        *self = Node::internal(a, node);
+    }
+
+    // Whether the right child is higher than the left one
+    fn leans_right(&self) -> bool {
+        match self {
+            Node::Leaf { .. } => false,
+            Node::Internal { left, right, .. } => right.height() > left.height(),
+        }
+    }
+
+    // Whether the left child is higher than the right one
+    fn leans_left(&self) -> bool {
+        match self {
+            Node::Leaf { .. } => false,
+            Node::Internal { left, right, .. } => left.height() > right.height(),
+        }
+    }
+
+    // Updates the counts after one of the children changed and, if their
+    // heights now differ by 2, rotates so they differ by at most 1 again
+    fn rebalance(&mut self) {
+        let Node::Internal { left, right, .. } = self else {
+            return;
+        };
+        if left.height() > right.height() + 1 {
+            // Left-right case, first turn the left child into a left-left case
+            if left.leans_right() {
+                left.rotate_left();
+            }
+            self.rotate_right();
+        } else if right.height() > left.height() + 1 {
+            // Right-left case, first turn the right child into a right-right case
+            if right.leans_left() {
+                right.rotate_right();
+            }
+            self.rotate_left();
+        } else {
+            self.update();
+        }
+    }
+
+    fn get(&self, i: usize) -> bool {
+        match self {
+            Node::Leaf { bits, .. } => (bits >> i) & 1 == 1,
+            Node::Internal { left, right, .. } => {
+                if i < left.len() {
+                    left.get(i)
+                } else {
+                    right.get(i - left.len())
+                }
+            }
+        }
+    }
+
+    // Sets bit i and returns how the number of ones changed
+    fn set(&mut self, i: usize, value: bool) -> isize {
+        match self {
+            Node::Leaf { bits, .. } => {
+                let before = (*bits >> i) & 1 == 1;
+                let mask = 1u64 << i;
+                if value {
+                    *bits |= mask;
+                } else {
+                    *bits &= !mask;
+                }
+                value as isize - before as isize
+            }
+            Node::Internal { left, right, ones, .. } => {
+                let delta = if i < left.len() {
+                    left.set(i, value)
+                } else {
+                    let left_len = left.len();
+                    right.set(i - left_len, value)
+                };
+                *ones = (*ones as isize + delta) as usize;
+                delta
+            }
+        }
+    }
+
+    fn rank1(&self, i: usize) -> usize {
+        match self {
+            Node::Leaf { bits, .. } => rank_in_word(*bits, i) as usize,
+            Node::Internal { left, right, .. } => {
+                if i < left.len() {
+                    left.rank1(i)
+                } else {
+                    left.ones() + right.rank1(i - left.len())
+                }
+            }
+        }
+    }
+
+    // Position of the k-th one (1 indexed), k has to be at most ones()
+    fn position_of_one(&self, k: usize) -> usize {
+        match self {
+            Node::Leaf { bits, .. } => select_in_word(*bits, k - 1),
+            Node::Internal { left, right, .. } => {
+                if k <= left.ones() {
+                    left.position_of_one(k)
+                } else {
+                    left.len() + right.position_of_one(k - left.ones())
+                }
+            }
+        }
+    }
+
+    // Inserts bit at position i, splitting a full leaf into two halves
+    fn insert(&mut self, i: usize, value: bool) {
+        if let Node::Leaf { bits, len: LEAF_BITS } = *self {
+            let left = Box::new(Node::Leaf { bits: bits & low_mask(MIN_LEAF_BITS), len: MIN_LEAF_BITS });
+            let right = Box::new(Node::Leaf { bits: bits >> MIN_LEAF_BITS, len: LEAF_BITS - MIN_LEAF_BITS });
+            *self = Node::internal(left, right);
+        }
+        match self {
+            Node::Leaf { bits, len } => {
+                // Keep the bits below i, put the new bit at i and move
+                // everything from i up by one
+                let low = *bits & low_mask(i);
+                let high = if i >= 64 { 0 } else { *bits >> i };
+                *bits = low | ((value as u64) << i) | (high << 1 << i);
+                *len += 1;
+            }
+            Node::Internal { left, right, .. } => {
+                if i < left.len() {
+                    left.insert(i, value);
+                } else {
+                    let left_len = left.len();
+                    right.insert(i - left_len, value);
+                }
+                self.rebalance();
+            }
+        }
+    }
+
+    // Deletes the bit at position i and returns it. A leaf child left with
+    // fewer than MIN_LEAF_BITS bits borrows from or merges into the leaf
+    // next to it, which is in the subtree of its sibling.
+    fn delete(&mut self, i: usize) -> bool {
+        let value = match self {
+            Node::Leaf { bits, len } => {
+                let value = (*bits >> i) & 1 == 1;
+                let low = *bits & low_mask(i);
+                let high = if i + 1 >= 64 { 0 } else { *bits >> (i + 1) };
+                *bits = low | (high << i);
+                *len -= 1;
+                return value;
+            }
+            Node::Internal { left, right, .. } => {
+                if i < left.len() {
+                    left.delete(i)
+                } else {
+                    let left_len = left.len();
+                    right.delete(i - left_len)
+                }
+            }
+        };
+        self.refill_leaf_child();
+        self.rebalance();
+        value
+    }
+
+    // Brings a leaf child that fell below MIN_LEAF_BITS back up, from the
+    // first leaf of the right sibling or the last leaf of the left one
+    fn refill_leaf_child(&mut self) {
+        let Node::Internal { left, right, .. } = self else {
+            return;
+        };
+        if let Node::Leaf { bits, len } = **left {
+            if len < MIN_LEAF_BITS {
+                let neighbour_len = right.first_leaf_len();
+                if len + neighbour_len <= LEAF_BITS {
+                    right.push_front(bits, len);
+                    self.replace_with_child(false);
+                } else {
+                    let moved = (neighbour_len - len) / 2;
+                    let taken = right.take_front(moved);
+                    **left = Node::Leaf { bits: bits | (taken << len), len: len + moved };
+                }
+                return;
+            }
+        }
+        if let Node::Leaf { bits, len } = **right {
+            if len < MIN_LEAF_BITS {
+                let neighbour_len = left.last_leaf_len();
+                if len + neighbour_len <= LEAF_BITS {
+                    left.push_back(bits, len);
+                    self.replace_with_child(true);
+                } else {
+                    let moved = (neighbour_len - len) / 2;
+                    let taken = left.take_back(moved);
+                    **right = Node::Leaf { bits: taken | (bits << moved), len: len + moved };
+                }
+            }
+        }
+    }
+
+    // Drops the other child and takes the place of the left or right one
+    fn replace_with_child(&mut self, left_child: bool) {
+        let Node::Internal { left, right, .. } = std::mem::replace(self, Node::EMPTY) else {
+            unreachable!("a leaf has no children")
+        };
+        *self = if left_child { *left } else { *right };
+    }
+
+    fn first_leaf_len(&self) -> usize {
+        match self {
+            Node::Leaf { len, .. } => *len,
+            Node::Internal { left, .. } => left.first_leaf_len(),
+        }
+    }
+
+    fn last_leaf_len(&self) -> usize {
+        match self {
+            Node::Leaf { len, .. } => *len,
+            Node::Internal { right, .. } => right.last_leaf_len(),
+        }
+    }
+
+    // Puts len bits in front of the first leaf, which has room for them
+    // and at least one bit already
+    fn push_front(&mut self, new_bits: u64, new_len: usize) {
+        match self {
+            Node::Leaf { bits, len } => {
+                *bits = new_bits | (*bits << new_len);
+                *len += new_len;
+            }
+            Node::Internal { left, .. } => {
+                left.push_front(new_bits, new_len);
+                self.update();
+            }
+        }
+    }
+
+    // Puts len bits after the last leaf, which has room for them and at
+    // least one bit already
+    fn push_back(&mut self, new_bits: u64, new_len: usize) {
+        match self {
+            Node::Leaf { bits, len } => {
+                *bits |= new_bits << *len;
+                *len += new_len;
+            }
+            Node::Internal { right, .. } => {
+                right.push_back(new_bits, new_len);
+                self.update();
+            }
+        }
+    }
+
+    // Removes the first k bits of the first leaf, fewer than it has, and
+    // returns them
+    fn take_front(&mut self, k: usize) -> u64 {
+        match self {
+            Node::Leaf { bits, len } => {
+                let taken = *bits & low_mask(k);
+                *bits >>= k;
+                *len -= k;
+                taken
+            }
+            Node::Internal { left, .. } => {
+                let taken = left.take_front(k);
+                self.update();
+                taken
+            }
+        }
+    }
+
+    // Removes the last k bits of the last leaf, fewer than it has, and
+    // returns them
+    fn take_back(&mut self, k: usize) -> u64 {
+        match self {
+            Node::Leaf { bits, len } => {
+                *len -= k;
+                let taken = (*bits >> *len) & low_mask(k);
+                *bits &= low_mask(*len);
+                taken
+            }
+            Node::Internal { right, .. } => {
+                let taken = right.take_back(k);
+                self.update();
+                taken
+            }
+        }
+    }
+
+    // Calls visit with every leaf in order
+    fn for_each_leaf<F: FnMut(u64, usize)>(&self, visit: &mut F) {
+        match self {
+            Node::Leaf { bits, len } => visit(*bits, *len),
+            Node::Internal { left, right, .. } => {
+                left.for_each_leaf(visit);
+                right.for_each_leaf(visit);
+            }
+        }
+    }
+
+    fn num_nodes(&self) -> usize {
+        match self {
+            Node::Leaf { .. } => 1,
+            Node::Internal { left, right, .. } => 1 + left.num_nodes() + right.num_nodes(),
+        }
+    }
+
+    fn num_leaves(&self) -> usize {
+        match self {
+            Node::Leaf { .. } => 1,
+            Node::Internal { left, right, .. } => left.num_leaves() + right.num_leaves(),
+        }
+    }
+}
+
+impl Default for DynamicBitVector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DynamicBitVector {
+
+    // Instantiates an empty DynamicBitVector
+    pub fn new() -> Self {
+        Self { root: Box::new(Node::EMPTY) }
+    }
+
+    // Copies the bits of a static BitVector, one leaf per word. A short
+    // last word is evened out with the one before it.
+    pub fn from_bit_vector(bit_vector: &BitVector) -> Self {
+        let size = bit_vector.size();
+        if size == 0 {
+            return Self::new();
+        }
+        let mut leaves = Vec::with_capacity(size.div_ceil(LEAF_BITS));
+        for (array_index, &word) in bit_vector.data.iter().enumerate() {
+            let start = array_index * 64;
+            if start >= size {
+                break;
+            }
+            let len = cmp::min(64, size - start);
+            leaves.push(Node::Leaf { bits: word & low_mask(len), len });
+        }
+        let last_len = size - (leaves.len() - 1) * LEAF_BITS;
+        if leaves.len() > 1 && last_len < MIN_LEAF_BITS {
+            let (Some(Node::Leaf { bits: last, .. }), Some(Node::Leaf { bits: full, .. })) = (leaves.pop(), leaves.pop()) else {
+                unreachable!("only leaves so far")
+            };
+            let both = full as u128 | (last as u128) << LEAF_BITS;
+            let total = LEAF_BITS + last_len;
+            let half = total / 2;
+            leaves.push(Node::Leaf { bits: both as u64 & low_mask(half), len: half });
+            leaves.push(Node::Leaf { bits: (both >> half) as u64, len: total - half });
+        }
+        Self { root: Node::build(leaves) }
+    }
+
+    // Copies the bit vector a RankSupport was built over
+    pub fn from_rank_support(rank_support: &RankSupport) -> Self {
+        Self::from_bit_vector(rank_support.bit_vector())
+    }
+
+    // Copies the bit vector a SelectSupport was built over
+    pub fn from_select_support(select_support: &SelectSupport) -> Self {
+        Self::from_rank_support(select_support.rank_support())
+    }
+
+    // Writes the bits into a static BitVector, which RankSupport and
+    // SelectSupport can then be built over
+    pub fn to_bit_vector(&self) -> BitVector {
+        let mut bit_vector = BitVector::new(self.size());
+        let mut offset = 0;
+        self.root.for_each_leaf(&mut |bits, len| {
+            let array_index = offset / 64;
+            let bit_index = offset % 64;
+            bit_vector.data[array_index] |= bits << bit_index;
+            // The leaf runs over into the next word
+            if bit_index != 0 && bit_index + len > 64 {
+                bit_vector.data[array_index + 1] |= bits >> (64 - bit_index);
+            }
+            offset += len;
+        });
+        bit_vector
+    }
+
+    // Returns the number of bits
+    pub fn size(&self) -> usize {
+        self.root.len()
+    }
+
+    // Returns the number of ones
+    pub fn num_ones(&self) -> usize {
+        self.root.ones()
+    }
+
+    // Gets the value at index
+    pub fn get(&self, index: usize) -> bool {
+        assert!(index < self.size(), "Index out of bounds");
+        self.root.get(index)
+    }
+
+    // Sets the value of the bit at index
+    pub fn set(&mut self, index: usize, value: bool) {
+        assert!(index < self.size(), "Index out of bounds");
+        self.root.set(index, value);
+    }
+
+    // Inserts a bit at index, moving every bit from index on up by one.
+    // index can be the size, which appends the bit.
+    pub fn insert(&mut self, index: usize, value: bool) {
+        assert!(index <= self.size(), "Index out of bounds");
+        self.root.insert(index, value);
+    }
+
+    // Appends a bit to the end
+    pub fn push(&mut self, value: bool) {
+        self.insert(self.size(), value);
+    }
+
+    // Deletes the bit at index, moving every bit after it down by one,
+    // and returns its value
+    pub fn delete(&mut self, index: usize) -> bool {
+        assert!(index < self.size(), "Index out of bounds");
+        self.root.delete(index)
+    }
+
+    // Number of ones before index i, i can be the size
+    pub fn rank1(&self, i: usize) -> u64 {
+        assert!(i <= self.size(), "Index out of bounds");
+        self.root.rank1(i) as u64
+    }
+
+    // Gives position, in the bit vector, of the FIRST index, j for which rank1(j) = i.
+    // Returns u64::MAX if there are fewer than i ones.
+    pub fn select1(&self, i: u64) -> u64 {
+        if i == 0 {
+            return 0;
+        }
+        if i > self.num_ones() as u64 {
+            return u64::MAX;
+        }
+        self.root.position_of_one(i as usize) as u64 + 1
+    }
+
+    // Height of the tree, a leaf on its own has height 0
+    pub fn height(&self) -> usize {
+        self.root.height()
+    }
+
+    // Number of leaves, each of them at least half full unless there is
+    // only one
+    pub fn num_leaves(&self) -> usize {
+        self.root.num_leaves()
+    }
+
+    // Bytes the tree takes, the total of its space report
+    pub fn memory_usage(&self) -> usize {
+        self.space_report().bytes() as usize
+    }
+}
+
+// Every node, the root included, is its own Box allocation
+impl SpaceUsage for DynamicBitVector {
+    fn space_report(&self) -> SpaceNode {
+        let num_nodes = self.root.num_nodes() as u64;
+        let num_leaves = self.root.num_leaves() as u64;
+        SpaceNode::with_children("DynamicBitVector", vec![
+            SpaceNode::leaf("header", inline_bits::<DynamicBitVector>()),
+            SpaceNode::leaf("leaves", num_leaves * inline_bits::<Node>()),
+            SpaceNode::leaf("internal nodes", (num_nodes - num_leaves) * inline_bits::<Node>()),
+        ])
+    }
+}
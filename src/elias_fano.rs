@@ -0,0 +1,151 @@
+use crate::bit_vector::BitVector;
+use crate::broadword::low_mask;
+use crate::packed_ints::PackedInts;
+use crate::rank_select::RankSelect;
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+
+// A sorted set of u64s in Elias-Fano form, for universes too big for a
+// bit per position, up to all of 2^64. Every value is split into its low
+// l bits, packed, and its high bits, a bucket number. The buckets are
+// unary like the rows of a SparseMatrix, a 0 for every value and a 1
+// closing every bucket, so bucket h holds values
+// [select1(h) - h, select1(h + 1) - (h + 1)).
+//
+// l is about log2(max / n), which keeps the number of buckets near n and
+// the whole set at about 2 + log2(max / n) bits a value.
+pub struct EliasFano {
+    low_bits: PackedInts,
+    buckets: RankSelect,
+    num_buckets: u64,
+}
+
+impl EliasFano {
+    // values has to be strictly increasing
+    pub fn from_sorted(values: &[u64]) -> Self {
+        for pair in values.windows(2) {
+            assert!(pair[0] < pair[1], "values must be strictly increasing, {} comes before {}", pair[0], pair[1]);
+        }
+        let n = values.len() as u128;
+        let universe = values.last().map_or(0, |&max| max as u128 + 1);
+        let low_width = if n == 0 || universe <= n { 0 } else { ((universe / n).ilog2() as usize).min(63) };
+        let num_buckets = values.last().map_or(0, |&max| (max >> low_width) + 1);
+
+        let mut low_bits = PackedInts::new(low_width);
+        let mut bits = BitVector::new(values.len() + num_buckets as usize);
+        let (mut pos, mut bucket) = (0, 0);
+        for &value in values {
+            while bucket < value >> low_width {
+                bits.set(pos, true);
+                pos += 1;
+                bucket += 1;
+            }
+            low_bits.push(value & low_mask(low_width));
+            pos += 1;
+        }
+        for pos in pos..bits.size() {
+            bits.set(pos, true);
+        }
+        low_bits.shrink_to_fit();
+
+        EliasFano {
+            low_bits,
+            buckets: RankSelect::new(bits),
+            num_buckets,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.low_bits.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.low_bits.is_empty()
+    }
+
+    fn low_width(&self) -> usize {
+        self.low_bits.width()
+    }
+
+    // The values of bucket h are the ones at [start, end)
+    fn bucket_range(&self, h: u64) -> (u64, u64) {
+        (self.buckets.select1(h) - h, self.buckets.select1(h + 1) - (h + 1))
+    }
+
+    // Number of values below x
+    pub fn rank(&self, x: u64) -> u64 {
+        let h = x >> self.low_width();
+        if h >= self.num_buckets {
+            return self.len();
+        }
+        let (start, end) = self.bucket_range(h);
+        let low = x & low_mask(self.low_width());
+        start + self.partition_point(start, end, |value| value < low)
+    }
+
+    // The rank of x if it is in the set
+    pub fn index_of(&self, x: u64) -> Option<u64> {
+        let i = self.rank(x);
+        (i < self.len() && self.get(i) == x).then_some(i)
+    }
+
+    pub fn contains(&self, x: u64) -> bool {
+        self.index_of(x).is_some()
+    }
+
+    // The i-th smallest value, 0 indexed. Its bucket is found with a
+    // binary search over the buckets, log(buckets) select1s.
+    pub fn get(&self, i: u64) -> u64 {
+        assert!(i < self.len(), "index {} out of bounds for {} values", i, self.len());
+        // The last bucket starting at or before i, which skips the empty
+        // buckets that start there too
+        let (mut low, mut high) = (0, self.num_buckets);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if self.buckets.select1(mid) - mid <= i {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        (low << self.low_width()) | self.low_bits.get(i as usize)
+    }
+
+    // The values in increasing order, walking the buckets once
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        let mut bucket = 0;
+        let mut i = 0;
+        (0..self.buckets.size()).filter_map(move |pos| {
+            if self.buckets.get(pos) {
+                bucket += 1;
+                return None;
+            }
+            i += 1;
+            Some((bucket << self.low_width()) | self.low_bits.get(i - 1))
+        })
+    }
+
+    // Number of low parts in [start, end) for which f holds, f has to
+    // hold for a prefix of them
+    fn partition_point<F: Fn(u64) -> bool>(&self, start: u64, end: u64, f: F) -> u64 {
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if f(self.low_bits.get(mid as usize)) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low - start
+    }
+}
+
+impl SpaceUsage for EliasFano {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("EliasFano", vec![
+            SpaceNode::leaf("header", inline_bits::<EliasFano>() - inline_bits::<PackedInts>() - inline_bits::<RankSelect>()),
+            self.low_bits.space_report().renamed("low_bits"),
+            self.buckets.space_report().renamed("buckets"),
+        ])
+    }
+}
@@ -0,0 +1,522 @@
+// The experiments from the writeup as a reproducible benchmark runner.
+//
+// Every input and every query is drawn from an rng seeded with one of the
+// configured seeds, so two runs with the same config on different commits
+// time exactly the same work and their result tables can be compared row
+// by row with `experiment compare`.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::process::Command;
+use std::time::Instant;
+
+use hw2_code::bit_vector::BitVector;
+use hw2_code::rank_support::RankSupport;
+use hw2_code::select_support::SelectSupport;
+use hw2_code::space_report::{inline_bits, HeapSize, SpaceUsage};
+use hw2_code::sparse_array::SparseArray;
+
+use crate::plots;
+
+pub const USAGE: &str = "\
+usage:
+  hw2_code experiment run [--config FILE] [--<key> VALUE...] [--csv FILE] [--json FILE]
+  hw2_code experiment compare <baseline.csv> <new.csv> [--threshold RATIO]
+  hw2_code experiment plot <results.csv> <output dir> [--format svg|png]
+
+config keys, in a file as 'key = value' lines or as --key value:
+  experiments   rank, select, sparse            (default all three)
+  sizes         sizes of the bit vectors and sparse arrays
+  sparsities    fraction of positions that are set, between 0 and 1
+  operations    queries timed per repetition
+  seeds         one set of inputs and queries per seed
+  warmup        untimed runs before the repetitions
+  repetitions   timed runs, the percentiles are over these";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Experiment {
+    Rank,
+    Select,
+    Sparse,
+}
+
+impl Experiment {
+    fn name(self) -> &'static str {
+        match self {
+            Experiment::Rank => "rank",
+            Experiment::Select => "select",
+            Experiment::Sparse => "sparse",
+        }
+    }
+
+    fn parse(name: &str) -> std::io::Result<Self> {
+        match name {
+            "rank" => Ok(Experiment::Rank),
+            "select" => Ok(Experiment::Select),
+            "sparse" => Ok(Experiment::Sparse),
+            _ => Err(invalid_input(format!("unknown experiment {}, expected rank, select or sparse", name))),
+        }
+    }
+}
+
+pub struct Config {
+    pub experiments: Vec<Experiment>,
+    pub sizes: Vec<u64>,
+    pub sparsities: Vec<f64>,
+    pub operations: usize,
+    pub seeds: Vec<u64>,
+    pub warmup: usize,
+    pub repetitions: usize,
+}
+
+impl Default for Config {
+    // The sizes and sparsities the writeup asks for
+    fn default() -> Self {
+        Self {
+            experiments: vec![Experiment::Rank, Experiment::Select, Experiment::Sparse],
+            sizes: vec![1_000, 10_000, 100_000, 1_000_000],
+            sparsities: vec![0.01, 0.05, 0.1],
+            operations: 100_000,
+            seeds: vec![1],
+            warmup: 1,
+            repetitions: 10,
+        }
+    }
+}
+
+impl Config {
+    // Reads 'key = value' lines, blank lines and lines starting with # are skipped
+    pub fn load(file_name: &str) -> std::io::Result<Self> {
+        let mut config = Config::default();
+        for (line_number, line) in fs::read_to_string(file_name)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid_input(format!("{}: line {}: expected key = value", file_name, line_number + 1)));
+            };
+            config.set(key.trim(), value.trim())
+                .map_err(|e| invalid_input(format!("{}: line {}: {}", file_name, line_number + 1, e)))?;
+        }
+        Ok(config)
+    }
+
+    // Sets one key, lists are separated by commas
+    pub fn set(&mut self, key: &str, value: &str) -> std::io::Result<()> {
+        match key {
+            "experiments" => self.experiments = parse_list(value, Experiment::parse)?,
+            "sizes" => self.sizes = parse_list(value, parse_value)?,
+            "sparsities" => {
+                self.sparsities = parse_list(value, parse_value)?;
+                if let Some(s) = self.sparsities.iter().find(|s| !(0.0..=1.0).contains(*s)) {
+                    return Err(invalid_input(format!("sparsity {} is not between 0 and 1", s)));
+                }
+            }
+            "operations" => self.operations = parse_value(value)?,
+            "seeds" => self.seeds = parse_list(value, parse_value)?,
+            "warmup" => self.warmup = parse_value(value)?,
+            "repetitions" => self.repetitions = parse_value(value)?,
+            _ => return Err(invalid_input(format!("unknown config key {}", key))),
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> std::io::Result<()> {
+        if self.repetitions == 0 || self.operations == 0 {
+            return Err(invalid_input("repetitions and operations have to be at least 1".to_string()));
+        }
+        if let Some(size) = self.sizes.iter().find(|&&size| size == 0) {
+            return Err(invalid_input(format!("size {} is too small, the queries need at least 1 bit", size)));
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> String {
+        let list = |values: Vec<String>| values.join(",");
+        format!(
+            "{{\"experiments\":[{}],\"sizes\":[{}],\"sparsities\":[{}],\"operations\":{},\"seeds\":[{}],\"warmup\":{},\"repetitions\":{}}}",
+            list(self.experiments.iter().map(|e| format!("\"{}\"", e.name())).collect()),
+            list(self.sizes.iter().map(|s| s.to_string()).collect()),
+            list(self.sparsities.iter().map(|s| s.to_string()).collect()),
+            self.operations,
+            list(self.seeds.iter().map(|s| s.to_string()).collect()),
+            self.warmup,
+            self.repetitions
+        )
+    }
+}
+
+fn invalid_input(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str) -> std::io::Result<T> {
+    value.trim().parse().map_err(|_| invalid_input(format!("can't parse {}", value.trim())))
+}
+
+fn parse_list<T, F: Fn(&str) -> std::io::Result<T>>(value: &str, parse: F) -> std::io::Result<Vec<T>> {
+    value.split(',').map(|item| parse(item.trim())).collect()
+}
+
+// One row of the results table: the timings of one operation on one
+// structure built from one seed, summarised over the repetitions
+pub struct ResultRow {
+    pub experiment: &'static str,
+    pub structure: &'static str,
+    pub operation: &'static str,
+    pub size: u64,
+    pub sparsity: f64,
+    pub seed: u64,
+    pub operations: usize,
+    pub repetitions: usize,
+    // Nanoseconds per operation
+    pub median_ns: f64,
+    pub p5_ns: f64,
+    pub p25_ns: f64,
+    pub p75_ns: f64,
+    pub p95_ns: f64,
+    pub min_ns: f64,
+    pub max_ns: f64,
+    // Total bits the structure takes, from its space report
+    pub space_bits: u64,
+}
+
+const CSV_HEADER: &str = "commit,experiment,structure,operation,size,sparsity,seed,operations,repetitions,\
+median_ns,p5_ns,p25_ns,p75_ns,p95_ns,min_ns,max_ns,space_bits";
+
+impl ResultRow {
+    fn to_csv(&self, commit: &str) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{}",
+            commit, self.experiment, self.structure, self.operation, self.size, self.sparsity, self.seed,
+            self.operations, self.repetitions, self.median_ns, self.p5_ns, self.p25_ns, self.p75_ns,
+            self.p95_ns, self.min_ns, self.max_ns, self.space_bits
+        )
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"experiment\":\"{}\",\"structure\":\"{}\",\"operation\":\"{}\",\"size\":{},\"sparsity\":{},\"seed\":{},\
+\"operations\":{},\"repetitions\":{},\"median_ns\":{:.3},\"p5_ns\":{:.3},\"p25_ns\":{:.3},\"p75_ns\":{:.3},\
+\"p95_ns\":{:.3},\"min_ns\":{:.3},\"max_ns\":{:.3},\"space_bits\":{}}}",
+            self.experiment, self.structure, self.operation, self.size, self.sparsity, self.seed,
+            self.operations, self.repetitions, self.median_ns, self.p5_ns, self.p25_ns, self.p75_ns,
+            self.p95_ns, self.min_ns, self.max_ns, self.space_bits
+        )
+    }
+}
+
+// What every timed operation is run with, and the fields every row of
+// one experiment shares
+struct Run<'c> {
+    config: &'c Config,
+    experiment: Experiment,
+    size: u64,
+    sparsity: f64,
+    seed: u64,
+}
+
+impl Run<'_> {
+    // Times op over all the queries warmup + repetitions times and keeps
+    // the nanoseconds per operation of the repetitions. The sum of the
+    // answers goes through black_box so the queries can't be optimized away.
+    fn time<F: FnMut(u64) -> u64>(&self, structure: &'static str, operation: &'static str, queries: &[u64], space_bits: u64, mut op: F) -> ResultRow {
+        let mut run_once = || {
+            let start = Instant::now();
+            let mut checksum = 0u64;
+            for &query in queries {
+                checksum = checksum.wrapping_add(op(black_box(query)));
+            }
+            black_box(checksum);
+            start.elapsed().as_nanos() as f64 / queries.len() as f64
+        };
+
+        for _ in 0..self.config.warmup {
+            run_once();
+        }
+        let mut samples: Vec<f64> = (0..self.config.repetitions).map(|_| run_once()).collect();
+        samples.sort_by(|a, b| a.total_cmp(b));
+
+        ResultRow {
+            experiment: self.experiment.name(),
+            structure,
+            operation,
+            size: self.size,
+            sparsity: self.sparsity,
+            seed: self.seed,
+            operations: queries.len(),
+            repetitions: samples.len(),
+            median_ns: percentile(&samples, 50.0),
+            p5_ns: percentile(&samples, 5.0),
+            p25_ns: percentile(&samples, 25.0),
+            p75_ns: percentile(&samples, 75.0),
+            p95_ns: percentile(&samples, 95.0),
+            min_ns: samples[0],
+            max_ns: samples[samples.len() - 1],
+            space_bits,
+        }
+    }
+
+    // A different stream for every experiment, size and sparsity, so adding
+    // a size to the config doesn't change the inputs of the others
+    fn rng(&self, stream: u64) -> StdRng {
+        let mut seed = self.seed ^ (self.size << 16) ^ ((self.sparsity * 1e6) as u64).rotate_left(40);
+        seed = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ stream;
+        StdRng::seed_from_u64(seed)
+    }
+
+    fn random_bit_vector(&self) -> BitVector {
+        let mut rng = self.rng(0);
+        let mut bit_vector = BitVector::new(self.size as usize);
+        for i in 0..self.size as usize {
+            if rng.gen_bool(self.sparsity) {
+                bit_vector.set(i, true);
+            }
+        }
+        bit_vector
+    }
+
+    // operations queries uniform in range, or none if the range is empty
+    fn queries(&self, range: std::ops::Range<u64>) -> Vec<u64> {
+        if range.is_empty() {
+            return Vec::new();
+        }
+        let mut rng = self.rng(1);
+        (0..self.config.operations).map(|_| rng.gen_range(range.clone())).collect()
+    }
+}
+
+// Linear interpolation between the closest ranks of sorted samples
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let below = rank.floor() as usize;
+    let above = rank.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+fn run_rank(run: &Run, rows: &mut Vec<ResultRow>) {
+    let bit_vector = run.random_bit_vector();
+    let rank_support = RankSupport::new(&bit_vector);
+    let space = rank_support.space_report().bits;
+    let queries = run.queries(0..run.size);
+    rows.push(run.time("rank_support", "rank1", &queries, space, |i| rank_support.rank1(i as usize)));
+}
+
+fn run_select(run: &Run, rows: &mut Vec<ResultRow>) {
+    let bit_vector = run.random_bit_vector();
+    let rank_support = RankSupport::new(&bit_vector);
+    let select_support = SelectSupport::new(&rank_support);
+    let space = select_support.space_report().bits;
+    let queries = run.queries(1..bit_vector.count_ones() as u64 + 1);
+    if !queries.is_empty() {
+        rows.push(run.time("select_support", "select1", &queries, space, |i| select_support.select1(i)));
+    }
+}
+
+// The sparse array against the same elements stored explicitly, with an
+// empty string at every missing position
+fn run_sparse(run: &Run, rows: &mut Vec<ResultRow>) {
+    let bit_vector = run.random_bit_vector();
+    let mut sparse_array = SparseArray::create(run.size);
+    let mut dense: Vec<String> = Vec::with_capacity(run.size as usize);
+    for i in 0..run.size {
+        if bit_vector.get(i as usize) {
+            sparse_array.append("hello world".to_string(), i);
+            dense.push("hello world".to_string());
+        } else {
+            dense.push(String::new());
+        }
+    }
+    sparse_array.finalize();
+
+    let sparse_space = sparse_array.space_report().bits;
+    let dense_space = inline_bits::<Vec<String>>() + dense.heap_bytes() as u64 * 8;
+    let num_elem = sparse_array.num_elem();
+    let index_queries = run.queries(0..run.size);
+    let rank_queries = run.queries(0..num_elem);
+
+    let mut s = String::new();
+    rows.push(run.time("sparse_array", "get_at_index", &index_queries, sparse_space, |i| {
+        sparse_array.get_at_index(i, &mut s) as u64
+    }));
+    rows.push(run.time("sparse_array", "num_elem_at", &index_queries, sparse_space, |i| sparse_array.num_elem_at(i)));
+    rows.push(run.time("dense_vec", "get_at_index", &index_queries, dense_space, |i| {
+        !dense[i as usize].is_empty() as u64
+    }));
+    if !rank_queries.is_empty() {
+        rows.push(run.time("sparse_array", "get_at_rank", &rank_queries, sparse_space, |r| {
+            sparse_array.get_at_rank(r, &mut s) as u64
+        }));
+        rows.push(run.time("sparse_array", "get_index_of", &rank_queries, sparse_space, |r| sparse_array.get_index_of(r)));
+    }
+}
+
+pub fn run_experiments(config: &Config) -> std::io::Result<Vec<ResultRow>> {
+    config.check()?;
+    let mut rows = Vec::new();
+    for &experiment in &config.experiments {
+        for &size in &config.sizes {
+            for &sparsity in &config.sparsities {
+                for &seed in &config.seeds {
+                    let run = Run { config, experiment, size, sparsity, seed };
+                    eprintln!("running {} size={} sparsity={} seed={}", experiment.name(), size, sparsity, seed);
+                    match experiment {
+                        Experiment::Rank => run_rank(&run, &mut rows),
+                        Experiment::Select => run_select(&run, &mut rows),
+                        Experiment::Sparse => run_sparse(&run, &mut rows),
+                    }
+                }
+            }
+        }
+    }
+    Ok(rows)
+}
+
+// The commit the results were measured on, so tables from different
+// commits can be told apart
+fn current_commit() -> String {
+    let output = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+pub fn write_csv<W: Write>(writer: &mut W, commit: &str, rows: &[ResultRow]) -> std::io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for row in rows {
+        writeln!(writer, "{}", row.to_csv(commit))?;
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write>(writer: &mut W, commit: &str, config: &Config, rows: &[ResultRow]) -> std::io::Result<()> {
+    writeln!(writer, "{{\"commit\":\"{}\",\"config\":{},\"results\":[", commit, config.to_json())?;
+    for (i, row) in rows.iter().enumerate() {
+        let separator = if i + 1 < rows.len() { "," } else { "" };
+        writeln!(writer, "{}{}", row.to_json(), separator)?;
+    }
+    writeln!(writer, "]}}")
+}
+
+pub fn run(args: &[String]) -> std::io::Result<()> {
+    match args.first().map(|s| s.as_str()) {
+        Some("run") => run_command(&args[1..]),
+        Some("compare") => compare_command(&args[1..]),
+        Some("plot") => plots::run(&args[1..]),
+        _ => Err(invalid_input(USAGE.to_string())),
+    }
+}
+
+fn run_command(args: &[String]) -> std::io::Result<()> {
+    // The config file goes first so that the flags override it
+    let mut config = match args.iter().position(|arg| arg == "--config") {
+        Some(i) => Config::load(args.get(i + 1).ok_or_else(|| invalid_input("--config needs a file".to_string()))?)?,
+        None => Config::default(),
+    };
+    let mut csv_file = None;
+    let mut json_file = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let Some(key) = arg.strip_prefix("--") else {
+            return Err(invalid_input(format!("unexpected argument {}\n\n{}", arg, USAGE)));
+        };
+        let value = iter.next().ok_or_else(|| invalid_input(format!("{} needs a value", arg)))?;
+        match key {
+            "config" => {}
+            "csv" => csv_file = Some(value),
+            "json" => json_file = Some(value),
+            _ => config.set(key, value)?,
+        }
+    }
+
+    let rows = run_experiments(&config)?;
+    let commit = current_commit();
+    if let Some(file_name) = json_file {
+        let mut file = BufWriter::new(File::create(file_name)?);
+        write_json(&mut file, &commit, &config, &rows)?;
+        file.flush()?;
+    }
+    match csv_file {
+        Some(file_name) => {
+            let mut file = BufWriter::new(File::create(file_name)?);
+            write_csv(&mut file, &commit, &rows)?;
+            file.flush()
+        }
+        // Without any output file the table goes to stdout
+        None if json_file.is_none() => write_csv(&mut std::io::stdout().lock(), &commit, &rows),
+        None => Ok(()),
+    }
+}
+
+// The rows of a results CSV keyed by everything that identifies a
+// measurement, with the median and the commit
+fn read_medians(file_name: &str) -> std::io::Result<Vec<(String, f64, String)>> {
+    let contents = fs::read_to_string(file_name)?;
+    let mut lines = contents.lines();
+    let header: Vec<&str> = lines.next().unwrap_or("").split(',').collect();
+    let column = |name: &str| {
+        header.iter().position(|&h| h == name)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} has no {} column", file_name, name)))
+    };
+    let key_columns = ["experiment", "structure", "operation", "size", "sparsity", "seed", "operations"]
+        .iter()
+        .map(|name| column(name))
+        .collect::<std::io::Result<Vec<usize>>>()?;
+    let median_column = column("median_ns")?;
+    let commit_column = column("commit")?;
+
+    let mut rows = Vec::new();
+    for (line_number, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != header.len() {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "{}: line {}: expected {} fields", file_name, line_number + 2, header.len()
+            )));
+        }
+        let key = key_columns.iter().map(|&c| fields[c]).collect::<Vec<&str>>().join(" ");
+        let median = fields[median_column].parse().map_err(|_| Error::new(ErrorKind::InvalidData, format!(
+            "{}: line {}: bad median {}", file_name, line_number + 2, fields[median_column]
+        )))?;
+        rows.push((key, median, fields[commit_column].to_string()));
+    }
+    Ok(rows)
+}
+
+// Prints the new median over the baseline median for every measurement
+// in both tables and marks the ones slower than the threshold
+fn compare_command(args: &[String]) -> std::io::Result<()> {
+    let (files, threshold) = match args {
+        [baseline, new] => ([baseline, new], 1.1),
+        [baseline, new, flag, value] if flag == "--threshold" => ([baseline, new], parse_value(value)?),
+        _ => return Err(invalid_input(USAGE.to_string())),
+    };
+    let baseline = read_medians(files[0])?;
+    let new = read_medians(files[1])?;
+    let commit_of = |rows: &[(String, f64, String)]| rows.first().map_or("unknown".to_string(), |row| row.2.clone());
+    println!("baseline {} ({}) against {} ({})", files[0], commit_of(&baseline), files[1], commit_of(&new));
+
+    let mut num_compared = 0;
+    let mut num_slower = 0;
+    for (key, new_median, _) in &new {
+        let Some((_, old_median, _)) = baseline.iter().find(|(old_key, _, _)| old_key == key) else {
+            continue;
+        };
+        num_compared += 1;
+        let ratio = new_median / old_median;
+        let mark = if ratio > threshold {
+            num_slower += 1;
+            "  SLOWER"
+        } else if ratio < 1.0 / threshold {
+            "  faster"
+        } else {
+            ""
+        };
+        println!("{:<70} {:>10.2} ns {:>10.2} ns {:>6.2}x{}", key, old_median, new_median, ratio, mark);
+    }
+    println!("{} measurements compared, {} slower than {}x", num_compared, num_slower, threshold);
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use crate::text_formats::line_error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+// A streaming reader for FASTA and FASTQ files, plain text only. The
+// format is taken from the first header, > for FASTA and @ for FASTQ, and
+// every record of the file has to be in it.
+//
+// Sequences can be split over any number of lines in both formats, and
+// in FASTQ the quality lines are read until they are as long as the
+// sequence. Blank lines between records are skipped, and so are FASTA
+// lines starting with ;. Errors name the line they were found on.
+//
+// read_record_with hands the bases over a line at a time, so a record
+// never has to be held in memory. The Iterator collects every record.
+pub struct FastxReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    line_number: usize,
+    format: Option<FastxFormat>,
+    // The header of the next FASTA record and its line number, read while
+    // looking for the end of the one before
+    next_header: Option<(usize, Vec<u8>)>,
+    failed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FastxFormat {
+    Fasta,
+    Fastq,
+}
+
+// The header line of a record, split at the first whitespace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordHeader {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub description: String,
+    pub sequence: Vec<u8>,
+    // The quality string of a FASTQ record, None for FASTA
+    pub quality: Option<Vec<u8>>,
+}
+
+impl FastxReader<BufReader<File>> {
+    pub fn open(fname: &str) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(fname)?)))
+    }
+}
+
+impl<R: BufRead> FastxReader<R> {
+    pub fn new(reader: R) -> Self {
+        FastxReader {
+            reader,
+            line: Vec::new(),
+            line_number: 0,
+            format: None,
+            next_header: None,
+            failed: false,
+        }
+    }
+
+    // None until the first header is read
+    pub fn format(&self) -> Option<FastxFormat> {
+        self.format
+    }
+
+    // Reads the next record, calling on_bases with every line of its
+    // sequence. The quality of a FASTQ record is checked and dropped.
+    // Returns None once there are no records left.
+    pub fn read_record_with<F: FnMut(&[u8])>(&mut self, on_bases: F) -> io::Result<Option<RecordHeader>> {
+        self.read_record(on_bases, None)
+    }
+
+    fn read_record<F: FnMut(&[u8])>(&mut self, mut on_bases: F, mut quality: Option<&mut Vec<u8>>) -> io::Result<Option<RecordHeader>> {
+        let (header_line, header) = match self.next_header.take() {
+            Some(header) => header,
+            None => loop {
+                if !self.read_line()? {
+                    return Ok(None);
+                }
+                if !self.line.is_empty() {
+                    break (self.line_number, std::mem::take(&mut self.line));
+                }
+            },
+        };
+
+        let format = match header[0] {
+            b'>' => FastxFormat::Fasta,
+            b'@' => FastxFormat::Fastq,
+            _ => return Err(line_error(header_line, "expected a header starting with > or @")),
+        };
+        if self.format.is_some_and(|expected| expected != format) {
+            return Err(line_error(header_line, format!("a {:?} record in a {:?} file", format, self.format.unwrap())));
+        }
+        self.format = Some(format);
+        let header = parse_header(header_line, &header[1..])?;
+
+        if format == FastxFormat::Fasta {
+            while self.read_line()? {
+                if self.line.starts_with(b">") {
+                    self.next_header = Some((self.line_number, std::mem::take(&mut self.line)));
+                    break;
+                }
+                if self.line.is_empty() || self.line.starts_with(b";") {
+                    continue;
+                }
+                self.check_bases()?;
+                on_bases(&self.line);
+            }
+            return Ok(Some(header));
+        }
+
+        let mut length = 0;
+        loop {
+            if !self.read_line()? {
+                return Err(line_error(header_line, format!("record {} has no + line", header.name)));
+            }
+            if self.line.starts_with(b"+") {
+                break;
+            }
+            self.check_bases()?;
+            on_bases(&self.line);
+            length += self.line.len();
+        }
+        // Quality lines can start with @ or +, so they are told apart from
+        // the next header by their length
+        let mut quality_length = 0;
+        while quality_length < length {
+            if !self.read_line()? {
+                return Err(line_error(header_line, format!(
+                    "record {} has {} bases but only {} quality values", header.name, length, quality_length
+                )));
+            }
+            if let Some(c) = self.line.iter().find(|&&c| !(b'!'..=b'~').contains(&c)) {
+                return Err(line_error(self.line_number, format!("{:?} is not a quality value", *c as char)));
+            }
+            quality_length += self.line.len();
+            if let Some(quality) = quality.as_mut() {
+                quality.extend_from_slice(&self.line);
+            }
+        }
+        if quality_length > length {
+            return Err(line_error(self.line_number, format!(
+                "record {} has {} bases but {} quality values", header.name, length, quality_length
+            )));
+        }
+        Ok(Some(header))
+    }
+
+    // Reads the next line into self.line without its line break, false at
+    // the end of the input
+    fn read_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        if self.line.ends_with(b"\n") {
+            self.line.pop();
+        }
+        if self.line.ends_with(b"\r") {
+            self.line.pop();
+        }
+        Ok(true)
+    }
+
+    // Letters are bases, IUPAC codes included, and - and * are gaps
+    fn check_bases(&self) -> io::Result<()> {
+        match self.line.iter().find(|&&c| !(c.is_ascii_alphabetic() || c == b'-' || c == b'*')) {
+            Some(&c) => Err(line_error(self.line_number, format!("{:?} is not a base", c as char))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_header(line_number: usize, header: &[u8]) -> io::Result<RecordHeader> {
+    let header = std::str::from_utf8(header).map_err(|_| line_error(line_number, "the header is not UTF-8"))?;
+    let (name, description) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+    Ok(RecordHeader {
+        name: name.to_string(),
+        description: description.trim().to_string(),
+    })
+}
+
+impl<R: BufRead> Iterator for FastxReader<R> {
+    type Item = io::Result<Record>;
+
+    // Stops after the first error
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        if self.failed {
+            return None;
+        }
+        let mut sequence = Vec::new();
+        let mut quality = Vec::new();
+        let record = self.read_record(|bases| sequence.extend_from_slice(bases), Some(&mut quality));
+        let header = match record {
+            Ok(header) => header?,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+        let quality = (self.format == Some(FastxFormat::Fastq)).then_some(quality);
+        Some(Ok(Record {
+            name: header.name,
+            description: header.description,
+            sequence,
+            quality,
+        }))
+    }
+}
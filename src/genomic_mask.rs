@@ -0,0 +1,350 @@
+use crate::bit_vector::BitVector;
+use crate::broadword::low_mask;
+use crate::rank_select::RankSelect;
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
+use crate::text_formats::{line_error, DataLines};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+
+// The covered bases of one contig, a bit per base with its rank and select
+// tables. Coordinates are 0 based like in BED files, and an interval
+// [start, end) covers start up to but not including end.
+//
+// The "compressed" coordinate of a covered base is the number of covered
+// bases before it, its position in the contig with everything uncovered
+// cut out.
+pub struct ContigMask {
+    name: String,
+    bases: RankSelect,
+}
+
+// A mask of covered bases over a set of contigs, read from BED intervals.
+// Overlapping and touching intervals are merged, since they only set the
+// same bits, and come back out as one interval.
+pub struct GenomicMask {
+    contigs: Vec<ContigMask>,
+    by_name: HashMap<String, usize>,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<GenomicMask>;
+
+impl ContigMask {
+    fn new(name: String, mut data: Vec<u64>, length: u64) -> Self {
+        data.resize(length as usize / 64 + 1, 0);
+        let bit_vector = BitVector { data, size: length as usize };
+        ContigMask {
+            name,
+            bases: RankSelect::new(bit_vector),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn length(&self) -> u64 {
+        self.bases.size() as u64
+    }
+
+    pub fn covered_bases(&self) -> u64 {
+        self.bases.num_ones()
+    }
+
+    // false past the end of the contig
+    pub fn is_covered(&self, pos: u64) -> bool {
+        pos < self.length() && self.bases.get(pos as usize)
+    }
+
+    // Number of covered bases in [start, end), the part past the end of
+    // the contig counts as uncovered
+    pub fn coverage(&self, start: u64, end: u64) -> u64 {
+        let end = end.min(self.length());
+        let start = start.min(end);
+        self.bases.rank1(end as usize) - self.bases.rank1(start as usize)
+    }
+
+    // The position of the k-th covered base, 0 indexed, None if fewer
+    // than k + 1 bases are covered
+    pub fn kth_covered(&self, k: u64) -> Option<u64> {
+        if k < self.covered_bases() {
+            return Some(self.bases.select1(k + 1) - 1);
+        }
+        None
+    }
+
+    // The compressed coordinate of pos, None if pos is not covered
+    pub fn to_compressed(&self, pos: u64) -> Option<u64> {
+        if self.is_covered(pos) {
+            return Some(self.bases.rank1(pos as usize));
+        }
+        None
+    }
+
+    // The position of a compressed coordinate, the inverse of to_compressed
+    pub fn to_genomic(&self, compressed: u64) -> Option<u64> {
+        self.kth_covered(compressed)
+    }
+
+    // The covered bases as maximal [start, end) intervals in order
+    pub fn intervals(&self) -> Intervals<'_> {
+        Intervals {
+            bit_vector: self.bases.bit_vector(),
+            pos: 0,
+        }
+    }
+
+    fn words(&self) -> &[u64] {
+        &self.bases.bit_vector().data
+    }
+}
+
+// The runs of ones of a bit vector as [start, end) intervals
+pub struct Intervals<'a> {
+    bit_vector: &'a BitVector,
+    pos: u64,
+}
+
+impl Iterator for Intervals<'_> {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<(u64, u64)> {
+        let start = next_bit(self.bit_vector, self.pos, true);
+        if start == self.bit_vector.size as u64 {
+            self.pos = start;
+            return None;
+        }
+        let end = next_bit(self.bit_vector, start, false);
+        self.pos = end;
+        Some((start, end))
+    }
+}
+
+// The first position at or after from whose bit is value, the size if
+// there is none
+fn next_bit(bit_vector: &BitVector, from: u64, value: bool) -> u64 {
+    let size = bit_vector.size as u64;
+    if from >= size {
+        return size;
+    }
+    let flip = if value { 0 } else { u64::MAX };
+    let mut w = from as usize / 64;
+    let mut word = (bit_vector.data[w] ^ flip) & !low_mask(from as usize % 64);
+    loop {
+        if word != 0 {
+            return (w as u64 * 64 + word.trailing_zeros() as u64).min(size);
+        }
+        w += 1;
+        if w == bit_vector.data.len() {
+            return size;
+        }
+        word = bit_vector.data[w] ^ flip;
+    }
+}
+
+// Sets the bits of [start, end) in words, growing them to fit
+fn set_range(words: &mut Vec<u64>, start: u64, end: u64) {
+    if start >= end {
+        return;
+    }
+    let (start, end) = (start as usize, end as usize);
+    if words.len() <= end / 64 {
+        words.resize(end / 64 + 1, 0);
+    }
+    let (first, last) = (start / 64, (end - 1) / 64);
+    let low = !low_mask(start % 64);
+    let high = low_mask((end - 1) % 64 + 1);
+    if first == last {
+        words[first] |= low & high;
+        return;
+    }
+    words[first] |= low;
+    for word in &mut words[first + 1..last] {
+        *word = u64::MAX;
+    }
+    words[last] |= high;
+}
+
+// A contig being read from a BED file
+struct ContigBuilder {
+    words: Vec<u64>,
+    length: u64,
+}
+
+impl GenomicMask {
+    // Reads a BED file. Every contig is as long as the end of its last
+    // interval, in the order the contigs first show up.
+    pub fn read_bed<R: BufRead>(reader: R) -> io::Result<Self> {
+        Self::read_bed_into(reader, Vec::new(), false)
+    }
+
+    // Reads a BED file for the contigs and lengths of a genome, like those
+    // read_genome_sizes gives. Contigs without intervals are kept, empty,
+    // and an interval on a contig that is not listed or past the end of
+    // its contig is an error.
+    pub fn read_bed_with_sizes<R: BufRead>(reader: R, sizes: &[(String, u64)]) -> io::Result<Self> {
+        let contigs = sizes.iter().map(|(name, length)| (name.clone(), ContigBuilder { words: Vec::new(), length: *length })).collect();
+        Self::read_bed_into(reader, contigs, true)
+    }
+
+    fn read_bed_into<R: BufRead>(reader: R, mut contigs: Vec<(String, ContigBuilder)>, fixed: bool) -> io::Result<Self> {
+        let mut by_name: HashMap<String, usize> = HashMap::new();
+        for (i, (name, _)) in contigs.iter().enumerate() {
+            if by_name.insert(name.clone(), i).is_some() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("contig {} is listed twice", name)));
+            }
+        }
+
+        for line in DataLines::new(reader, '#', 0) {
+            let (line_number, line) = line?;
+            let (name, start, end) = match parse_bed_line(line_number, &line)? {
+                Some(interval) => interval,
+                None => continue,
+            };
+            let i = match by_name.get(name) {
+                Some(&i) => i,
+                None if fixed => return Err(line_error(line_number, format!("contig {} is not in the genome", name))),
+                None => {
+                    by_name.insert(name.to_string(), contigs.len());
+                    contigs.push((name.to_string(), ContigBuilder { words: Vec::new(), length: 0 }));
+                    contigs.len() - 1
+                }
+            };
+            let contig = &mut contigs[i].1;
+            if fixed && end > contig.length {
+                return Err(line_error(line_number, format!("{} is past the end of {}, which is {} long", end, name, contig.length)));
+            }
+            contig.length = contig.length.max(end);
+            set_range(&mut contig.words, start, end);
+        }
+
+        let contigs = contigs.into_iter().map(|(name, contig)| ContigMask::new(name, contig.words, contig.length)).collect();
+        Ok(GenomicMask { contigs, by_name })
+    }
+
+    pub fn load_bed(fname: &str) -> io::Result<Self> {
+        Self::read_bed(BufReader::new(File::open(fname)?))
+    }
+
+    // Reads a chrom.sizes style file, name<TAB>length per line
+    pub fn read_genome_sizes<R: BufRead>(reader: R) -> io::Result<Vec<(String, u64)>> {
+        let mut sizes = Vec::new();
+        for line in DataLines::new(reader, '#', 0) {
+            let (line_number, line) = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let [name, length, ..] = words[..] else {
+                return Err(line_error(line_number, "expected name<TAB>length"));
+            };
+            let length = length.parse().map_err(|_| line_error(line_number, format!("{} is not a length", length)))?;
+            sizes.push((name.to_string(), length));
+        }
+        Ok(sizes)
+    }
+
+    pub fn save_bed(&self, fname: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(fname)?);
+        self.write_bed(&mut file)?;
+        file.flush()
+    }
+
+    // Writes the covered bases as merged, sorted intervals, contig by contig
+    pub fn write_bed<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for contig in &self.contigs {
+            for (start, end) in contig.intervals() {
+                writeln!(writer, "{}\t{}\t{}", contig.name, start, end)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contig(&self, name: &str) -> Option<&ContigMask> {
+        self.by_name.get(name).map(|&i| &self.contigs[i])
+    }
+
+    pub fn contigs(&self) -> impl Iterator<Item = &ContigMask> + '_ {
+        self.contigs.iter()
+    }
+
+    pub fn num_contigs(&self) -> usize {
+        self.contigs.len()
+    }
+
+    // Covered bases over all contigs
+    pub fn covered_bases(&self) -> u64 {
+        self.contigs.iter().map(|contig| contig.covered_bases()).sum()
+    }
+
+    // Covered bases of [start, end) on a contig, 0 for a contig not in the mask
+    pub fn coverage(&self, name: &str, start: u64, end: u64) -> u64 {
+        self.contig(name).map_or(0, |contig| contig.coverage(start, end))
+    }
+
+    // Bases covered by either mask
+    pub fn union(&self, other: &GenomicMask) -> GenomicMask {
+        self.combine(other, |a, b| a | b)
+    }
+
+    // Bases covered by both masks
+    pub fn intersection(&self, other: &GenomicMask) -> GenomicMask {
+        self.combine(other, |a, b| a & b)
+    }
+
+    // Bases covered by this mask and not by the other
+    pub fn subtract(&self, other: &GenomicMask) -> GenomicMask {
+        self.combine(other, |a, b| a & !b)
+    }
+
+    // Combines the masks a word at a time. The result has the contigs of
+    // this mask followed by the ones only the other has, each as long as
+    // the longer of the two, with missing bases uncovered. f has to keep
+    // two zeros zero so nothing is set past the end.
+    fn combine<F: Fn(u64, u64) -> u64>(&self, other: &GenomicMask, f: F) -> GenomicMask {
+        let empty = ContigMask::new(String::new(), Vec::new(), 0);
+        let names = self.contigs.iter().chain(other.contigs.iter().filter(|contig| self.contig(&contig.name).is_none()));
+
+        let mut contigs = Vec::new();
+        let mut by_name = HashMap::new();
+        for name in names.map(|contig| &contig.name) {
+            let ours = self.contig(name).unwrap_or(&empty);
+            let theirs = other.contig(name).unwrap_or(&empty);
+            let length = ours.length().max(theirs.length());
+            let word = |words: &[u64], w: usize| words.get(w).copied().unwrap_or(0);
+            let words = (0..length as usize / 64 + 1).map(|w| f(word(ours.words(), w), word(theirs.words(), w))).collect();
+            by_name.insert(name.clone(), contigs.len());
+            contigs.push(ContigMask::new(name.clone(), words, length));
+        }
+        GenomicMask { contigs, by_name }
+    }
+}
+
+// The contig, start and end of a BED line, None for track and browser lines
+fn parse_bed_line(line_number: usize, line: &str) -> io::Result<Option<(&str, u64, u64)>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if matches!(words[0], "track" | "browser") {
+        return Ok(None);
+    }
+    let [name, start, end, ..] = words[..] else {
+        return Err(line_error(line_number, "expected a contig, a start and an end"));
+    };
+    let parse = |word: &str| word.parse::<u64>().map_err(|_| line_error(line_number, format!("{} is not a coordinate", word)));
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(line_error(line_number, format!("the interval starts at {} after it ends at {}", start, end)));
+    }
+    Ok(Some((name, start, end)))
+}
+
+impl SpaceUsage for GenomicMask {
+    fn space_report(&self) -> SpaceNode {
+        let names_bits = self.contigs.iter().map(|contig| contig.name.capacity() as u64 * 8).sum::<u64>()
+            + self.by_name.capacity() as u64 * inline_bits::<(String, usize)>()
+            + self.by_name.keys().map(|name| name.capacity() as u64 * 8).sum::<u64>();
+        let mut children = vec![
+            SpaceNode::leaf("header", inline_bits::<GenomicMask>()
+                + vec_bits(&self.contigs) - self.contigs.len() as u64 * inline_bits::<RankSelect>()),
+            SpaceNode::leaf("names", names_bits),
+        ];
+        children.extend(self.contigs.iter().map(|contig| contig.bases.space_report().renamed(&contig.name)));
+        SpaceNode::with_children("GenomicMask", children)
+    }
+}
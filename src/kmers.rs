@@ -0,0 +1,343 @@
+use crate::broadword::low_mask;
+use crate::elias_fano::EliasFano;
+use crate::fastx::FastxReader;
+use crate::packed_ints::PackedInts;
+use crate::packed_sequences::base_code;
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+// k-mers are encoded 2 bits a base, A C G T as 0 1 2 3, with the first
+// base in the highest bits so that codes sort like the k-mers do. A k-mer
+// and its reverse complement are the same canonical k-mer, the smaller of
+// the two codes. k goes up to 32, which fills all 64 bits.
+//
+// A k-mer with anything other than A, C, G or T in it (upper or lower
+// case) has no code and is skipped when reading sequences.
+pub const MAX_K: usize = 32;
+
+// How many k-mers the builders collect before sorting them into counts
+const BUFFERED_KMERS: usize = 1 << 22;
+
+fn check_k(k: usize) {
+    assert!((1..=MAX_K).contains(&k), "k has to be between 1 and {}, not {}", MAX_K, k);
+}
+
+// The code of a k-mer, None if it has a base other than A, C, G or T
+pub fn encode_kmer(kmer: &[u8]) -> Option<u64> {
+    check_k(kmer.len());
+    kmer.iter().try_fold(0, |code, &base| Some((code << 2) | base_code(base)?))
+}
+
+pub fn decode_kmer(code: u64, k: usize) -> String {
+    check_k(k);
+    (0..k).rev().map(|i| ['A', 'C', 'G', 'T'][(code >> (2 * i) & 3) as usize]).collect()
+}
+
+pub fn reverse_complement(code: u64, k: usize) -> u64 {
+    check_k(k);
+    // Complementing is 3 - base, and reversing swaps the 2 bit groups
+    let mut code = !code;
+    code = (code >> 2 & 0x3333_3333_3333_3333) | ((code & 0x3333_3333_3333_3333) << 2);
+    code = (code >> 4 & 0x0f0f_0f0f_0f0f_0f0f) | ((code & 0x0f0f_0f0f_0f0f_0f0f) << 4);
+    code = code.swap_bytes();
+    code >> (64 - 2 * k)
+}
+
+pub fn canonical(code: u64, k: usize) -> u64 {
+    code.min(reverse_complement(code, k))
+}
+
+// Rolls the forward and reverse complement codes along a sequence, which
+// can come in pieces, like the lines of a FASTA record
+struct KmerScanner {
+    k: usize,
+    forward: u64,
+    reverse: u64,
+    // Number of bases since the last one that has no code, up to k
+    valid: usize,
+}
+
+impl KmerScanner {
+    fn new(k: usize) -> Self {
+        check_k(k);
+        KmerScanner { k, forward: 0, reverse: 0, valid: 0 }
+    }
+
+    // Starts over, for the next record
+    fn reset(&mut self) {
+        self.valid = 0;
+    }
+
+    // Calls f with the canonical code of every k-mer that ends in bases
+    fn scan<F: FnMut(u64)>(&mut self, bases: &[u8], mut f: F) {
+        let mask = low_mask(2 * self.k);
+        let top = 2 * (self.k - 1);
+        for &base in bases {
+            let Some(code) = base_code(base) else {
+                self.valid = 0;
+                continue;
+            };
+            self.forward = ((self.forward << 2) | code) & mask;
+            self.reverse = (self.reverse >> 2) | ((3 - code) << top);
+            self.valid = (self.valid + 1).min(self.k);
+            if self.valid == self.k {
+                f(self.forward.min(self.reverse));
+            }
+        }
+    }
+}
+
+// Collects k-mer codes into sorted (code, count) pairs, sorting a buffer
+// at a time so that memory goes with the distinct k-mers rather than
+// every occurrence
+struct KmerCounter {
+    buffer: Vec<u64>,
+    counts: Vec<(u64, u64)>,
+}
+
+impl KmerCounter {
+    fn new() -> Self {
+        KmerCounter { buffer: Vec::new(), counts: Vec::new() }
+    }
+
+    fn add(&mut self, code: u64) {
+        self.buffer.push(code);
+        if self.buffer.len() == BUFFERED_KMERS {
+            self.flush();
+        }
+    }
+
+    // Sorts the buffer into runs and merges them into the counts
+    fn flush(&mut self) {
+        self.buffer.sort_unstable();
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        for &code in &self.buffer {
+            match runs.last_mut() {
+                Some((last, count)) if *last == code => *count += 1,
+                _ => runs.push((code, 1)),
+            }
+        }
+        self.buffer.clear();
+
+        let mut merged = Vec::with_capacity(self.counts.len() + runs.len());
+        let mut runs = runs.into_iter().peekable();
+        for (code, count) in self.counts.drain(..) {
+            while let Some(run) = runs.next_if(|&(run_code, _)| run_code < code) {
+                merged.push(run);
+            }
+            match runs.next_if(|&(run_code, _)| run_code == code) {
+                Some((_, run_count)) => merged.push((code, count + run_count)),
+                None => merged.push((code, count)),
+            }
+        }
+        merged.extend(runs);
+        self.counts = merged;
+    }
+
+    fn finish(mut self) -> Vec<(u64, u64)> {
+        self.flush();
+        self.counts
+    }
+}
+
+fn count_codes<I: IntoIterator<Item = u64>>(k: usize, codes: I) -> Vec<(u64, u64)> {
+    check_k(k);
+    let mut counter = KmerCounter::new();
+    for code in codes {
+        assert!(code <= low_mask(2 * k), "{} is not the code of a {}-mer", code, k);
+        counter.add(canonical(code, k));
+    }
+    counter.finish()
+}
+
+fn count_sequences<'a, I: IntoIterator<Item = &'a [u8]>>(k: usize, sequences: I) -> Vec<(u64, u64)> {
+    let mut scanner = KmerScanner::new(k);
+    let mut counter = KmerCounter::new();
+    for sequence in sequences {
+        scanner.reset();
+        scanner.scan(sequence, |code| counter.add(code));
+    }
+    counter.finish()
+}
+
+// Counts the canonical k-mers of every record of a FASTA or FASTQ file,
+// reading it a line at a time. k-mers don't span records.
+fn count_fastx<R: BufRead>(reader: R, k: usize) -> io::Result<Vec<(u64, u64)>> {
+    let mut reader = FastxReader::new(reader);
+    let mut scanner = KmerScanner::new(k);
+    let mut counter = KmerCounter::new();
+    while reader.read_record_with(|line| scanner.scan(line, |code| counter.add(code)))?.is_some() {
+        scanner.reset();
+    }
+    Ok(counter.finish())
+}
+
+// The distinct canonical k-mers of a set of sequences. Every k-mer gets
+// an id, its rank among them in code order.
+pub struct KmerSet {
+    k: usize,
+    kmers: EliasFano,
+}
+
+impl KmerSet {
+    // Builds the set from k-mer codes in any order, canonical or not
+    pub fn from_codes<I: IntoIterator<Item = u64>>(k: usize, codes: I) -> Self {
+        Self::from_counts(k, &count_codes(k, codes))
+    }
+
+    // The canonical k-mers of the sequences
+    pub fn from_sequences<'a, I: IntoIterator<Item = &'a [u8]>>(k: usize, sequences: I) -> Self {
+        Self::from_counts(k, &count_sequences(k, sequences))
+    }
+
+    // The canonical k-mers of every record of a FASTA or FASTQ file,
+    // streamed a line at a time
+    pub fn from_fastx<R: BufRead>(reader: R, k: usize) -> io::Result<Self> {
+        Ok(Self::from_counts(k, &count_fastx(reader, k)?))
+    }
+
+    pub fn load_fastx(fname: &str, k: usize) -> io::Result<Self> {
+        Self::from_fastx(BufReader::new(File::open(fname)?), k)
+    }
+
+    fn from_counts(k: usize, counts: &[(u64, u64)]) -> Self {
+        let codes: Vec<u64> = counts.iter().map(|&(code, _)| code).collect();
+        KmerSet { k, kmers: EliasFano::from_sorted(&codes) }
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    // Number of distinct canonical k-mers
+    pub fn len(&self) -> u64 {
+        self.kmers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kmers.is_empty()
+    }
+
+    // Whether the k-mer or its reverse complement is in the set. Has to
+    // be k bases long, and is never in the set if it has other bases.
+    pub fn contains(&self, kmer: &[u8]) -> bool {
+        self.id(kmer).is_some()
+    }
+
+    // The id of the k-mer, the same for its reverse complement
+    pub fn id(&self, kmer: &[u8]) -> Option<u64> {
+        assert_eq!(kmer.len(), self.k, "expected a {}-mer", self.k);
+        self.id_of_code(encode_kmer(kmer)?)
+    }
+
+    pub fn id_of_code(&self, code: u64) -> Option<u64> {
+        self.kmers.index_of(canonical(code, self.k))
+    }
+
+    // Number of canonical k-mers in the set with a code below code's
+    // canonical code
+    pub fn rank_of_code(&self, code: u64) -> u64 {
+        self.kmers.rank(canonical(code, self.k))
+    }
+
+    // The canonical code of the k-mer with the given id
+    pub fn code(&self, id: u64) -> u64 {
+        self.kmers.get(id)
+    }
+
+    // The canonical codes in id order
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.kmers.iter()
+    }
+}
+
+// A KmerSet with the number of times every k-mer showed up, counting both
+// strands together. The counts are packed as narrow as the largest allows.
+pub struct KmerMap {
+    set: KmerSet,
+    counts: PackedInts,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<KmerMap>;
+
+impl KmerMap {
+    // Counts k-mer codes given in any order, canonical or not
+    pub fn from_codes<I: IntoIterator<Item = u64>>(k: usize, codes: I) -> Self {
+        Self::from_counts(k, count_codes(k, codes))
+    }
+
+    // Counts the canonical k-mers of the sequences
+    pub fn from_sequences<'a, I: IntoIterator<Item = &'a [u8]>>(k: usize, sequences: I) -> Self {
+        Self::from_counts(k, count_sequences(k, sequences))
+    }
+
+    // Counts the canonical k-mers of every record of a FASTA or FASTQ file
+    pub fn from_fastx<R: BufRead>(reader: R, k: usize) -> io::Result<Self> {
+        Ok(Self::from_counts(k, count_fastx(reader, k)?))
+    }
+
+    pub fn load_fastx(fname: &str, k: usize) -> io::Result<Self> {
+        Self::from_fastx(BufReader::new(File::open(fname)?), k)
+    }
+
+    fn from_counts(k: usize, counts: Vec<(u64, u64)>) -> Self {
+        let set = KmerSet::from_counts(k, &counts);
+        let counts: Vec<u64> = counts.into_iter().map(|(_, count)| count).collect();
+        KmerMap { set, counts: PackedInts::from_values(&counts) }
+    }
+
+    pub fn set(&self) -> &KmerSet {
+        &self.set
+    }
+
+    pub fn k(&self) -> usize {
+        self.set.k()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.set.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    // How many times the k-mer or its reverse complement showed up, 0 if
+    // never
+    pub fn count(&self, kmer: &[u8]) -> u64 {
+        self.set.id(kmer).map_or(0, |id| self.count_of_id(id))
+    }
+
+    pub fn count_of_code(&self, code: u64) -> u64 {
+        self.set.id_of_code(code).map_or(0, |id| self.count_of_id(id))
+    }
+
+    pub fn count_of_id(&self, id: u64) -> u64 {
+        self.counts.get(id as usize)
+    }
+
+    // The (canonical code, count) pairs in id order
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.set.iter().zip(self.counts.iter())
+    }
+}
+
+impl SpaceUsage for KmerSet {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("KmerSet", vec![
+            SpaceNode::leaf("header", inline_bits::<KmerSet>() - inline_bits::<EliasFano>()),
+            self.kmers.space_report().renamed("kmers"),
+        ])
+    }
+}
+
+impl SpaceUsage for KmerMap {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("KmerMap", vec![
+            SpaceNode::leaf("header", inline_bits::<KmerMap>() - inline_bits::<KmerSet>() - inline_bits::<PackedInts>()),
+            self.set.space_report().renamed("set"),
+            self.counts.space_report().renamed("counts"),
+        ])
+    }
+}
@@ -0,0 +1,24 @@
+pub mod batch;
+pub mod bit_vector;
+pub mod broadword;
+pub mod rank_support;
+pub mod rank_select;
+pub mod utils;
+pub mod select_support;
+pub mod position_index;
+pub mod sparse_array;
+pub mod packed_ints;
+pub mod string_arena;
+pub mod sparse_str_array;
+pub mod sparse_dict_array;
+pub mod sparse_vector;
+pub mod sparse_matrix;
+pub mod text_formats;
+pub mod genomic_mask;
+pub mod fastx;
+pub mod packed_sequences;
+pub mod elias_fano;
+pub mod kmers;
+pub mod bloom_filter;
+pub mod dynamic_bit_vector;
+pub mod space_report;
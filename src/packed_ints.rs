@@ -0,0 +1,154 @@
+use crate::broadword::{self, low_mask};
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
+use std::cmp;
+use std::io::{Error, ErrorKind, Read, Write};
+
+// A sequence of unsigned integers that all take the same number of bits,
+// packed back to back into 64 bit words. Value i is the width bits
+// starting at bit i * width, in the same bit order as BitVector.
+#[derive(Clone)]
+pub struct PackedInts {
+    words: Vec<u64>,
+    width: usize,
+    len: usize,
+}
+
+// Number of bits needed to write max, 0 for 0
+pub fn bits_needed(max: u64) -> usize {
+    64 - max.leading_zeros() as usize
+}
+
+impl PackedInts {
+    // An empty sequence of values that fit in width bits. A width of 0
+    // only holds zeros and takes no space for them.
+    pub fn new(width: usize) -> Self {
+        assert!(width <= 64, "can't pack values of {} bits", width);
+        Self {
+            words: Vec::new(),
+            width,
+            len: 0,
+        }
+    }
+
+    // Packs the values with the smallest width that fits the largest one
+    pub fn from_values(values: &[u64]) -> Self {
+        let width = bits_needed(values.iter().copied().max().unwrap_or(0));
+        let mut packed = Self::new(width);
+        packed.words.reserve_exact(Self::words_for(values.len(), width));
+        for &value in values {
+            packed.push(value);
+        }
+        packed
+    }
+
+    fn words_for(len: usize, width: usize) -> usize {
+        (len * width).div_ceil(64)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, value: u64) {
+        assert!(bits_needed(value) <= self.width, "{} doesn't fit in {} bits", value, self.width);
+        let start = self.len * self.width;
+        self.len += 1;
+        self.words.resize(Self::words_for(self.len, self.width), 0);
+        if self.width == 0 {
+            return;
+        }
+        let offset = start % 64;
+        self.words[start / 64] |= value << offset;
+        if offset + self.width > 64 {
+            self.words[start / 64 + 1] |= value >> (64 - offset);
+        }
+    }
+
+    // Repacks the values into width bits, which has to fit all of them
+    pub fn widen(&mut self, width: usize) {
+        assert!(width >= self.width && width <= 64, "can't widen {} bit values to {} bits", self.width, width);
+        let mut widened = Self::new(width);
+        widened.words.reserve_exact(Self::words_for(self.len, width));
+        for value in self.iter() {
+            widened.push(value);
+        }
+        *self = widened;
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.words.shrink_to_fit();
+    }
+
+    // The packed words, which for 1 bit values are the words of a
+    // BitVector of len bits, short of its last word
+    pub(crate) fn into_words(self) -> Vec<u64> {
+        self.words
+    }
+
+    pub fn get(&self, i: usize) -> u64 {
+        assert!(i < self.len, "index {} out of bounds for {} values", i, self.len);
+        broadword::read_bits(&self.words, i * self.width, self.width)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+
+    // Writes the width and the number of values followed by the packed
+    // words, all in one write
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut bytes = Vec::with_capacity(16 + self.words.len() * 8);
+        bytes.extend_from_slice(&(self.width as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.len as u64).to_le_bytes());
+        for &word in &self.words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        writer.write_all(&bytes)
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        let width = u64::from_le_bytes(buf);
+        reader.read_exact(&mut buf)?;
+        let len = u64::from_le_bytes(buf);
+        if width > 64 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("packed values can't take {} bits", width)));
+        }
+        let (width, len) = (width as usize, len as usize);
+        let num_words = len.checked_mul(width).map(|bits| bits.div_ceil(64)).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("{} values of {} bits don't fit in memory", len, width))
+        })?;
+
+        // A corrupt length shouldn't allocate everything up front, read_exact fails first
+        let mut words = Vec::with_capacity(cmp::min(num_words, 1 << 20));
+        for _ in 0..num_words {
+            reader.read_exact(&mut buf)?;
+            words.push(u64::from_le_bytes(buf));
+        }
+
+        // Only the bits of the last value can be set in the last word
+        let used_bits = len * width % 64;
+        if used_bits != 0 && words[num_words - 1] & !low_mask(used_bits) != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "bits are set past the last packed value"));
+        }
+        Ok(Self { words, width, len })
+    }
+}
+
+impl SpaceUsage for PackedInts {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("PackedInts", vec![
+            SpaceNode::leaf("header", inline_bits::<PackedInts>()),
+            SpaceNode::leaf("words", vec_bits(&self.words)),
+        ])
+    }
+}
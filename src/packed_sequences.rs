@@ -0,0 +1,190 @@
+use crate::bit_vector::BitVector;
+use crate::fastx::FastxReader;
+use crate::packed_ints::PackedInts;
+use crate::rank_select::RankSelect;
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+use crate::string_arena::StringArena;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+// The records of a FASTA or FASTQ file, all the sequences back to back in
+// one text with 2 bits a base. Positions are in that text, record after
+// record.
+//
+// Only A, C, G and T fit in 2 bits. Any other base is stored as an A and
+// marked in the N mask, and comes back out as an N. Lower case bases are
+// read as upper case.
+//
+// The record ends are unary, a 0 for every base and a 1 closing every
+// record, like the rows of a SparseMatrix, so record r starts at
+// select1(r) - r and empty records take one bit.
+pub struct PackedSequences {
+    names: StringArena,
+    bases: PackedInts,
+    n_mask: RankSelect,
+    record_ends: RankSelect,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<PackedSequences>;
+
+// The 2 bit code of a base, A C G T as 0 1 2 3 in either case
+pub(crate) fn base_code(base: u8) -> Option<u64> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+// The bit vector of 1 bit packed values
+fn bit_vector_from_packed(packed: PackedInts) -> BitVector {
+    let size = packed.len();
+    let mut data = packed.into_words();
+    data.resize(size / 64 + 1, 0);
+    BitVector { data, size }
+}
+
+impl PackedSequences {
+    // Reads the records a line at a time, straight into the packed text
+    pub fn from_fastx<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut reader = FastxReader::new(reader);
+        let mut names = StringArena::new();
+        let mut bases = PackedInts::new(2);
+        let mut n_mask = PackedInts::new(1);
+        let mut record_ends = PackedInts::new(1);
+        while let Some(header) = reader.read_record_with(|line| {
+            for &base in line {
+                let code = base_code(base);
+                bases.push(code.unwrap_or(0));
+                n_mask.push(code.is_none() as u64);
+                record_ends.push(0);
+            }
+        })? {
+            names.push(&header.name);
+            record_ends.push(1);
+        }
+        names.shrink_to_fit();
+        bases.shrink_to_fit();
+
+        Ok(PackedSequences {
+            names,
+            bases,
+            n_mask: RankSelect::new(bit_vector_from_packed(n_mask)),
+            record_ends: RankSelect::new(bit_vector_from_packed(record_ends)),
+        })
+    }
+
+    pub fn load_fastx(fname: &str) -> io::Result<Self> {
+        Self::from_fastx(BufReader::new(File::open(fname)?))
+    }
+
+    pub fn num_records(&self) -> usize {
+        self.names.len()
+    }
+
+    // Number of bases over all the records
+    pub fn len(&self) -> u64 {
+        self.bases.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bases.is_empty()
+    }
+
+    pub fn name(&self, r: usize) -> &str {
+        self.names.get(r)
+    }
+
+    // The [start, end) positions of record r in the text
+    pub fn record_range(&self, r: usize) -> (u64, u64) {
+        assert!(r < self.num_records(), "record {} out of bounds for {} records", r, self.num_records());
+        let r = r as u64;
+        (self.record_ends.select1(r) - r, self.record_ends.select1(r + 1) - (r + 1))
+    }
+
+    pub fn record_len(&self, r: usize) -> u64 {
+        let (start, end) = self.record_range(r);
+        end - start
+    }
+
+    // The record holding the base at pos. Found with a binary search over
+    // the record starts, log(records) select1s.
+    pub fn record_of(&self, pos: u64) -> usize {
+        assert!(pos < self.len(), "position {} out of bounds for {} bases", pos, self.len());
+        // The last record starting at or before pos, which skips the empty
+        // records that start there too
+        let (mut low, mut high) = (0, self.num_records());
+        while high - low > 1 {
+            let mid = (low + high) / 2;
+            if self.record_range(mid).0 <= pos {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    // The base at pos, an N for anything that isn't A, C, G or T
+    pub fn base(&self, pos: u64) -> u8 {
+        if self.n_mask.get(pos as usize) {
+            return b'N';
+        }
+        BASES[self.bases.get(pos as usize) as usize]
+    }
+
+    // The bases of [start, end)
+    pub fn bases(&self, start: u64, end: u64) -> impl Iterator<Item = u8> + '_ {
+        assert!(start <= end && end <= self.len(), "[{}, {}) out of bounds for {} bases", start, end, self.len());
+        (start..end).map(|pos| self.base(pos))
+    }
+
+    pub fn record_sequence(&self, r: usize) -> Vec<u8> {
+        let (start, end) = self.record_range(r);
+        self.bases(start, end).collect()
+    }
+
+    // Number of bases in the N mask
+    pub fn num_n(&self) -> u64 {
+        self.n_mask.num_ones()
+    }
+
+    // Number of masked bases in [start, end)
+    pub fn n_in_range(&self, start: u64, end: u64) -> u64 {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        self.n_mask.rank1(end as usize) - self.n_mask.rank1(start as usize)
+    }
+
+    // The 2 bit codes of the text, A C G T as 0 1 2 3 and masked bases as 0
+    pub fn packed_bases(&self) -> &PackedInts {
+        &self.bases
+    }
+
+    // A 1 at every base that isn't A, C, G or T
+    pub fn n_mask(&self) -> &RankSelect {
+        &self.n_mask
+    }
+
+    // A 0 for every base and a 1 at the end of every record
+    pub fn record_ends(&self) -> &RankSelect {
+        &self.record_ends
+    }
+}
+
+impl SpaceUsage for PackedSequences {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("PackedSequences", vec![
+            SpaceNode::leaf("header", inline_bits::<PackedSequences>() - inline_bits::<StringArena>()
+                - inline_bits::<PackedInts>() - 2 * inline_bits::<RankSelect>()),
+            self.names.space_report().renamed("names"),
+            self.bases.space_report().renamed("bases"),
+            self.n_mask.space_report().renamed("n_mask"),
+            self.record_ends.space_report().renamed("record_ends"),
+        ])
+    }
+}
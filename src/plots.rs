@@ -0,0 +1,362 @@
+// Charts of the results tables written by `experiment run`.
+//
+// For every experiment it draws the time per operation against the size
+// (one chart per sparsity) and against the sparsity (one chart per size),
+// and the space per position against the size. Every structure and
+// operation is its own series. Points are the median over the seeds of
+// the median time and the error bars span the 5th to the 95th percentile
+// of all repetitions of all seeds.
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+pub const USAGE: &str = "\
+usage:
+  hw2_code experiment plot <results.csv> <output dir> [--format svg|png]";
+
+#[derive(Clone, Copy)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Svg => "svg",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+// The columns of one results row the charts need
+struct Measurement {
+    experiment: String,
+    structure: String,
+    operation: String,
+    size: u64,
+    sparsity: f64,
+    median_ns: f64,
+    p5_ns: f64,
+    p95_ns: f64,
+    space_bits: u64,
+}
+
+fn invalid_data(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn read_measurements(file_name: &str) -> std::io::Result<Vec<Measurement>> {
+    let contents = fs::read_to_string(file_name)?;
+    let mut lines = contents.lines();
+    let header: Vec<&str> = lines.next().unwrap_or("").split(',').collect();
+    let column = |name: &str| {
+        header.iter().position(|&h| h == name).ok_or_else(|| invalid_data(format!("{} has no {} column", file_name, name)))
+    };
+    let columns = [
+        "experiment", "structure", "operation", "size", "sparsity", "median_ns", "p5_ns", "p95_ns", "space_bits",
+    ]
+    .iter()
+    .map(|name| column(name))
+    .collect::<std::io::Result<Vec<usize>>>()?;
+
+    let mut measurements = Vec::new();
+    for (line_number, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != header.len() {
+            return Err(invalid_data(format!("{}: line {}: expected {} fields", file_name, line_number + 2, header.len())));
+        }
+        let field = |i: usize| fields[columns[i]];
+        let bad = |i: usize| invalid_data(format!("{}: line {}: can't parse {}", file_name, line_number + 2, field(i)));
+        measurements.push(Measurement {
+            experiment: field(0).to_string(),
+            structure: field(1).to_string(),
+            operation: field(2).to_string(),
+            size: field(3).parse().map_err(|_| bad(3))?,
+            sparsity: field(4).parse().map_err(|_| bad(4))?,
+            median_ns: field(5).parse().map_err(|_| bad(5))?,
+            p5_ns: field(6).parse().map_err(|_| bad(6))?,
+            p95_ns: field(7).parse().map_err(|_| bad(7))?,
+            space_bits: field(8).parse().map_err(|_| bad(8))?,
+        });
+    }
+    Ok(measurements)
+}
+
+// One point of a series with the ends of its error bar
+struct Point {
+    x: f64,
+    y: f64,
+    low: f64,
+    high: f64,
+}
+
+struct Series {
+    label: String,
+    points: Vec<Point>,
+}
+
+// (center, low, high) of one measurement
+type Bar = (f64, f64, f64);
+
+struct Chart {
+    title: String,
+    x_desc: &'static str,
+    y_desc: &'static str,
+    x_log: bool,
+    series: Vec<Series>,
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[mid]
+    } else {
+        (values[mid - 1] + values[mid]) / 2.0
+    }
+}
+
+// Groups the measurements into one series per label and one point per x,
+// combining the seeds of every point
+fn build_series<'a, I, L, X, Y>(measurements: I, label: L, x: X, y: Y) -> Vec<Series>
+where
+    I: Iterator<Item = &'a Measurement>,
+    L: Fn(&Measurement) -> String,
+    X: Fn(&Measurement) -> f64,
+    Y: Fn(&Measurement) -> Bar,
+{
+    // x is keyed by its bits so the points come out sorted, x is never negative
+    let mut groups: BTreeMap<String, BTreeMap<u64, Vec<Bar>>> = BTreeMap::new();
+    for m in measurements {
+        groups.entry(label(m)).or_default().entry(x(m).to_bits()).or_default().push(y(m));
+    }
+    groups
+        .into_iter()
+        .map(|(label, points)| Series {
+            label,
+            points: points
+                .into_iter()
+                .map(|(x, ys)| {
+                    let mut centers: Vec<f64> = ys.iter().map(|y| y.0).collect();
+                    Point {
+                        x: f64::from_bits(x),
+                        y: median(&mut centers),
+                        low: ys.iter().map(|y| y.1).fold(f64::INFINITY, f64::min),
+                        high: ys.iter().map(|y| y.2).fold(f64::NEG_INFINITY, f64::max),
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn distinct(values: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut values: Vec<f64> = values.collect();
+    values.sort_by(|a, b| a.total_cmp(b));
+    values.dedup();
+    values
+}
+
+fn charts_for(experiment: &str, measurements: &[Measurement]) -> Vec<(String, Chart)> {
+    let rows = || measurements.iter().filter(move |m| m.experiment == experiment);
+    let time_label = |m: &Measurement| format!("{} {}", m.structure, m.operation);
+    let time = |m: &Measurement| (m.median_ns, m.p5_ns, m.p95_ns);
+    let mut charts = Vec::new();
+
+    for sparsity in distinct(rows().map(|m| m.sparsity)) {
+        charts.push((format!("{}_time_vs_size_sparsity_{}", experiment, sparsity), Chart {
+            title: format!("{}: time per operation, sparsity {}", experiment, sparsity),
+            x_desc: "size",
+            y_desc: "ns per operation",
+            x_log: true,
+            series: build_series(rows().filter(|m| m.sparsity == sparsity), time_label, |m| m.size as f64, time),
+        }));
+        // The space doesn't depend on the operation, every operation of a
+        // structure gives the same point
+        charts.push((format!("{}_space_vs_size_sparsity_{}", experiment, sparsity), Chart {
+            title: format!("{}: space, sparsity {}", experiment, sparsity),
+            x_desc: "size",
+            y_desc: "bits per position",
+            x_log: true,
+            series: build_series(
+                rows().filter(|m| m.sparsity == sparsity),
+                |m| m.structure.clone(),
+                |m| m.size as f64,
+                |m| {
+                    let bits = m.space_bits as f64 / m.size as f64;
+                    (bits, bits, bits)
+                },
+            ),
+        }));
+    }
+
+    let sparsities = distinct(rows().map(|m| m.sparsity));
+    if sparsities.len() > 1 {
+        for size in distinct(rows().map(|m| m.size as f64)) {
+            charts.push((format!("{}_time_vs_sparsity_size_{}", experiment, size), Chart {
+                title: format!("{}: time per operation, size {}", experiment, size),
+                x_desc: "sparsity",
+                y_desc: "ns per operation",
+                // A sparsity of 0 has no place on a log axis
+                x_log: sparsities[0] > 0.0,
+                series: build_series(rows().filter(|m| m.size as f64 == size), time_label, |m| m.sparsity, time),
+            }));
+        }
+    }
+    charts
+}
+
+fn plot_error(e: impl std::fmt::Display) -> Error {
+    Error::other(e.to_string())
+}
+
+// Where a log axis puts the values that are 0 or less, a decade below
+// the smallest positive one. A single 0 would otherwise stretch the axis
+// down to the smallest float and flatten every other point.
+fn log_floor(values: impl Iterator<Item = f64>) -> f64 {
+    let smallest = values.filter(|&v| v > 0.0).fold(f64::INFINITY, f64::min);
+    if smallest.is_finite() { smallest / 10.0 } else { 1.0 }
+}
+
+// Widens a range that is a single value so the axis has some length. On a
+// log axis both ends have to be floored with log_floor first.
+fn axis_range(min: f64, max: f64, log: bool) -> (f64, f64) {
+    if log {
+        if min >= max { (min / 2.0, max * 2.0) } else { (min / 1.25, max * 1.25) }
+    } else {
+        let pad = if min >= max { min.abs().max(1.0) / 2.0 } else { (max - min) / 20.0 };
+        (min - pad, max + pad)
+    }
+}
+
+// Sizes are whole numbers and shouldn't get a .0
+fn tick_label(value: &f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", *value as i64)
+    } else {
+        format!("{}", (value * 1000.0).round() / 1000.0)
+    }
+}
+
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, chart: &Chart) -> std::io::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE).map_err(plot_error)?;
+    let points = || chart.series.iter().flat_map(|s| s.points.iter());
+    let x_floor = if chart.x_log { log_floor(points().map(|p| p.x)) } else { f64::NEG_INFINITY };
+    let y_floor = log_floor(points().flat_map(|p| [p.low, p.y, p.high]));
+    let x = |p: &Point| p.x.max(x_floor);
+    let y = |p: &Point| p.y.max(y_floor);
+    let low = |p: &Point| p.low.max(y_floor);
+    let high = |p: &Point| p.high.max(y_floor);
+
+    let (x_min, x_max) = axis_range(
+        points().map(x).fold(f64::INFINITY, f64::min),
+        points().map(x).fold(f64::NEG_INFINITY, f64::max),
+        chart.x_log,
+    );
+    // The log axis only gets labels at powers of ten, so y spans whole decades
+    let (y_min, y_max) = axis_range(
+        points().map(low).fold(f64::INFINITY, f64::min),
+        points().map(high).fold(f64::NEG_INFINITY, f64::max),
+        true,
+    );
+    let (y_min, y_max) = (10f64.powf(y_min.log10().floor()), 10f64.powf(y_max.log10().ceil()));
+
+    let mut builder = ChartBuilder::on(&root);
+    builder.caption(&chart.title, ("sans-serif", 22)).margin(15).x_label_area_size(40).y_label_area_size(70);
+
+    // The two axis types are different types so the drawing is shared
+    // through a macro instead of a function
+    macro_rules! draw_series {
+        ($context:expr) => {{
+            let mut context = $context;
+            context
+                .configure_mesh()
+                .x_desc(chart.x_desc)
+                .y_desc(chart.y_desc)
+                .x_label_formatter(&tick_label)
+                .y_label_formatter(&tick_label)
+                .draw()
+                .map_err(plot_error)?;
+            for (i, series) in chart.series.iter().enumerate() {
+                let color = Palette99::pick(i).to_rgba();
+                let line = series.points.iter().map(|p| (x(p), y(p)));
+                context
+                    .draw_series(LineSeries::new(line, color.stroke_width(2)))
+                    .map_err(plot_error)?
+                    .label(&series.label)
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+                context
+                    .draw_series(series.points.iter().map(|p| ErrorBar::new_vertical(x(p), low(p), y(p), high(p), color.filled(), 8)))
+                    .map_err(plot_error)?;
+            }
+            context
+                .configure_series_labels()
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
+                .position(SeriesLabelPosition::UpperLeft)
+                .draw()
+                .map_err(plot_error)?;
+        }};
+    }
+
+    if chart.x_log {
+        draw_series!(builder.build_cartesian_2d((x_min..x_max).log_scale(), (y_min..y_max).log_scale()).map_err(plot_error)?);
+    } else {
+        draw_series!(builder.build_cartesian_2d(x_min..x_max, (y_min..y_max).log_scale()).map_err(plot_error)?);
+    }
+    root.present().map_err(plot_error)
+}
+
+// Draws every chart of the results table into out_dir and returns the
+// files it wrote
+pub fn plot_results(results_file: &str, out_dir: &str, format: ImageFormat) -> std::io::Result<Vec<String>> {
+    let measurements = read_measurements(results_file)?;
+    fs::create_dir_all(out_dir)?;
+
+    let mut experiments: Vec<&str> = measurements.iter().map(|m| m.experiment.as_str()).collect();
+    experiments.sort();
+    experiments.dedup();
+
+    let mut written = Vec::new();
+    for experiment in experiments {
+        for (name, chart) in charts_for(experiment, &measurements) {
+            if chart.series.is_empty() {
+                continue;
+            }
+            let path = Path::new(out_dir).join(format!("{}.{}", name, format.extension()));
+            let size = (1024, 768);
+            match format {
+                ImageFormat::Svg => draw(SVGBackend::new(&path, size).into_drawing_area(), &chart)?,
+                ImageFormat::Png => draw(BitMapBackend::new(&path, size).into_drawing_area(), &chart)?,
+            }
+            written.push(path.display().to_string());
+        }
+    }
+    Ok(written)
+}
+
+pub fn run(args: &[String]) -> std::io::Result<()> {
+    let (results_file, out_dir, format) = match args {
+        [results_file, out_dir] => (results_file, out_dir, ImageFormat::Svg),
+        [results_file, out_dir, flag, format] if flag == "--format" => {
+            let format = match format.as_str() {
+                "svg" => ImageFormat::Svg,
+                "png" => ImageFormat::Png,
+                _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown format {}\n\n{}", format, USAGE))),
+            };
+            (results_file, out_dir, format)
+        }
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    };
+    for file in plot_results(results_file, out_dir, format)? {
+        println!("wrote {}", file);
+    }
+    Ok(())
+}
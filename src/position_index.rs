@@ -0,0 +1,417 @@
+use crate::bit_vector::BitVector;
+use crate::batch;
+use crate::rank_support::{build_tables, read_tables, RankTables};
+use crate::utils::rank_block_sizes;
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
+use std::cmp;
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Which structure a SparseArray uses to remember where its present elements are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    // A bit vector over every position with rank tables on top of it.
+    // Costs a little over one bit per position no matter how many are present.
+    Plain,
+    // Only the sorted positions of the present elements, as plain u64s.
+    // Costs 64 bits per present element no matter how big the array is.
+    SortedList,
+}
+
+impl IndexKind {
+    // Picks the index that takes less space when num_present out of
+    // size positions hold an element. Ties go to the sorted list,
+    // which is the simpler of the two.
+    pub fn for_density(size: u64, num_present: u64) -> Self {
+        let sorted_list_bits = SortedListIndex::bits_for(num_present);
+        let plain_bits = PlainIndex::bits_for(size);
+        if sorted_list_bits <= plain_bits {
+            IndexKind::SortedList
+        } else {
+            IndexKind::Plain
+        }
+    }
+}
+
+// The positions of the present elements of a SparseArray.
+// rank1(i) is the number of present positions before i and
+// select1(i) is the FIRST index j for which rank1(j) = i, just like
+// RankSupport and SelectSupport.
+pub enum PositionIndex {
+    Plain(PlainIndex),
+    SortedList(SortedListIndex),
+}
+
+impl PositionIndex {
+    pub fn new(size: u64, kind: IndexKind) -> Self {
+        match kind {
+            IndexKind::Plain => PositionIndex::Plain(PlainIndex::new(size)),
+            IndexKind::SortedList => PositionIndex::SortedList(SortedListIndex::new(size)),
+        }
+    }
+
+    // Builds a finalized index from the sorted present positions
+    pub fn from_sorted_positions(size: u64, positions: Vec<u64>, kind: IndexKind) -> Self {
+        match kind {
+            IndexKind::Plain => PositionIndex::Plain(PlainIndex::from_positions(size, positions)),
+            IndexKind::SortedList => PositionIndex::SortedList(SortedListIndex { positions, size }),
+        }
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        match self {
+            PositionIndex::Plain(_) => IndexKind::Plain,
+            PositionIndex::SortedList(_) => IndexKind::SortedList,
+        }
+    }
+
+    // Marks pos as present, positions have to come in increasing order
+    pub fn set(&mut self, pos: u64) {
+        match self {
+            PositionIndex::Plain(index) => index.set(pos),
+            PositionIndex::SortedList(index) => index.push(pos),
+        }
+    }
+
+    // Builds whatever the index needs to answer rank and select
+    pub fn finalize(&mut self) {
+        self.finalize_with_threads(1);
+    }
+
+    // The sorted list has nothing to build, so it ignores the threads
+    pub fn finalize_with_threads(&mut self, num_threads: usize) {
+        match self {
+            PositionIndex::Plain(index) => index.finalize_with_threads(num_threads),
+            PositionIndex::SortedList(_) => {}
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        match self {
+            PositionIndex::Plain(index) => index.bit_vector.size() as u64,
+            PositionIndex::SortedList(index) => index.size,
+        }
+    }
+
+    // Returns whether there is a present element at position i
+    pub fn get(&self, i: u64) -> bool {
+        match self {
+            PositionIndex::Plain(index) => index.bit_vector.get(i as usize),
+            PositionIndex::SortedList(index) => index.get(i),
+        }
+    }
+
+    pub fn rank1(&self, i: u64) -> u64 {
+        match self {
+            PositionIndex::Plain(index) => index.rank1(i as usize),
+            PositionIndex::SortedList(index) => index.rank1(i),
+        }
+    }
+
+    pub fn select1(&self, i: u64) -> u64 {
+        match self {
+            PositionIndex::Plain(index) => index.select1(i),
+            PositionIndex::SortedList(index) => index.select1(i),
+        }
+    }
+
+    // Starts loading what get(i) reads first. For the plain index that is
+    // the word with bit i, the rank tables are only read when the bit is
+    // set. The sorted list is binary searched, so it loads where i
+    // would be if the present positions were spread evenly.
+    pub(crate) fn prefetch(&self, i: u64) {
+        match self {
+            PositionIndex::Plain(index) => batch::prefetch(&index.bit_vector.data, i as usize / 64),
+            PositionIndex::SortedList(index) => {
+                let guess = i as u128 * index.positions.len() as u128 / (index.size as u128 + 1);
+                batch::prefetch(&index.positions, guess as usize);
+            }
+        }
+    }
+
+    // Returns every present position in increasing order
+    pub fn positions(&self) -> Vec<u64> {
+        match self {
+            PositionIndex::Plain(_) => self.positions_iter().collect(),
+            PositionIndex::SortedList(index) => index.positions.clone(),
+        }
+    }
+
+    // Walks the present positions in increasing order without collecting
+    // them, a word of the bit vector at a time for the plain index
+    pub fn positions_iter(&self) -> Positions<'_> {
+        match self {
+            PositionIndex::Plain(index) => Positions::Plain {
+                words: &index.bit_vector.data,
+                size: index.bit_vector.size() as u64,
+                word_index: 0,
+                rest: index.bit_vector.data.first().copied().unwrap_or(0),
+            },
+            PositionIndex::SortedList(index) => Positions::SortedList(index.positions.iter()),
+        }
+    }
+
+    pub fn print_index(&self, bv_step_size: usize) {
+        match self {
+            PositionIndex::Plain(index) => {
+                index.bit_vector.print_bit_vector(bv_step_size);
+            }
+            PositionIndex::SortedList(index) => {
+                println!("Present positions out of {}: {:?}", index.size, index.positions);
+            }
+        }
+    }
+}
+
+
+// Iterator over the present positions of a PositionIndex, from positions_iter
+pub enum Positions<'a> {
+    // rest is what is left of the word at word_index after the positions
+    // already handed out were peeled off
+    Plain { words: &'a [u64], size: u64, word_index: usize, rest: u64 },
+    SortedList(std::slice::Iter<'a, u64>),
+}
+
+impl Iterator for Positions<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match self {
+            Positions::Plain { words, size, word_index, rest } => {
+                while *rest == 0 {
+                    *word_index += 1;
+                    *rest = *words.get(*word_index)?;
+                }
+                // Peel off the lowest set bit of the word
+                let pos = *word_index as u64 * 64 + rest.trailing_zeros() as u64;
+                *rest &= *rest - 1;
+                if pos >= *size {
+                    // Bits past the end are never set, but don't count on it
+                    *word_index = words.len();
+                    *rest = 0;
+                    return None;
+                }
+                Some(pos)
+            }
+            Positions::SortedList(positions) => positions.next().copied(),
+        }
+    }
+}
+
+
+// The bit vector and the rank tables of RankSupport, owned by the index
+// so that the sparse array does not have to borrow anything
+pub struct PlainIndex {
+    pub bit_vector: BitVector,
+    pub superchunk_data: Vec<u64>,
+    pub chunk_data: Vec<u16>,
+    pub chunk_size: usize,
+    pub superchunk_size: usize,
+}
+
+impl PlainIndex {
+    pub fn new(size: u64) -> Self {
+        let size = size as usize;
+        // Create bit vector
+        let bit_vector = BitVector::new(size);
+
+        // The size of a chunk is .5*log(n) and the size of a global
+        // chunk is (logn)^2, with a minimum for tiny bit vectors
+        let (chunk_size, superchunk_size) = rank_block_sizes(size);
+
+        // The superchunk has ranks of celing of n/(logn)^2 entries
+        let superchunk_data = vec![0; size.div_ceil(superchunk_size)];
+
+        // The chunk has the ranks of celing of n/(.5logn) entries
+        let chunk_data = vec![0; size.div_ceil(chunk_size)];
+
+        Self {
+            bit_vector,
+            superchunk_data,
+            chunk_data,
+            chunk_size,
+            superchunk_size,
+        }
+    }
+
+    // Builds a finalized plain index with the given positions set
+    pub fn from_positions<I: IntoIterator<Item = u64>>(size: u64, positions: I) -> Self {
+        let mut index = PlainIndex::new(size);
+        for pos in positions {
+            index.set(pos);
+        }
+        index.finalize();
+        index
+    }
+
+    // Number of bits a plain index over size positions takes
+    pub fn bits_for(size: u64) -> u64 {
+        let (chunk_size, superchunk_size) = rank_block_sizes(size as usize);
+        let (chunk_size, superchunk_size) = (chunk_size as u64, superchunk_size as u64);
+        let bit_vector_bits = size.div_ceil(64) * 64;
+        bit_vector_bits + size.div_ceil(superchunk_size) * 64 + size.div_ceil(chunk_size) * 16
+    }
+
+    pub fn set(&mut self, pos: u64) {
+        self.bit_vector.set(pos as usize, true);
+    }
+
+    pub fn finalize(&mut self) {
+        self.finalize_with_threads(1);
+    }
+
+    // Builds the rank tables with num_threads threads, 0 meaning one per
+    // core, the same tables finalize builds
+    pub fn finalize_with_threads(&mut self, num_threads: usize) {
+        let (superchunk_data, chunk_data) = build_tables(&self.bit_vector, self.chunk_size, self.superchunk_size, num_threads);
+        self.superchunk_data = superchunk_data;
+        self.chunk_data = chunk_data;
+    }
+
+    pub fn rank1(&self, i: usize) -> u64 {
+        self.tables().rank1(i)
+    }
+
+    // Gives position, in the underlying bit-vector, of the FIRST index, j for which rank1(j) = i.
+    // Returns u64::MAX if there are fewer than i ones.
+    pub fn select1(&self, i: u64) -> u64 {
+        self.tables().select1(i)
+    }
+
+    fn tables(&self) -> RankTables<'_> {
+        RankTables {
+            bit_vector: &self.bit_vector,
+            superchunk_data: &self.superchunk_data,
+            chunk_data: &self.chunk_data,
+            chunk_size: self.chunk_size,
+            superchunk_size: self.superchunk_size,
+        }
+    }
+
+    // Reads the chunk_size, superchunk_size and the rank tables written by
+    // write_rank_tables for the given bit vector
+    pub fn read_rank_tables<R: Read>(bit_vector: BitVector, reader: &mut R) -> std::io::Result<Self> {
+        let tables = read_tables(bit_vector.size(), reader)?;
+        Ok(Self {
+            bit_vector,
+            superchunk_data: tables.superchunk_data,
+            chunk_data: tables.chunk_data,
+            chunk_size: tables.chunk_size,
+            superchunk_size: tables.superchunk_size,
+        })
+    }
+
+    // Checks the bit vector and that the rank tables are the ones
+    // finalize would build for it
+    pub fn verify(&self) -> std::io::Result<()> {
+        self.bit_vector.verify()?;
+        let size = self.bit_vector.size();
+        let (chunk_size, superchunk_size) = rank_block_sizes(size);
+        if self.chunk_size != chunk_size || self.superchunk_size != superchunk_size {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "chunk sizes {}/{} don't match the {}/{} of a bit vector of size {}",
+                self.chunk_size, self.superchunk_size, chunk_size, superchunk_size, size
+            )));
+        }
+        self.tables().verify()
+    }
+
+    // Writes the chunk_size, superchunk_size and the rank tables to writer
+    pub fn write_rank_tables<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.tables().write_to(writer)
+    }
+
+    pub fn print_chunks_and_super_chunks(&self) {
+        println!("Printing superchunks");
+
+        let size = self.bit_vector.size();
+        let num_superchunks = self.superchunk_data.len();
+        let num_chunks = self.chunk_data.len();
+
+        for i in 0..num_superchunks{
+            let start = i*self.superchunk_size;
+            let end = cmp::min((1+i)*self.superchunk_size, size);
+            println!("start is = {} and end is = {}", start, end);
+            println!("SuperBlock i = {} has offset value={}", i, self.superchunk_data[i]);
+            println!();
+        }
+
+        println!("Printing offset values for in between chunks");
+        for i in 0..num_chunks{
+            let start = i*self.chunk_size;
+            let end = cmp::min((1+i)*self.chunk_size, size);
+            println!("start is = {} and end is = {}", start, end);
+            println!("Block i = {} has offset value={}", i, self.chunk_data[i]);
+            println!();
+        }
+    }
+}
+
+
+// The sorted positions of the present elements. rank1 is a binary search
+// over the positions and select1 is a lookup.
+pub struct SortedListIndex {
+    pub positions: Vec<u64>,
+    pub size: u64,
+}
+
+impl SortedListIndex {
+    pub fn new(size: u64) -> Self {
+        Self {
+            positions: Vec::new(),
+            size,
+        }
+    }
+
+    // Number of bits a sorted list index with num_present positions takes
+    pub fn bits_for(num_present: u64) -> u64 {
+        num_present * 64
+    }
+
+    pub fn push(&mut self, pos: u64) {
+        if let Some(&last) = self.positions.last() {
+            assert!(pos > last, "Positions must be appended in increasing order");
+        }
+        self.positions.push(pos);
+    }
+
+    pub fn get(&self, i: u64) -> bool {
+        assert!(i < self.size, "Index out of bounds");
+        self.positions.binary_search(&i).is_ok()
+    }
+
+    pub fn rank1(&self, i: u64) -> u64 {
+        self.positions.partition_point(|&pos| pos < i) as u64
+    }
+
+    // The i-th present position is positions[i-1], and rank1 first reaches i right after it
+    pub fn select1(&self, i: u64) -> u64 {
+        if i == 0 {
+            return 0;
+        }
+        if i > self.positions.len() as u64 {
+            return u64::MAX;
+        }
+        self.positions[(i - 1) as usize] + 1
+    }
+}
+
+
+// The bit vector is inline in the plain index and reports its own header,
+// so the headers here only count the rest of the struct
+impl SpaceUsage for PositionIndex {
+    fn space_report(&self) -> SpaceNode {
+        let enum_header = inline_bits::<PositionIndex>();
+        match self {
+            PositionIndex::Plain(index) => SpaceNode::with_children("PlainIndex", vec![
+                SpaceNode::leaf("header", enum_header - inline_bits::<BitVector>()),
+                index.bit_vector.space_report().renamed("bit_vector"),
+                SpaceNode::leaf("superchunk_data", vec_bits(&index.superchunk_data)),
+                SpaceNode::leaf("chunk_data", vec_bits(&index.chunk_data)),
+            ]),
+            PositionIndex::SortedList(index) => SpaceNode::with_children("SortedListIndex", vec![
+                SpaceNode::leaf("header", enum_header),
+                SpaceNode::leaf("positions", vec_bits(&index.positions)),
+            ]),
+        }
+    }
+}
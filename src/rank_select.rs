@@ -0,0 +1,179 @@
+use crate::batch::QueryOrder;
+use crate::bit_vector::BitVector;
+use crate::rank_support::{build_tables, read_tables, BlockSizes, RankSupport, RankTables};
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+// A bit vector together with its rank tables, answering rank1 and select1.
+// RankSupport and SelectSupport borrow the bit vector, which keeps them
+// from outliving the function that built them. RankSelect owns everything,
+// so it can be put in an Arc and handed to as many threads as needed.
+//
+// Nothing changes a RankSelect once it is built, so it is Send and Sync,
+// and so are RankSupport, SelectSupport and finalized SparseArrays of
+// Send + Sync values.
+pub struct RankSelect {
+    bit_vector: BitVector,
+    superchunk_data: Vec<u64>,
+    chunk_data: Vec<u16>,
+    chunk_size: usize,
+    superchunk_size: usize,
+}
+
+// Fails to compile unless T can be shared across threads. The read-only
+// structures each check themselves next to their definition with
+// const _: fn() = assert_shareable::<T>;
+pub(crate) fn assert_shareable<T: Send + Sync>() {}
+
+const _: fn() = assert_shareable::<RankSelect>;
+
+impl RankSelect {
+    // Takes the bit vector and builds its rank tables with the same block
+    // sizes as RankSupport::new
+    pub fn new(bit_vector: BitVector) -> Self {
+        Self::build_with_threads(bit_vector, BlockSizes::Theoretical, 1).unwrap()
+    }
+
+    // Builds the rank tables with the given block sizes and num_threads
+    // threads, 0 meaning one per core
+    pub fn build_with_threads(bit_vector: BitVector, block_sizes: BlockSizes, num_threads: usize) -> std::io::Result<Self> {
+        let (chunk_size, superchunk_size) = block_sizes.resolve(bit_vector.size())?;
+        let (superchunk_data, chunk_data) = build_tables(&bit_vector, chunk_size, superchunk_size, num_threads);
+        Ok(Self {
+            bit_vector,
+            superchunk_data,
+            chunk_data,
+            chunk_size,
+            superchunk_size,
+        })
+    }
+
+    // Copies a rank support and the bit vector it borrows
+    pub fn from_rank_support(rank_support: &RankSupport) -> Self {
+        let tables = rank_support.tables();
+        Self {
+            bit_vector: tables.bit_vector.clone(),
+            superchunk_data: tables.superchunk_data.to_vec(),
+            chunk_data: tables.chunk_data.to_vec(),
+            chunk_size: tables.chunk_size,
+            superchunk_size: tables.superchunk_size,
+        }
+    }
+
+    pub fn bit_vector(&self) -> &BitVector {
+        &self.bit_vector
+    }
+
+    // Gives the bit vector back, dropping the tables
+    pub fn into_bit_vector(self) -> BitVector {
+        self.bit_vector
+    }
+
+    pub fn size(&self) -> usize {
+        self.bit_vector.size()
+    }
+
+    pub fn num_ones(&self) -> u64 {
+        self.rank1(self.size())
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.bit_vector.get(i)
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn superchunk_size(&self) -> usize {
+        self.superchunk_size
+    }
+
+    // Number of ones before i, like RankSupport::rank1
+    pub fn rank1(&self, i: usize) -> u64 {
+        self.tables().rank1(i)
+    }
+
+    // Gives position, in the underlying bit-vector, of the FIRST index, j for which rank1(j) = i.
+    // Returns u64::MAX if there are fewer than i ones.
+    pub fn select1(&self, i: u64) -> u64 {
+        self.tables().select1(i)
+    }
+
+    pub fn rank1_batch(&self, positions: &[usize], out: &mut [u64], order: QueryOrder) {
+        self.tables().rank1_batch(positions, out, order)
+    }
+
+    pub fn select1_batch(&self, ranks: &[u64], out: &mut [u64], order: QueryOrder) {
+        self.tables().select1_batch(ranks, out, order)
+    }
+
+    // Bits the rank tables take on top of the bit vector, the tables of
+    // the space report
+    pub fn overhead(&self) -> usize {
+        self.space_report().children_bits(&["superchunk_data", "chunk_data"]) as usize
+    }
+
+    fn tables(&self) -> RankTables<'_> {
+        RankTables {
+            bit_vector: &self.bit_vector,
+            superchunk_data: &self.superchunk_data,
+            chunk_data: &self.chunk_data,
+            chunk_size: self.chunk_size,
+            superchunk_size: self.superchunk_size,
+        }
+    }
+
+    // Saves the bit vector followed by its rank tables, the same file the
+    // command line tool builds for the rank kind
+    pub fn save(&self, file_name: &str) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(file_name)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.bit_vector.write_to(writer)?;
+        self.tables().write_to(writer)
+    }
+
+    pub fn load(file_name: &str) -> std::io::Result<Self> {
+        let mut file = BufReader::new(File::open(file_name)?);
+        let rank_select = Self::read_from(&mut file)?;
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the rank tables"));
+        }
+        Ok(rank_select)
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let bit_vector = BitVector::read_from(reader)?;
+        let tables = read_tables(bit_vector.size(), reader)?;
+        Ok(Self {
+            bit_vector,
+            superchunk_data: tables.superchunk_data,
+            chunk_data: tables.chunk_data,
+            chunk_size: tables.chunk_size,
+            superchunk_size: tables.superchunk_size,
+        })
+    }
+
+    // Checks the bit vector and that the tables are the ones its block
+    // sizes build for it
+    pub fn verify(&self) -> std::io::Result<()> {
+        self.bit_vector.verify()?;
+        self.tables().verify()
+    }
+}
+
+impl SpaceUsage for RankSelect {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("RankSelect", vec![
+            SpaceNode::leaf("header", inline_bits::<RankSelect>()),
+            SpaceNode::leaf("superchunk_data", vec_bits(&self.superchunk_data)),
+            SpaceNode::leaf("chunk_data", vec_bits(&self.chunk_data)),
+            self.bit_vector.space_report().renamed("bit_vector"),
+        ])
+    }
+}
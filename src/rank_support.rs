@@ -1,107 +1,382 @@
 use crate::bit_vector::BitVector;
-use crate::utils::my_log;
+use crate::batch::{self, QueryOrder};
+use crate::broadword;
+use crate::utils::rank_block_sizes;
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
 use std::cmp;
+use std::sync::OnceLock;
+
+
+// The largest chunk, since rank1 reads a chunk as one u64
+pub const MAX_CHUNK_SIZE: usize = 64;
+
+// How big the chunks and superchunks of the rank tables are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockSizes {
+    // The Jacobson sizes, .5*log(n) bit chunks and (logn)^2 bit
+    // superchunks, which are o(n) in theory. Every chunk still costs a
+    // 16 bit entry, so for any size that fits in memory Speed's larger
+    // blocks take less space.
+    Theoretical,
+    // 64 bit chunks that are read as one word and 512 bit superchunks
+    Speed,
+    Custom { chunk_size: usize, superchunk_size: usize },
+}
 
+impl BlockSizes {
+    // Returns (chunk_size, superchunk_size) for a bit vector of size bits
+    pub fn resolve(self, size: usize) -> std::io::Result<(usize, usize)> {
+        let (chunk_size, superchunk_size) = match self {
+            BlockSizes::Theoretical => rank_block_sizes(size),
+            BlockSizes::Speed => (64, 512),
+            BlockSizes::Custom { chunk_size, superchunk_size } => (chunk_size, superchunk_size),
+        };
+        check_block_sizes(chunk_size, superchunk_size)?;
+        Ok((chunk_size, superchunk_size))
+    }
+}
 
-pub struct RankSupport<'a> {
-    bit_vector: &'a BitVector,
-    superchunk_data: Vec<u64>,
-    chunk_data: Vec<u16>,
-    chunk_size: usize,
-    superchunk_size: usize,
+// A chunk has to fit in the u64 rank1 reads it into, a superchunk has to
+// be whole chunks, and the rank of the last chunk in a superchunk has to
+// fit in the u16 of the chunk table
+fn check_block_sizes(chunk_size: usize, superchunk_size: usize) -> std::io::Result<()> {
+    let problem = if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        format!("the chunk size has to be between 1 and {} bits", MAX_CHUNK_SIZE)
+    } else if superchunk_size == 0 || !superchunk_size.is_multiple_of(chunk_size) {
+        "the superchunk size has to be a multiple of the chunk size".to_string()
+    } else if superchunk_size - chunk_size > u16::MAX as usize {
+        format!("the chunk ranks of a superchunk don't fit in {} bits", u16::BITS)
+    } else {
+        return Ok(());
+    };
+    Err(Error::new(ErrorKind::InvalidInput, format!(
+        "chunk size {} and superchunk size {}: {}", chunk_size, superchunk_size, problem
+    )))
 }
 
-impl<'a> RankSupport<'a> {
+// The rank tables together with the bit vector they describe. RankSupport
+// and PlainIndex keep the same tables, so both answer rank1 and select1
+// through this.
+pub(crate) struct RankTables<'t> {
+    pub bit_vector: &'t BitVector,
+    pub superchunk_data: &'t [u64],
+    pub chunk_data: &'t [u16],
+    pub chunk_size: usize,
+    pub superchunk_size: usize,
+}
 
-    // Only way I knew how to implement the sparse_array finalize
-    pub fn finalize_rank(&mut self) {
+impl<'t> RankTables<'t> {
+    pub fn rank1(&self, i: usize) -> u64 {
+        if i == 0 {
+            return 0;
+        }
         let size =  self.bit_vector.size();
+        if size == 0 {
+            return 0;
+        }
 
-        // log of size is ALWAYS EVEN
-        let log_of_size = my_log(size);
+        // Past the last superchunk there is no table entry, so the rank of
+        // the whole vector is the rank of the last bit plus the last bit
+        if i >= size {
+            return self.rank1(size - 1) + self.bit_vector.get(size - 1) as u64;
+        }
 
-        // The size of a chunk is .5*log(n)
-        let chunk_size: usize = log_of_size/2;
+        let superchunk_rank = self.superchunk_data[i / self.superchunk_size];
+        let chunk_rank = self.chunk_data[i / self.chunk_size] as u64;
+        let base_rank = superchunk_rank + chunk_rank;
 
-        // The size of a global chunk is (logn)^2
-        let superchunk_size: usize = 2*chunk_size*log_of_size;
+        // The chunk is read as one word, which is a single load when the
+        // chunks are word aligned
+        let chunk_local_position = i % self.chunk_size;
+        let chunk_start =  i - chunk_local_position;
+        let chunk_end = cmp::min(chunk_start + self.chunk_size, size);
+        let chunk = self.bit_vector.interpret_as_u64_int(chunk_start, chunk_end);
 
+        base_rank + broadword::rank_in_word(chunk, chunk_local_position)
+    }
 
-        let mut rank = 0;
-        let mut prev_superchunk_rank = 0;
+    // Gives position, in the underlying bit-vector, of the FIRST index, j for which rank1(j) = i.
+    // Returns u64::MAX if there are fewer than i ones.
+    pub fn select1(&self, i: u64) -> u64 {
+        if i == 0 {
+            return 0;
+        }
+        if i > self.rank1(self.bit_vector.size()) {
+            return u64::MAX;
+        }
+        self.select_present(i)
+    }
+
+    // select1 for an i between 1 and the number of ones
+    fn select_present(&self, i: u64) -> u64 {
+        let size = self.bit_vector.size();
 
-        for i in 0..size {
-            // if it's divisible by size_of_chuck
-            // we add the rank to the global chuck rank
-            
-            // if it's divisible by size of super chunk
-            if i % superchunk_size == 0 {
-                // We need to update the sperchunk's rank
-                self.superchunk_data[i / superchunk_size] = rank;
-                // Keep track of the previous superchunk rank
-                prev_superchunk_rank = rank;
-                // The first entry is 0 for the sub_chuck so we don't need to update anything
-            } else if i % chunk_size == 0 {
-                // If it's divisible by the chunk_size we need to update the chunk_data rank
-                self.chunk_data[i / chunk_size] = (rank - prev_superchunk_rank) as u16;
-            }
-            
-            // Increase the rank by 1 if we see a 1
-            if self.bit_vector.get(i) {
-                rank += 1;
-            }
-            
+        // The last superchunk with fewer than i ones before it has the i-th
+        // one, the first superchunk has 0 before it so there always is one
+        let superchunk = self.superchunk_data.partition_point(|&rank| rank < i) - 1;
+        let in_superchunk = i - self.superchunk_data[superchunk];
+
+        // Same for the chunks of the superchunk, whose ranks start over
+        // from 0 at the first chunk
+        let chunks_per_superchunk = self.superchunk_size / self.chunk_size;
+        let first_chunk = superchunk * chunks_per_superchunk;
+        let end_chunk = cmp::min(first_chunk + chunks_per_superchunk, self.chunk_data.len());
+        let chunk = first_chunk + self.chunk_data[first_chunk + 1..end_chunk]
+            .partition_point(|&rank| (rank as u64) < in_superchunk);
+        let in_chunk = in_superchunk - if chunk == first_chunk { 0 } else { self.chunk_data[chunk] as u64 };
+
+        let chunk_start = chunk * self.chunk_size;
+        let chunk_end = cmp::min(chunk_start + self.chunk_size, size);
+        let word = self.bit_vector.interpret_as_u64_int(chunk_start, chunk_end);
+        (chunk_start + broadword::select_in_word(word, in_chunk as usize - 1) + 1) as u64
+    }
+
+    // Writes the chunk sizes and then the superchunk and chunk tables
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        // Save chunk_size and superchunk_size
+        writer.write_all(&(self.chunk_size as u64).to_le_bytes())?;
+        writer.write_all(&(self.superchunk_size as u64).to_le_bytes())?;
+
+        // Save superchunk_data
+        for &value in self.superchunk_data {
+            writer.write_all(&value.to_le_bytes())?;
         }
 
+        // Save chunk_data
+        for &value in self.chunk_data {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
     }
 
-    pub fn new(bit_vector: &'a BitVector) -> Self {
+    // Checks that the tables are the ones their block sizes build for the
+    // bit vector, which catches tables saved for a different bit vector
+    pub fn verify(&self) -> std::io::Result<()> {
+        check_block_sizes(self.chunk_size, self.superchunk_size).map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        let (superchunk_data, chunk_data) = build_tables(self.bit_vector, self.chunk_size, self.superchunk_size, 1);
+        if let Some(i) = (0..self.superchunk_data.len()).find(|&i| self.superchunk_data[i] != superchunk_data[i]) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("superchunk {} has the wrong rank", i)));
+        }
+        if let Some(i) = (0..self.chunk_data.len()).find(|&i| self.chunk_data[i] != chunk_data[i]) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("chunk {} has the wrong rank", i)));
+        }
+        Ok(())
+    }
+
+    // Starts loading the table entries and the word rank1(i) reads
+    pub fn prefetch_rank(&self, i: usize) {
+        batch::prefetch(self.superchunk_data, i / self.superchunk_size);
+        batch::prefetch(self.chunk_data, i / self.chunk_size);
+        batch::prefetch(&self.bit_vector.data, i / 64);
+    }
+
+    // Fills out[k] with rank1(positions[k])
+    pub fn rank1_batch(&self, positions: &[usize], out: &mut [u64], order: QueryOrder) {
+        batch::check_lengths(positions.len(), out.len());
+        batch::for_each_query(positions, order, |slot, i| out[slot] = self.rank1(i), |i| self.prefetch_rank(i));
+    }
+
+    // Fills out[k] with select1(ranks[k]). Where the i-th one is isn't
+    // known before the search, so the superchunk it would be in if the
+    // ones were spread evenly is prefetched instead.
+    pub fn select1_batch(&self, ranks: &[u64], out: &mut [u64], order: QueryOrder) {
+        batch::check_lengths(ranks.len(), out.len());
+        let num_ones = self.rank1(self.bit_vector.size());
+        let num_superchunks = self.superchunk_data.len() as u64;
+        batch::for_each_query(
+            ranks,
+            order,
+            |slot, i| {
+                out[slot] = match i {
+                    0 => 0,
+                    i if i > num_ones => u64::MAX,
+                    i => self.select_present(i),
+                }
+            },
+            |i| {
+                if i <= num_ones {
+                    let guess = (i as u128 * num_superchunks as u128 / (num_ones as u128 + 1)) as usize;
+                    batch::prefetch(self.superchunk_data, guess);
+                }
+            },
+        );
+    }
+}
+
+// Rank tables read back from a file, before they are tied to a bit vector
+pub(crate) struct SavedTables {
+    pub superchunk_data: Vec<u64>,
+    pub chunk_data: Vec<u16>,
+    pub chunk_size: usize,
+    pub superchunk_size: usize,
+}
 
-        let size =  bit_vector.size();
-        let log_of_size = my_log(size);
-
-        // The size of a chunk is .5*log(n)
-        let chunk_size: usize = log_of_size/2;
-
-        // The size of a global chunk is (logn)^2
-        let superchunk_size: usize = 2*chunk_size*log_of_size;
-
-        // The superchunk has ranks of celing of n/(logn)^2 entries
-        let mut superchunk_data = vec![0; (size + superchunk_size - 1) / superchunk_size];
-        
-        // The chunk has the ranks of celing of n/(.5logn) entries
-        let mut chunk_data = vec![0; (size + chunk_size - 1) / chunk_size];
-        
-        
-
-        let mut rank = 0;
-        let mut prev_superchunk_rank = 0;
-
-        for i in 0..size {
-            // if it's divisible by size_of_chuck
-            // we add the rank to the global chuck rank
-            
-            // if it's divisible by size of super chunk
-            if i % superchunk_size == 0 {
-                // We need to update the sperchunk's rank
-                superchunk_data[i / superchunk_size] = rank;
-                // Keep track of the previous superchunk rank
-                prev_superchunk_rank = rank;
-                // The first entry is 0 for the sub_chuck so we don't need to update anything
-            } else if i % chunk_size == 0 {
-                // If it's divisible by the chunk_size we need to update the chunk_data rank
-                chunk_data[i / chunk_size] = (rank - prev_superchunk_rank) as u16;
-            }
-            
-            // Increase the rank by 1 if we see a 1
-            if bit_vector.get(i) {
-                rank += 1;
-            }
-            
+// Reads the chunk sizes and the rank tables written by RankTables::write_to.
+// The number of entries in each table follows from the size of the bit vector.
+pub(crate) fn read_tables<R: Read>(size: usize, reader: &mut R) -> std::io::Result<SavedTables> {
+    // Load chunk_size and superchunk_size
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    let chunk_size = u64::from_le_bytes(buf) as usize;
+    reader.read_exact(&mut buf)?;
+    let superchunk_size = u64::from_le_bytes(buf) as usize;
+
+    check_block_sizes(chunk_size, superchunk_size).map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+    // Load superchunk_data
+    let mut superchunk_data = Vec::with_capacity(size.div_ceil(superchunk_size));
+    for _ in 0..size.div_ceil(superchunk_size) {
+        reader.read_exact(&mut buf)?;
+        superchunk_data.push(u64::from_le_bytes(buf));
+    }
+
+    // Load chunk_data
+    let mut chunk_data = Vec::with_capacity(size.div_ceil(chunk_size));
+    let mut chunk_buf = [0u8; 2];
+    for _ in 0..size.div_ceil(chunk_size) {
+        reader.read_exact(&mut chunk_buf)?;
+        chunk_data.push(u16::from_le_bytes(chunk_buf));
+    }
+
+    Ok(SavedTables { superchunk_data, chunk_data, chunk_size, superchunk_size })
+}
+
+// Builds the superchunk and chunk tables of a bit vector. The bit vector
+// is split into ranges of whole superchunks, each counted by its own
+// thread, and the superchunk ranks are then shifted by the ones in the
+// ranges before them. Chunk ranks only count from the start of their
+// superchunk, so they need no fixing up.
+pub(crate) fn build_tables(bit_vector: &BitVector, chunk_size: usize, superchunk_size: usize, num_threads: usize) -> (Vec<u64>, Vec<u16>) {
+    let size = bit_vector.size();
+    let mut superchunk_data = vec![0; size.div_ceil(superchunk_size)];
+    let mut chunk_data = vec![0; size.div_ceil(chunk_size)];
+
+    let num_superchunks = superchunk_data.len();
+    let num_threads = cmp::max(cmp::min(thread_count(num_threads), num_superchunks), 1);
+    if num_threads == 1 {
+        fill_tables(bit_vector, chunk_size, superchunk_size, 0, &mut superchunk_data, &mut chunk_data);
+        return (superchunk_data, chunk_data);
+    }
+
+    let superchunks_per_thread = num_superchunks.div_ceil(num_threads);
+    let chunks_per_superchunk = superchunk_size / chunk_size;
+    let totals: Vec<u64> = std::thread::scope(|scope| {
+        let mut handles = Vec::with_capacity(num_threads);
+        let mut superchunks_left = &mut superchunk_data[..];
+        let mut chunks_left = &mut chunk_data[..];
+        let mut first_superchunk = 0;
+        while !superchunks_left.is_empty() {
+            let num = cmp::min(superchunks_per_thread, superchunks_left.len());
+            let (superchunks, rest) = superchunks_left.split_at_mut(num);
+            superchunks_left = rest;
+            let num_chunks = cmp::min(num * chunks_per_superchunk, chunks_left.len());
+            let (chunks, rest) = chunks_left.split_at_mut(num_chunks);
+            chunks_left = rest;
+            let first = first_superchunk;
+            handles.push(scope.spawn(move || fill_tables(bit_vector, chunk_size, superchunk_size, first, superchunks, chunks)));
+            first_superchunk += num;
+        }
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    // Every range counted from 0, so add the ones of the ranges before it
+    let mut ones_before = 0;
+    for (range, total) in superchunk_data.chunks_mut(superchunks_per_thread).zip(totals) {
+        for rank in range {
+            *rank += ones_before;
         }
+        ones_before += total;
+    }
+    (superchunk_data, chunk_data)
+}
+
+// Fills the tables of the superchunks starting at first_superchunk that
+// the slices hold, counting ones from the start of the first one.
+// Returns the number of ones in those superchunks.
+fn fill_tables(bit_vector: &BitVector, chunk_size: usize, superchunk_size: usize, first_superchunk: usize, superchunk_data: &mut [u64], chunk_data: &mut [u16]) -> u64 {
+    let size = bit_vector.size();
+    let first_chunk = first_superchunk * (superchunk_size / chunk_size);
+    let mut rank = 0;
+    for (k, superchunk_rank) in superchunk_data.iter_mut().enumerate() {
+        *superchunk_rank = rank;
+        let start = (first_superchunk + k) * superchunk_size;
+        let end = cmp::min(start + superchunk_size, size);
+
+        // The first chunk of a superchunk always has 0 ones before it
+        let mut in_superchunk = 0;
+        let mut chunk_start = start;
+        while chunk_start < end {
+            chunk_data[chunk_start / chunk_size - first_chunk] = in_superchunk as u16;
+            let chunk_end = cmp::min(chunk_start + chunk_size, end);
+            in_superchunk += bit_vector.interpret_as_u64_int(chunk_start, chunk_end).count_ones() as u64;
+            chunk_start = chunk_end;
+        }
+        rank += in_superchunk;
+    }
+    rank
+}
+
+// 0 threads means one per core. Finding the number of cores reads files
+// on Linux, so it's only done once.
+fn thread_count(num_threads: usize) -> usize {
+    static NUM_CORES: OnceLock<usize> = OnceLock::new();
+    if num_threads == 0 {
+        *NUM_CORES.get_or_init(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+    } else {
+        num_threads
+    }
+}
 
+pub struct RankSupport<'a> {
+    bit_vector: &'a BitVector,
+    superchunk_data: Vec<u64>,
+    chunk_data: Vec<u16>,
+    chunk_size: usize,
+    superchunk_size: usize,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<RankSupport<'static>>;
+
+impl<'a> RankSupport<'a> {
+
+    // Only way I knew how to implement the sparse_array finalize
+    pub fn finalize_rank(&mut self) {
+        let (superchunk_data, chunk_data) = build_tables(self.bit_vector, self.chunk_size, self.superchunk_size, 1);
+        self.superchunk_data = superchunk_data;
+        self.chunk_data = chunk_data;
+    }
+
+    pub fn new(bit_vector: &'a BitVector) -> Self {
+        // The size of a chunk is .5*log(n) and the size of a global
+        // chunk is (logn)^2, with a minimum for tiny bit vectors
+        let (chunk_size, superchunk_size) = rank_block_sizes(bit_vector.size());
+        Self::build(bit_vector, chunk_size, superchunk_size)
+    }
+
+    // Builds the rank tables with the given block sizes, which fails if
+    // custom sizes can't be stored in the tables
+    pub fn with_block_sizes(bit_vector: &'a BitVector, block_sizes: BlockSizes) -> std::io::Result<Self> {
+        Self::build_with_threads(bit_vector, block_sizes, 1)
+    }
+
+    // Builds the tables with num_threads threads, 0 meaning one per core.
+    // The tables come out the same whatever the number of threads.
+    pub fn build_with_threads(bit_vector: &'a BitVector, block_sizes: BlockSizes, num_threads: usize) -> std::io::Result<Self> {
+        let (chunk_size, superchunk_size) = block_sizes.resolve(bit_vector.size())?;
+        let (superchunk_data, chunk_data) = build_tables(bit_vector, chunk_size, superchunk_size, num_threads);
+        Ok(Self {
+            bit_vector,
+            superchunk_data,
+            chunk_data,
+            chunk_size,
+            superchunk_size,
+        })
+    }
+
+    fn build(bit_vector: &'a BitVector, chunk_size: usize, superchunk_size: usize) -> Self {
+        let (superchunk_data, chunk_data) = build_tables(bit_vector, chunk_size, superchunk_size, 1);
         Self {
             bit_vector,
             superchunk_data,
@@ -115,96 +390,90 @@ impl<'a> RankSupport<'a> {
         self.bit_vector.size()
     }
 
-    pub fn rank1(&self, i: usize) -> u64 {
-    
-        if i == 0 {
-            return 0;
-        }
-        let size =  self.bit_vector.size();
-
-        let superchunk_rank = self.superchunk_data[i / self.superchunk_size];
-        let chunk_rank = self.chunk_data[i / self.chunk_size] as u64;
-        let base_rank = superchunk_rank + chunk_rank;
-
-       
-        let mut rank = base_rank;
-        
-        let chunk_local_position = i % self.chunk_size;
-        let chunk_start =  i - chunk_local_position;
-        
-        let chunk_end = cmp::min(chunk_start + self.chunk_size, size);
-
-        let chunk_as_int = self.bit_vector.interpret_as_u64_int(chunk_start, chunk_end);
-        rank += self.bit_vector.get_i_th_rank(chunk_as_int, chunk_local_position);
-       
-        rank
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
     }
 
-    pub fn overhead(&self) -> usize {
-        
-        let superchunk_data_bits = self.superchunk_data.len() * 64;
-        let chunk_data_bits = self.chunk_data.len() * 16;
-        superchunk_data_bits + chunk_data_bits
+    pub fn superchunk_size(&self) -> usize {
+        self.superchunk_size
     }
 
-    pub fn save(&self, file_name: &str) -> std::io::Result<()>  { 
+    // Returns the bit vector the rank support was built over
+    pub fn bit_vector(&self) -> &'a BitVector {
+        self.bit_vector
+    }
 
-        let mut file = File::create(file_name)?;
+    pub fn rank1(&self, i: usize) -> u64 {
+        self.tables().rank1(i)
+    }
 
-        // Save chunk_size and superchunk_size
-        file.write_all(&(self.chunk_size as u64).to_le_bytes())?;
-        file.write_all(&(self.superchunk_size as u64).to_le_bytes())?;
+    // Fills out[k] with rank1(positions[k]), prefetching the tables of the
+    // queries a few places ahead
+    pub fn rank1_batch(&self, positions: &[usize], out: &mut [u64], order: QueryOrder) {
+        self.tables().rank1_batch(positions, out, order)
+    }
 
-        
-        // Save superchunk_data
-        for &value in &self.superchunk_data {
-            file.write_all(&value.to_le_bytes())?;
+    pub(crate) fn tables(&self) -> RankTables<'_> {
+        RankTables {
+            bit_vector: self.bit_vector,
+            superchunk_data: &self.superchunk_data,
+            chunk_data: &self.chunk_data,
+            chunk_size: self.chunk_size,
+            superchunk_size: self.superchunk_size,
         }
+    }
 
-        // Save chunk_data
-        for &value in &self.chunk_data {
-            file.write_all(&value.to_le_bytes())?;
-        }
+    // Bits the rank tables take on top of the bit vector, the tables of
+    // the space report
+    pub fn overhead(&self) -> usize {
+        self.space_report().children_bits(&["superchunk_data", "chunk_data"]) as usize
+    }
 
-        Ok(())
+    pub fn save(&self, file_name: &str) -> std::io::Result<()>  { 
 
+        let mut file = BufWriter::new(File::create(file_name)?);
+        self.write_to(&mut file)?;
+        file.flush()
     }
 
-    pub fn load(bit_vector: &'a BitVector, file_name: &str) -> std::io::Result<Self> {
-
-        let mut file = File::open(file_name)?;
-        
-        // Load chunk_size and superchunk_size
-        let mut buf = [0u8; 8];
-        file.read_exact(&mut buf)?;
-        let chunk_size = u64::from_le_bytes(buf) as usize;
-        file.read_exact(&mut buf)?;
-        let superchunk_size = u64::from_le_bytes(buf) as usize;
+    // Writes the chunk sizes and the rank tables but not the bit vector,
+    // so a bit vector and its rank support can share one file. The chunk
+    // sizes are what load uses, whichever preset built the tables.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.tables().write_to(writer)
+    }
 
+    pub fn load(bit_vector: &'a BitVector, file_name: &str) -> std::io::Result<Self> {
 
-        
-        // Load superchunk_data
-        let mut superchunk_data = Vec::new();
-        while file.read_exact(&mut buf).is_ok() {
-            superchunk_data.push(u64::from_le_bytes(buf));
-        }
+        let mut file = BufReader::new(File::open(file_name)?);
+        let rank_support = Self::read_from(bit_vector, &mut file)?;
 
-        // Load chunk_data
-        let mut chunk_data = Vec::new();
-        let mut chunk_buf = [0u8; 2];
-        while file.read_exact(&mut chunk_buf).is_ok() {
-            chunk_data.push(u16::from_le_bytes(chunk_buf));
+        // Anything after the tables means the file doesn't belong to this bit vector
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the rank tables"));
         }
+        Ok(rank_support)
+    }
 
+    // Reads the chunk sizes and the rank tables written by save. The
+    // number of entries in each table follows from the size of the bit vector.
+    pub fn read_from<R: Read>(bit_vector: &'a BitVector, reader: &mut R) -> std::io::Result<Self> {
+        let tables = read_tables(bit_vector.size(), reader)?;
         Ok(Self {
             bit_vector,
-            superchunk_data,
-            chunk_data,
-            chunk_size,
-            superchunk_size,
+            superchunk_data: tables.superchunk_data,
+            chunk_data: tables.chunk_data,
+            chunk_size: tables.chunk_size,
+            superchunk_size: tables.superchunk_size,
         })
     }
 
+    // Checks that the rank tables are the ones its block sizes build for
+    // the bit vector, which catches tables saved for a different bit vector
+    pub fn verify(&self) -> std::io::Result<()> {
+        self.tables().verify()
+    }
+
     pub fn print_chunks_and_super_chunks(&self) {
         println!("Printing superchunks");
 
@@ -219,7 +488,7 @@ impl<'a> RankSupport<'a> {
             let end = cmp::min((1+i)*self.superchunk_size, size);
             println!("start is = {} and end is = {}", start, end);
             println!("SuperBlock i = {} has offset value={}", i, self.superchunk_data[i]);
-            println!("");
+            println!();
 
         }
 
@@ -229,7 +498,7 @@ impl<'a> RankSupport<'a> {
             let end = cmp::min((1+i)*self.chunk_size, size);
             println!("start is = {} and end is = {}", start, end);
             println!("Block i = {} has offset value={}", i, self.chunk_data[i]);
-            println!("");
+            println!();
              
            
         }
@@ -237,4 +506,17 @@ impl<'a> RankSupport<'a> {
     }
 
 
-}
\ No newline at end of file
+}
+
+// The bit vector is only borrowed but it is listed too since the rank
+// support can't answer anything without it
+impl<'a> SpaceUsage for RankSupport<'a> {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("RankSupport", vec![
+            SpaceNode::leaf("header", inline_bits::<RankSupport>()),
+            SpaceNode::leaf("superchunk_data", vec_bits(&self.superchunk_data)),
+            SpaceNode::leaf("chunk_data", vec_bits(&self.chunk_data)),
+            self.bit_vector.space_report().renamed("bit_vector (borrowed)"),
+        ])
+    }
+}
@@ -1,12 +1,12 @@
+use crate::batch::QueryOrder;
 use crate::rank_support::RankSupport;
-use crate::bit_vector::BitVector;
-use std::fs::File;
-use std::io::{Read, Write};
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
 
 pub struct SelectSupport<'a> {
     rank_support: &'a RankSupport<'a>,
 }
 
+const _: fn() = crate::rank_select::assert_shareable::<SelectSupport<'static>>;
 
 impl<'a> SelectSupport<'a> {
     pub fn new(rank_support: &'a RankSupport<'a>) -> Self {
@@ -15,53 +15,29 @@ impl<'a> SelectSupport<'a> {
         }
     }
 
+    // Returns the rank support the select support was built over
+    pub fn rank_support(&self) -> &'a RankSupport<'a> {
+        self.rank_support
+    }
+
     
 
     // Gives position, in the underlying bit-vector, of the FIRST index, j for which rank1(j) = i.
+    // Returns u64::MAX if there are fewer than i ones.
     pub fn select1(&self, i: u64) -> u64 {
-        // Base case we don't want to deal with
-        if i==0 {
-            return 0;
-        }
-
-        let size = self.rank_support.bit_vector_size();
-        let total_num_bits_minus_last = self.rank_support.rank1(size-1);
-        if i > total_num_bits_minus_last {
-            return u64::MAX;
-        }
-        if i == total_num_bits_minus_last {
-            return (size-1) as u64;
-        }
+        // The rank tables narrow it down to one chunk and the one is
+        // found within the chunk with an in-word select
+        self.rank_support.tables().select1(i)
+    }
 
-        let mut start_index = 0;
-        let mut end_index = size-1;
-        let mut guess_index = (start_index + end_index) / 2;
-        let mut guess_value = self.rank_support.rank1(guess_index);
-        loop {
-            // println!("guess value was = {} and the start and end was = {} | {}", guess_value, start_index, end_index);
-            if guess_value >= i {
-                end_index = guess_index;
-            } else {
-                start_index= guess_index;
-            }
-            
-            if (start_index + 1 == guess_index) || (end_index - 1 == guess_index) {
-                if (end_index - 1 == guess_index) {
-                    guess_index += 1;
-                }
-                assert!(self.rank_support.rank1(guess_index) == i);
-                assert!(self.rank_support.rank1(guess_index-1) != i);
-                break;
-            }
-            guess_index = (start_index + end_index) / 2;
-            guess_value = self.rank_support.rank1(guess_index);
-        }
-        return guess_index as u64;
-        
+    // Fills out[k] with select1(ranks[k]), see QueryOrder for when sorting
+    // the ranks first pays off
+    pub fn select1_batch(&self, ranks: &[u64], out: &mut [u64], order: QueryOrder) {
+        self.rank_support.tables().select1_batch(ranks, out, order)
     }
 
-    // Keep the overhead method as is, but note that the 
-    // overhead will be higher due to the select table.
+    // Bits the rank tables take on top of the bit vector. Select is a
+    // binary search over them, so there is no select table to add.
     pub fn overhead(&self) -> u64 {
         self.rank_support.overhead().try_into().unwrap() // + additional overhead for the select_table
     }
@@ -80,7 +56,16 @@ impl<'a> SelectSupport<'a> {
     pub fn load(rank_support: &'a RankSupport) -> std::io::Result<Self> {
         
         Ok( Self {
-            rank_support: rank_support
+            rank_support
         })
     }
-}
\ No newline at end of file
+}
+
+impl<'a> SpaceUsage for SelectSupport<'a> {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("SelectSupport", vec![
+            SpaceNode::leaf("header", inline_bits::<SelectSupport>()),
+            self.rank_support.space_report().renamed("rank_support (borrowed)"),
+        ])
+    }
+}
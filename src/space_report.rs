@@ -0,0 +1,259 @@
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+// One named part of a structure and how many bits it takes.
+// A part with children takes exactly the sum of its children.
+pub struct SpaceNode {
+    pub name: String,
+    pub bits: u64,
+    pub children: Vec<SpaceNode>,
+}
+
+// Structures that can break down the memory they take into named parts.
+// Every part counts the bytes inline in the struct plus what it owns on
+// the heap, by capacity rather than length, so the sizes add up to what
+// the allocator actually handed out.
+pub trait SpaceUsage {
+    fn space_report(&self) -> SpaceNode;
+}
+
+// Bytes a value owns on the heap on top of its inline size, used for the
+// elements of a SparseArray
+pub trait HeapSize {
+    fn heap_bytes(&self) -> usize;
+}
+
+impl HeapSize for String {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * std::mem::size_of::<T>() + self.iter().map(|x| x.heap_bytes()).sum::<usize>()
+    }
+}
+
+macro_rules! impl_heap_size_inline {
+    ($($t:ty),*) => {
+        $(impl HeapSize for $t {
+            fn heap_bytes(&self) -> usize {
+                0
+            }
+        })*
+    };
+}
+
+impl_heap_size_inline!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+// Bits taken by the inline part of a value of type T
+pub fn inline_bits<T>() -> u64 {
+    std::mem::size_of::<T>() as u64 * 8
+}
+
+// Bits taken by the buffer of a Vec<T>
+pub fn vec_bits<T>(vec: &Vec<T>) -> u64 {
+    vec.capacity() as u64 * inline_bits::<T>()
+}
+
+impl SpaceNode {
+    // A part with no children
+    pub fn leaf(name: &str, bits: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            bits,
+            children: Vec::new(),
+        }
+    }
+
+    // A part made up of children, its size is their sum
+    pub fn with_children(name: &str, children: Vec<SpaceNode>) -> Self {
+        let bits = children.iter().map(|child| child.bits).sum();
+        Self {
+            name: name.to_string(),
+            bits,
+            children,
+        }
+    }
+
+    // Same part under a different name, used when a structure is a field of another
+    pub fn renamed(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    // The child called name, if there is one
+    pub fn child(&self, name: &str) -> Option<&SpaceNode> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    // Total bits of the named children, 0 for names that aren't there
+    pub fn children_bits(&self, names: &[&str]) -> u64 {
+        names.iter().filter_map(|name| self.child(name)).map(|child| child.bits).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bits.div_ceil(8)
+    }
+
+    // Indented tree with the bits, bytes and share of the total of every part
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        self.write_text(&mut out, 0, self.bits);
+        out
+    }
+
+    fn write_text(&self, out: &mut String, depth: usize, total_bits: u64) {
+        let label = format!("{}{}", "  ".repeat(depth), self.name);
+        let share = if total_bits == 0 { 0.0 } else { 100.0 * self.bits as f64 / total_bits as f64 };
+        writeln!(out, "{:<40} {:>14} bits {:>12} bytes {:>7.2}%", label, self.bits, self.bytes(), share).unwrap();
+        for child in &self.children {
+            child.write_text(out, depth + 1, total_bits);
+        }
+    }
+
+    // {"name": ..., "bits": ..., "children": [...]} for every part
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        write!(out, "{{\"name\":\"{}\",\"bits\":{},\"children\":[", json_escape(&self.name), self.bits).unwrap();
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            child.write_json(out);
+        }
+        out.push_str("]}");
+    }
+
+    // A standalone HTML page with a sunburst of the parts, the structure
+    // in the middle and every level of children one ring further out.
+    // Hovering a slice shows its name and size. The text report is below it.
+    pub fn to_html(&self) -> String {
+        let radius = 300.0;
+        let ring_width = radius / (self.depth() as f64 + 1.0);
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"{1} {1} {0} {0}\">",
+            2.0 * radius + 20.0,
+            -radius - 10.0
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<circle r=\"{:.2}\" fill=\"#dddddd\" stroke=\"white\"><title>{}</title></circle>",
+            ring_width,
+            html_escape(&self.tooltip(self.bits))
+        )
+        .unwrap();
+        let rings = Rings { ring_width, total_bits: self.bits };
+        let num_parts = self.children.len().max(1) as f64;
+        let mut start = 0.0;
+        for (i, child) in self.children.iter().enumerate() {
+            let span = if self.bits == 0 { 0.0 } else { 2.0 * PI * child.bits as f64 / self.bits as f64 };
+            // Every top level part gets its own hue that its children inherit
+            let hue = 360.0 * i as f64 / num_parts;
+            child.write_arcs(&mut svg, &rings, start, span, 1, hue);
+            start += span;
+        }
+        svg.push_str("</svg>\n");
+
+        let mut out = String::new();
+        writeln!(out, "<!DOCTYPE html>").unwrap();
+        writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+        writeln!(out, "<title>Space usage of {}</title>", html_escape(&self.name)).unwrap();
+        writeln!(out, "</head>\n<body>").unwrap();
+        writeln!(out, "<h1>{}: {} bits ({} bytes)</h1>", html_escape(&self.name), self.bits, self.bytes()).unwrap();
+        out.push_str(&svg);
+        writeln!(out, "<pre>\n{}</pre>", html_escape(&self.to_text())).unwrap();
+        writeln!(out, "</body>\n</html>").unwrap();
+        out
+    }
+
+    // Number of levels of children below this part
+    fn depth(&self) -> usize {
+        self.children.iter().map(|child| child.depth() + 1).max().unwrap_or(0)
+    }
+
+    fn tooltip(&self, total_bits: u64) -> String {
+        let share = if total_bits == 0 { 0.0 } else { 100.0 * self.bits as f64 / total_bits as f64 };
+        format!("{}: {} bits ({} bytes, {:.2}%)", self.name, self.bits, self.bytes(), share)
+    }
+
+    // Draws this part as a slice of ring number level and its children
+    // in the rings further out
+    fn write_arcs(&self, svg: &mut String, rings: &Rings, start: f64, span: f64, level: usize, hue: f64) {
+        if self.bits == 0 {
+            return;
+        }
+        let inner = rings.ring_width * level as f64;
+        let outer = inner + rings.ring_width;
+        // Deeper rings get lighter
+        let lightness = (45.0 + 10.0 * level as f64).min(85.0);
+        writeln!(
+            svg,
+            "<path d=\"{}\" fill=\"hsl({:.0},60%,{:.0}%)\" stroke=\"white\"><title>{}</title></path>",
+            annulus_sector(inner, outer, start, span),
+            hue,
+            lightness,
+            html_escape(&self.tooltip(rings.total_bits))
+        )
+        .unwrap();
+
+        let mut child_start = start;
+        for child in &self.children {
+            let child_span = span * child.bits as f64 / self.bits as f64;
+            child.write_arcs(svg, rings, child_start, child_span, level + 1, hue);
+            child_start += child_span;
+        }
+    }
+}
+
+// How wide every ring of the sunburst is and the size of the whole structure
+struct Rings {
+    ring_width: f64,
+    total_bits: u64,
+}
+
+// SVG path of the part of a ring between radii inner and outer that starts
+// at angle start (radians, clockwise from 12 o'clock) and covers span
+fn annulus_sector(inner: f64, outer: f64, start: f64, span: f64) -> String {
+    // An arc can't start and end at the same point, so a full ring stops just short
+    let span = if span >= 2.0 * PI { 2.0 * PI - 1e-4 } else { span };
+    let end = start + span;
+    let large_arc = if span > PI { 1 } else { 0 };
+    let point = |r: f64, angle: f64| (r * angle.sin(), -r * angle.cos());
+    let (x0, y0) = point(outer, start);
+    let (x1, y1) = point(outer, end);
+    let (x2, y2) = point(inner, end);
+    let (x3, y3) = point(inner, start);
+    format!(
+        "M{:.2},{:.2} A{:.2},{:.2} 0 {} 1 {:.2},{:.2} L{:.2},{:.2} A{:.2},{:.2} 0 {} 0 {:.2},{:.2} Z",
+        x0, y0, outer, outer, large_arc, x1, y1, x2, y2, inner, inner, large_arc, x3, y3
+    )
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
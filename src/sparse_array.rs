@@ -1,436 +1,740 @@
-use std::io::{Read, Write};
-use std::fs::File;
-use crate::rank_support::RankSupport;
-use crate::select_support::SelectSupport;
-use crate::bit_vector::BitVector;
-use crate::utils::my_log;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
 use std::cmp;
-use std::fs::OpenOptions;
-
-pub struct SparseArray  {
-    bit_vector: BitVector,
-    elements: Vec<String>,
-    superchunk_data: Vec<u64>,
-    chunk_data: Vec<u16>,
-    chunk_size: usize,
-    superchunk_size: usize,
-}
-
-
-
-
-
-
-
-
-impl SparseArray {
-    pub fn create(size: u64) -> Self {
-        let size = size as usize;
-        // Create bit vector
-        let bit_vector = BitVector::new(size as usize);
-
-
-        // DO RANK INIT VARIABLES
-
-
-        let log_of_size = my_log(size);
-
-        // The size of a chunk is .5*log(n)
-        let chunk_size: usize = log_of_size/2;
-
-        // The size of a global chunk is (logn)^2
-        let superchunk_size: usize = 2*chunk_size*log_of_size;
-
-        // The superchunk has ranks of celing of n/(logn)^2 entries
-        let mut superchunk_data = vec![0; (size + superchunk_size - 1) / superchunk_size];
-        
-        // The chunk has the ranks of celing of n/(.5logn) entries
-        let mut chunk_data = vec![0; (size + chunk_size - 1) / chunk_size];
-        
-        
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds};
+use crate::batch::{self, QueryOrder};
+use crate::bit_vector::BitVector;
+use crate::position_index::{IndexKind, PlainIndex, PositionIndex};
+use crate::space_report::{inline_bits, vec_bits, HeapSize, SpaceNode, SpaceUsage};
 
+// Pending edits are never merged before there are this many of them
+const MIN_PENDING_EDITS: usize = 64;
 
+pub struct SparseArray<T = String> {
+    index: PositionIndex,
+    elements: Vec<T>,
+    pending: PendingEdits<T>,
+}
 
+const _: fn() = crate::rank_select::assert_shareable::<SparseArray<String>>;
+
+// Elements added or removed after finalize that are not in the index yet.
+// Queries add the pending edits on top of the index, and once there are
+// enough of them they get merged into a freshly built index.
+struct PendingEdits<T> {
+    // Positions the index does not have that now hold an element, sorted,
+    // with their elements in the same order
+    inserted_positions: Vec<u64>,
+    inserted_values: Vec<T>,
+    // Positions the index has whose element was removed, sorted
+    removed_positions: Vec<u64>,
+}
 
+// Where an element lives, either in the pending inserted values or in elements
+enum Slot {
+    Inserted(usize),
+    Indexed(usize),
+}
 
+impl<T> PendingEdits<T> {
+    fn new() -> Self {
+        Self {
+            inserted_positions: Vec::new(),
+            inserted_values: Vec::new(),
+            removed_positions: Vec::new(),
+        }
+    }
 
+    fn len(&self) -> usize {
+        self.inserted_positions.len() + self.removed_positions.len()
+    }
 
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
 
-        // Return the sparse Array
 
+impl<T> SparseArray<T> {
+    pub fn create(size: u64) -> Self {
+        Self::create_with_index(size, IndexKind::Plain)
+    }
 
+    // Same as create but lets you pick how the present positions are stored
+    pub fn create_with_index(size: u64, kind: IndexKind) -> Self {
+        SparseArray {
+            index: PositionIndex::new(size, kind),
+            elements: Vec::new(),
+            pending: PendingEdits::new(),
+        }
+    }
 
+    // Builds a finalized sparse array from a dense sequence where None
+    // marks an empty position. The position index is picked from the
+    // measured density.
+    pub fn from_dense<I: IntoIterator<Item = Option<T>>>(values: I) -> Self {
+        let mut size = 0;
+        let mut positions = Vec::new();
+        let mut elements = Vec::new();
+        for value in values {
+            if let Some(elem) = value {
+                positions.push(size);
+                elements.push(elem);
+            }
+            size += 1;
+        }
+        Self::from_sorted_parts(size, positions, elements)
+    }
 
+    // Builds a finalized sparse array from a dense sequence where the
+    // positions for which is_empty returns true are left out
+    pub fn from_dense_by<I, F>(values: I, mut is_empty: F) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        F: FnMut(&T) -> bool,
+    {
+        Self::from_dense(values.into_iter().map(|elem| if is_empty(&elem) { None } else { Some(elem) }))
+    }
 
+    // Takes the sorted present positions and their elements and picks the
+    // index that takes less space for that density
+    fn from_sorted_parts(size: u64, positions: Vec<u64>, elements: Vec<T>) -> Self {
+        let kind = IndexKind::for_density(size, positions.len() as u64);
         SparseArray {
-            bit_vector,
-            elements: Vec::new(),
-            superchunk_data,
-            chunk_data,
-            chunk_size,
-            superchunk_size,
+            index: PositionIndex::from_sorted_positions(size, positions, kind),
+            elements,
+            pending: PendingEdits::new(),
         }
     }
 
-
+    // Returns which structure stores the present positions
+    pub fn index_kind(&self) -> IndexKind {
+        self.index.kind()
+    }
 
 
     // Assuming the elements are inserted in order
     // Appends the element to the end of the list
-    pub fn append(&mut self, elem: String, pos: u64) {
+    pub fn append(&mut self, elem: T, pos: u64) {
 
-        // Makes a double check that the position is less 
+        // Makes a double check that the position is less
         // than the size just to be sure
-        if pos < self.bit_vector.size().try_into().unwrap() {
-            self.bit_vector.set(pos as usize, true);
+        if pos < self.index.size() {
+            self.index.set(pos);
             self.elements.push(elem);
         }
     }
 
 
-    // // Literally just 
+    // Builds the rank tables once all the elements are appended
     pub fn finalize(&mut self) {
-        
-       // COPY OF RANK INITIALIZATION CODE
-       let size =  self.bit_vector.size();
-
-       // log of size is ALWAYS EVEN
-       let log_of_size = my_log(size);
-
-       // The size of a chunk is .5*log(n)
-       let chunk_size: usize = log_of_size/2;
-
-       // The size of a global chunk is (logn)^2
-       let superchunk_size: usize = 2*chunk_size*log_of_size;
-
-
-       let mut rank = 0;
-       let mut prev_superchunk_rank = 0;
-
-       for i in 0..size {
-           // if it's divisible by size_of_chuck
-           // we add the rank to the global chuck rank
-           
-           // if it's divisible by size of super chunk
-           if i % superchunk_size == 0 {
-               // We need to update the sperchunk's rank
-               self.superchunk_data[i / superchunk_size] = rank;
-               // Keep track of the previous superchunk rank
-               prev_superchunk_rank = rank;
-               // The first entry is 0 for the sub_chuck so we don't need to update anything
-           } else if i % chunk_size == 0 {
-               // If it's divisible by the chunk_size we need to update the chunk_data rank
-               self.chunk_data[i / chunk_size] = (rank - prev_superchunk_rank) as u16;
-           }
-           
-           // Increase the rank by 1 if we see a 1
-           if self.bit_vector.get(i) {
-               rank += 1;
-           }
-           
-       }
-    }
-
-    // This function places a reference to the r-th present 
-    // item in the array in the reference elem. It returns true if 
-    // there was >= r items in the sparse array and false otherwise.
-    // 0 Indexed
-    pub fn get_at_rank(&self, r: u64, s: &mut String) -> bool {
-        if r < self.elements.len() as u64 {
-            *s = self.elements[r as usize].clone();
-            return true;
-        } 
-        return false;
+        self.index.finalize();
     }
 
-    // This function looks at the r-th index in the sparse bitvector; 
-    // if that bit is 1, it fetches the corresponding value and binds 
-    // it to the reference elem and returns true, if that bit is a 0,
-    // it simply returns false.
-    pub fn get_at_index(&self, r: u64, s: &mut String) -> bool {
-        // If there is a 1 there 
-        if self.bit_vector.get(r.try_into().unwrap()) {
-            // take the rank
-            let rank = self.get_rank1(r.try_into().unwrap());
-            // return whether you were able to put it in
-            // may need rank+1
-            return self.get_at_rank(rank, s);
-        }
-        return false;
-       
-    }
-
-    // This function takes as its argument a rank r and 
-    // returns the index in the sparse array where the r-th present element appears
-    pub fn get_index_of(&self, r: u64) -> u64 {
-        if r < self.elements.len().try_into().unwrap() {
-            return self.get_select1(r+1)-1
-        }
-        return u64::MAX;
+    // finalize with the rank tables built by num_threads threads, 0
+    // meaning one per core. The array is the same either way.
+    pub fn finalize_with_threads(&mut self, num_threads: usize) {
+        self.index.finalize_with_threads(num_threads);
     }
 
     // This function returns the count of present elements (1s in the bit vector)
     // up to and including index r (Note: This is just rank on the bitvector,
     // but it is inclusive rather than exclusive of index r).
     pub fn num_elem_at(&self, idx: u64) -> u64 {
+        self.get_rank1(idx.saturating_add(1).min(self.size()) as usize)
+    }
 
-        if idx as usize == self.bit_vector.size()-1 {
-            return self.elements.len().try_into().unwrap();
+    // This function takes as its argument a rank r and
+    // returns the index in the sparse array where the r-th present element appears
+    pub fn get_index_of(&self, r: u64) -> u64 {
+        if r < self.num_elem() {
+            return self.get_select1(r+1)-1
         }
-        return self.get_rank1((idx+1).try_into().unwrap());
+        u64::MAX
     }
 
     // Returns the size of the sparse array.
     pub fn size(&self) -> u64 {
-        self.bit_vector.size().try_into().unwrap()
+        self.index.size()
     }
 
-    // Returns the number of present elements 
+    // Returns the number of present elements
     // in the sparse array (i.e. the number of 1s in the bitvector).
     pub fn num_elem(&self) -> u64 {
-        self.elements.len() as u64
+        (self.elements.len() + self.pending.inserted_positions.len()
+            - self.pending.removed_positions.len()) as u64
     }
 
-
-
-
-
-
-
-
-
-
-
-
-    pub fn get_overhead(&self) -> usize {
-        let bit_vector_size = self.bit_vector.memory_usage();
-        let elements_size = self.elements.iter().map(|s| s.len()).sum::<usize>();
-        let superchunk_data_size = self.superchunk_data.len() * std::mem::size_of::<u64>();
-        let chunk_data_size = self.chunk_data.len() * std::mem::size_of::<u16>();
-        let sparse_array_size = bit_vector_size + elements_size + superchunk_data_size + chunk_data_size;
-        
-        sparse_array_size
+    // Returns whether there is a present element at index
+    pub fn contains(&self, index: u64) -> bool {
+        self.get(index).is_some()
     }
 
-
-
-
-
-
-
-    // Print contents of sparse array for debugging purposes:
-    pub fn print_everything(&self, bv_step_size: usize) {
-        self.bit_vector.print_bit_vector(bv_step_size);
-        for element in &self.elements {
-            println!("{}", element);
+    // Returns a reference to the element at index, None if that position is empty
+    pub fn get(&self, index: u64) -> Option<&T> {
+        match self.locate(index)? {
+            Slot::Inserted(k) => Some(&self.pending.inserted_values[k]),
+            Slot::Indexed(rank) => Some(&self.elements[rank]),
         }
-        println!("Printing contents of the CHUNKS AND SUPER CHUNKS");
-        self.print_chunks_and_super_chunks();
     }
 
+    // Returns a mutable reference to the element at index so it can be
+    // replaced in place, None if that position is empty
+    pub fn get_mut(&mut self, index: u64) -> Option<&mut T> {
+        match self.locate(index)? {
+            Slot::Inserted(k) => Some(&mut self.pending.inserted_values[k]),
+            Slot::Indexed(rank) => Some(&mut self.elements[rank]),
+        }
+    }
 
+    // Fills out[k] with the element at indices[k], None where that position
+    // is empty. Unlike get_at_index nothing is cloned.
+    pub fn get_at_index_batch<'s>(&'s self, indices: &[u64], out: &mut [Option<&'s T>], order: QueryOrder) {
+        batch::check_lengths(indices.len(), out.len());
+        batch::for_each_query(indices, order, |slot, index| out[slot] = self.get(index), |index| self.index.prefetch(index));
+    }
 
+    // Finds where the element at index is stored, None if there is none
+    fn locate(&self, index: u64) -> Option<Slot> {
+        if index >= self.size() {
+            return None;
+        }
+        if let Ok(k) = self.pending.inserted_positions.binary_search(&index) {
+            return Some(Slot::Inserted(k));
+        }
+        if !self.index.get(index) || self.pending.removed_positions.binary_search(&index).is_ok() {
+            return None;
+        }
+        // The index still counts removed elements, so its rank is the
+        // position of the element in elements
+        Some(Slot::Indexed(self.index.rank1(index) as usize))
+    }
 
+    // Puts value at index after the array is finalized. If there already
+    // was an element there it is replaced right away and returned, otherwise
+    // the new position is kept as a pending edit until the next merge.
+    // An index past the size changes nothing and value comes back as the
+    // error, the same way remove returns false there.
+    pub fn set(&mut self, index: u64, value: T) -> Result<Option<T>, T> {
+        if index >= self.size() {
+            return Err(value);
+        }
 
+        if let Ok(k) = self.pending.inserted_positions.binary_search(&index) {
+            return Ok(Some(std::mem::replace(&mut self.pending.inserted_values[k], value)));
+        }
 
+        if self.index.get(index) {
+            let base_rank = self.index.rank1(index) as usize;
+            match self.pending.removed_positions.binary_search(&index) {
+                // The element was removed, so bringing it back only has to
+                // undo the removal and there is no old value to hand back
+                Ok(k) => {
+                    self.pending.removed_positions.remove(k);
+                    self.elements[base_rank] = value;
+                    return Ok(None);
+                }
+                Err(_) => return Ok(Some(std::mem::replace(&mut self.elements[base_rank], value))),
+            }
+        }
 
+        let k = self.pending.inserted_positions.partition_point(|&pos| pos < index);
+        self.pending.inserted_positions.insert(k, index);
+        self.pending.inserted_values.insert(k, value);
+        self.merge_if_full();
+        Ok(None)
+    }
 
+    // Removes the element at index after the array is finalized.
+    // Returns true if there was an element to remove, and false without
+    // changing anything for an empty position or an index past the size.
+    pub fn remove(&mut self, index: u64) -> bool {
+        if index >= self.size() {
+            return false;
+        }
 
+        // An element that never made it into the index is just dropped
+        if let Ok(k) = self.pending.inserted_positions.binary_search(&index) {
+            self.pending.inserted_positions.remove(k);
+            self.pending.inserted_values.remove(k);
+            return true;
+        }
 
+        if !self.index.get(index) {
+            return false;
+        }
+        match self.pending.removed_positions.binary_search(&index) {
+            Ok(_) => false,
+            Err(k) => {
+                self.pending.removed_positions.insert(k, index);
+                self.merge_if_full();
+                true
+            }
+        }
+    }
 
+    // Returns the number of edits that are not merged into the index yet
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
 
+    // Pending edits are merged once there are about sqrt(merge cost) of
+    // them, so every edit pays O(sqrt(n)) amortized for the rebuild and the
+    // sorted insert into the pending lists
+    fn merge_if_full(&mut self) {
+        let merge_cost = match self.index.kind() {
+            IndexKind::Plain => self.size(),
+            IndexKind::SortedList => self.elements.len() as u64,
+        };
+        let limit = cmp::max(MIN_PENDING_EDITS, (merge_cost as f64).sqrt() as usize);
+        if self.pending.len() >= limit {
+            self.merge_pending();
+        }
+    }
 
+    // Rebuilds the index and the elements with every pending edit applied
+    pub fn merge_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
 
+        let num_elem = self.num_elem() as usize;
+        let pending = std::mem::replace(&mut self.pending, PendingEdits::new());
+        let old_elements = std::mem::take(&mut self.elements);
+        let old_positions = self.index.positions();
+
+        let mut positions = Vec::with_capacity(num_elem);
+        let mut elements = Vec::with_capacity(num_elem);
+
+        // Walk the old and the inserted elements in position order, the
+        // two never share a position
+        let mut removed = pending.removed_positions.iter().peekable();
+        let mut inserted = pending.inserted_positions.into_iter()
+            .zip(pending.inserted_values)
+            .peekable();
+        for (pos, elem) in old_positions.into_iter().zip(old_elements) {
+            while let Some((new_pos, new_elem)) = inserted.next_if(|&(new_pos, _)| new_pos < pos) {
+                positions.push(new_pos);
+                elements.push(new_elem);
+            }
+            if removed.next_if(|&&removed_pos| removed_pos == pos).is_none() {
+                positions.push(pos);
+                elements.push(elem);
+            }
+        }
+        for (new_pos, new_elem) in inserted {
+            positions.push(new_pos);
+            elements.push(new_elem);
+        }
 
+        self.index = PositionIndex::from_sorted_positions(self.size(), positions, self.index.kind());
+        self.elements = elements;
+    }
 
 
-
-
-
-
-
-    // COPIED FROM RANK SUPPORT
-
-    pub fn get_rank1(&self, i:usize) -> u64{
-        if i == 0 {
-            return 0;
+    // Returns an iterator over the (index, element) pairs of the present
+    // elements in position order.
+    pub fn iter(&self) -> SparseArrayIter<'_, T> {
+        SparseArrayIter {
+            sparse_array: self,
+            front_rank: 0,
+            back_rank: self.num_elem(),
         }
-        let size =  self.bit_vector.size();
-    
-        let superchunk_rank = self.superchunk_data[i / self.superchunk_size];
-        let chunk_rank = self.chunk_data[i / self.chunk_size] as u64;
-        let base_rank = superchunk_rank + chunk_rank;
-    
-       
-        let mut rank = base_rank;
-        
-        let chunk_local_position = i % self.chunk_size;
-        let chunk_start =  i - chunk_local_position;
-        
-        let chunk_end = cmp::min(chunk_start + self.chunk_size, size);
-    
-        let chunk_as_int = self.bit_vector.interpret_as_u64_int(chunk_start, chunk_end);
-        rank += self.bit_vector.get_i_th_rank(chunk_as_int, chunk_local_position);
-       
-        rank
-
-    }
-
-    
-    pub fn print_chunks_and_super_chunks(&self) {
-        println!("Printing superchunks");
+    }
 
-        let size = self.bit_vector.size();
-        let num_superchunks = self.superchunk_data.len();
-        let num_chunks = self.chunk_data.len();
+    // Returns an iterator over the (index, element) pairs of the present
+    // elements whose index lies in range. The first and last ranks come
+    // from two rank queries and every index from a select query, so we
+    // never scan the empty positions in between.
+    pub fn range<R: RangeBounds<u64>>(&self, range: R) -> SparseArrayIter<'_, T> {
+        let (start, end) = self.resolve_range(range);
+        SparseArrayIter {
+            sparse_array: self,
+            front_rank: self.get_rank1(start as usize),
+            back_rank: self.get_rank1(end as usize),
+        }
+    }
 
+    // Returns the number of present elements whose index lies in range
+    pub fn count_in_range<R: RangeBounds<u64>>(&self, range: R) -> u64 {
+        let (start, end) = self.resolve_range(range);
+        self.get_rank1(end as usize) - self.get_rank1(start as usize)
+    }
 
-        for i in 0..num_superchunks{
+    // Returns the present element with the smallest index
+    pub fn first(&self) -> Option<(u64, &T)> {
+        self.iter().next()
+    }
 
-            let start = i*self.superchunk_size;
-            let end = cmp::min((1+i)*self.superchunk_size, size);
-            println!("start is = {} and end is = {}", start, end);
-            println!("SuperBlock i = {} has offset value={}", i, self.superchunk_data[i]);
-            println!("");
+    // Returns the present element with the largest index
+    pub fn last(&self) -> Option<(u64, &T)> {
+        self.iter().next_back()
+    }
 
+    // Returns the smallest index >= i that holds a present element.
+    // The number of elements before i is the rank of that element.
+    pub fn next_present(&self, i: u64) -> Option<u64> {
+        if i >= self.size() {
+            return None;
+        }
+        let rank = self.get_rank1(i as usize);
+        if rank < self.num_elem() {
+            return Some(self.get_index_of(rank));
         }
+        None
+    }
 
-        println!("Printing offset values for in between chunks");
-        for i in 0..num_chunks{
-            let start = i*self.chunk_size;
-            let end = cmp::min((1+i)*self.chunk_size, size);
-            println!("start is = {} and end is = {}", start, end);
-            println!("Block i = {} has offset value={}", i, self.chunk_data[i]);
-            println!("");
-             
-           
+    // Returns the largest index <= i that holds a present element.
+    // The number of elements up to and including i is one past its rank.
+    pub fn prev_present(&self, i: u64) -> Option<u64> {
+        if self.size() == 0 {
+            return None;
         }
+        let i = cmp::min(i, self.size() - 1);
+        let count = self.num_elem_at(i);
+        if count > 0 {
+            return Some(self.get_index_of(count - 1));
+        }
+        None
+    }
 
+    // Turns any range of indices into a half open [start, end) clamped to the size
+    fn resolve_range<R: RangeBounds<u64>>(&self, range: R) -> (u64, u64) {
+        let size = self.size();
+        let start = match range.start_bound() {
+            Bound::Included(&a) => a,
+            Bound::Excluded(&a) => a.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&b) => b.saturating_add(1),
+            Bound::Excluded(&b) => b,
+            Bound::Unbounded => size,
+        };
+        let end = cmp::min(end, size);
+        (cmp::min(start, end), end)
     }
 
-    // COPIED FROM SELECT SUPPORT
-    
-    // Update the select1 method to use the precomputed
-    // select table for lookups. This will have constant time 
-    // complexity for most cases, depending on the hashmap implementation.
+
+    // Number of 1s in the bit vector before index i, counting the pending
+    // edits as if they were already merged
+    pub fn get_rank1(&self, i:usize) -> u64{
+        let i = i as u64;
+        let inserted_before = self.pending.inserted_positions.partition_point(|&pos| pos < i);
+        let removed_before = self.pending.removed_positions.partition_point(|&pos| pos < i);
+        self.index.rank1(i) + inserted_before as u64 - removed_before as u64
+    }
+
+    // Gives position, in the underlying bit-vector, of the FIRST index, j for which rank1(j) = i.
+    // Returns u64::MAX if there are fewer than i present elements.
     pub fn get_select1(&self, i: u64) -> u64 {
+        if self.pending.is_empty() {
+            return self.index.select1(i);
+        }
+
         // Base case we don't want to deal with
         if i==0 {
             return 0;
         }
-        let size = self.bit_vector.size();
-        let total_num_bits_minus_last = self.get_rank1(size-1);
-        if i > total_num_bits_minus_last {
+        let size = self.size();
+        if i > self.num_elem() {
             return u64::MAX;
         }
-        if i == total_num_bits_minus_last {
-            return (size-1) as u64;
-        }
 
+        // Same binary search as the plain index but over the rank with
+        // the pending edits, the answer is always in (start_index, end_index]
         let mut start_index = 0;
-        let mut end_index = size-1;
-        let mut guess_index = (start_index + end_index) / 2;
-        let mut guess_value = self.get_rank1(guess_index);
-        loop {
-            // println!("guess value was = {} and the start and end was = {} | {}", guess_value, start_index, end_index);
-            if guess_value >= i {
+        let mut end_index = size;
+        while start_index + 1 < end_index {
+            let guess_index = (start_index + end_index) / 2;
+            if self.get_rank1(guess_index as usize) >= i {
                 end_index = guess_index;
             } else {
-                start_index= guess_index;
-            }
-            
-            if (start_index + 1 == guess_index) || (end_index - 1 == guess_index) {
-                if (end_index - 1 == guess_index) {
-                    guess_index += 1;
-                }
-                assert!(self.get_rank1(guess_index) == i);
-                assert!(self.get_rank1(guess_index-1) != i);
-                break;
+                start_index = guess_index;
             }
-            guess_index = (start_index + end_index) / 2;
-            guess_value = self.get_rank1(guess_index);
         }
-        return guess_index as u64;
-        
+        end_index
     }
 
-
-
-    // Saves the sparse array to the file 'fname'.
-    pub fn save(&self, fname: &str) -> std::io::Result<()> {
-        // Save the bit_vector
-        self.bit_vector.save(fname)?;
-
-        // Open the file in append mode
-        let mut file = OpenOptions::new().append(true).open(fname)?;
-
-        // Save the elements
-        let elements_len = self.elements.len();
-        file.write_all(&(elements_len as u64).to_le_bytes())?;
-        for elem in &self.elements {
-            let elem_len = elem.len();
-            file.write_all(&(elem_len as u64).to_le_bytes())?;
-            file.write_all(elem.as_bytes())?;
+    // Returns the index and the element of the r-th present element
+    fn entry_at_rank(&self, r: u64) -> (u64, &T) {
+        let index = self.get_index_of(r);
+        if self.pending.is_empty() {
+            return (index, &self.elements[r as usize]);
         }
+        (index, self.get(index).unwrap())
+    }
 
-
-
-        // Save the chunk_size and superchunk_size
-        file.write_all(&(self.chunk_size as u64).to_le_bytes())?;
-        file.write_all(&(self.superchunk_size as u64).to_le_bytes())?;
-        
-
-        // Save superchunk_data
-        for &value in &self.superchunk_data {
-            file.write_all(&value.to_le_bytes())?;
+    // Checks that the index is consistent and holds one position for every element
+    pub fn verify(&self) -> std::io::Result<()> {
+        if let PositionIndex::Plain(index) = &self.index {
+            index.verify()?;
         }
-
-        // Save chunk_data
-        for &value in &self.chunk_data {
-            file.write_all(&value.to_le_bytes())?;
+        let num_indexed = self.index.rank1(self.index.size());
+        if num_indexed != self.elements.len() as u64 {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the index has {} present positions but there are {} elements", num_indexed, self.elements.len()
+            )));
         }
-
-
-
         Ok(())
+    }
 
+    pub fn print_chunks_and_super_chunks(&self) {
+        match &self.index {
+            PositionIndex::Plain(index) => index.print_chunks_and_super_chunks(),
+            PositionIndex::SortedList(_) => println!("Sorted list index has no chunks"),
+        }
     }
+}
 
-    // Loads the sparse array data structure from the file 'fname'.
-    pub fn load(fname: &str) { //std::io::Result<Self>
-      
 
+impl<T: Clone> SparseArray<T> {
+    // This function places a reference to the r-th present
+    // item in the array in the reference elem. It returns true if
+    // there was >= r items in the sparse array and false otherwise.
+    // 0 Indexed
+    pub fn get_at_rank(&self, r: u64, s: &mut T) -> bool {
+        if r < self.num_elem() {
+            *s = self.entry_at_rank(r).1.clone();
+            return true;
+        }
+        false
+    }
 
+    // This function looks at the r-th index in the sparse bitvector;
+    // if that bit is 1, it fetches the corresponding value and binds
+    // it to the reference elem and returns true, if that bit is a 0,
+    // it simply returns false.
+    pub fn get_at_index(&self, r: u64, s: &mut T) -> bool {
+        // If there is an element there
+        if let Some(elem) = self.get(r) {
+            *s = elem.clone();
+            return true;
+        }
+        false
     }
 
+    // Expands the sparse array into one entry per position with None
+    // for the empty ones
+    pub fn to_dense(&self) -> Vec<Option<T>> {
+        let mut dense: Vec<Option<T>> = (0..self.size()).map(|_| None).collect();
+        for (index, elem) in self.iter() {
+            dense[index as usize] = Some(elem.clone());
+        }
+        dense
+    }
 }
 
 
+impl<T: Display> SparseArray<T> {
+    // Print contents of sparse array for debugging purposes:
+    pub fn print_everything(&self, bv_step_size: usize) {
+        self.index.print_index(bv_step_size);
+        for element in &self.elements {
+            println!("{}", element);
+        }
+        println!("{} pending edits", self.pending.len());
+        println!("Printing contents of the CHUNKS AND SUPER CHUNKS");
+        self.print_chunks_and_super_chunks();
+    }
+}
 
 
+impl<T: HeapSize> SparseArray<T> {
+    // Bytes taken by the index, the elements and the pending edits, the
+    // total of the space report. Every String counts its header and its
+    // whole heap buffer, not just its length, see SparseStrArray for a
+    // layout without either.
+    pub fn get_overhead(&self) -> usize {
+        self.space_report().bytes() as usize
+    }
+}
 
 
+impl SparseArray<String> {
+    // Saves the sparse array to the file 'fname'.
+    // A sorted list index or one with pending edits is written in the same
+    // layout as a plain one with the edits merged.
+    pub fn save(&self, fname: &str) -> std::io::Result<()> {
+        let expanded;
+        let plain = match &self.index {
+            PositionIndex::Plain(index) if self.pending.is_empty() => index,
+            _ => {
+                expanded = PlainIndex::from_positions(self.size(), self.iter().map(|(pos, _)| pos));
+                &expanded
+            }
+        };
 
+        // Save the bit_vector
+        plain.bit_vector.save(fname)?;
 
+        // Open the file in append mode
+        let mut file = OpenOptions::new().append(true).open(fname)?;
 
+        // Save the elements
+        let elements_len = self.num_elem();
+        file.write_all(&elements_len.to_le_bytes())?;
+        for (_, elem) in self.iter() {
+            let elem_len = elem.len();
+            file.write_all(&(elem_len as u64).to_le_bytes())?;
+            file.write_all(elem.as_bytes())?;
+        }
 
+        // Save the chunk sizes and the rank tables
+        plain.write_rank_tables(&mut file)?;
 
+        Ok(())
 
+    }
 
+    // Loads the sparse array data structure from the file 'fname'.
+    // It always comes back with a plain index and no pending edits.
+    pub fn load(fname: &str) -> std::io::Result<Self> {
+        let mut file = BufReader::new(File::open(fname)?);
+
+        // Load the bit_vector
+        let bit_vector = BitVector::read_from(&mut file)?;
+
+        // Load the elements
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf)?;
+        let elements_len = u64::from_le_bytes(buf);
+        let num_ones = bit_vector.count_ones() as u64;
+        if elements_len != num_ones {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "{} elements saved for {} present positions", elements_len, num_ones
+            )));
+        }
+        let mut elements = Vec::with_capacity(elements_len as usize);
+        for i in 0..elements_len {
+            file.read_exact(&mut buf)?;
+            let elem_len = u64::from_le_bytes(buf);
+            // A corrupt length shouldn't allocate everything up front, the
+            // read comes up short first
+            let mut bytes = Vec::new();
+            (&mut file).take(elem_len).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 != elem_len {
+                return Err(Error::new(ErrorKind::InvalidData, format!(
+                    "element {} should have {} bytes but the file ends after {}", i, elem_len, bytes.len()
+                )));
+            }
+            let elem = String::from_utf8(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            elements.push(elem);
+        }
 
+        // Load the chunk sizes and the rank tables
+        let index = PlainIndex::read_rank_tables(bit_vector, &mut file)?;
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the rank tables"));
+        }
 
+        Ok(SparseArray {
+            index: PositionIndex::Plain(index),
+            elements,
+            pending: PendingEdits::new(),
+        })
+    }
+}
 
 
+// The index is inline in the sparse array and reports its own header.
+// Elements are split into the slots of the Vec and what they own on the heap.
+impl<T: HeapSize> SpaceUsage for SparseArray<T> {
+    fn space_report(&self) -> SpaceNode {
+        let heap_bits = |values: &Vec<T>| values.iter().map(|v| v.heap_bytes() as u64 * 8).sum::<u64>();
+        SpaceNode::with_children("SparseArray", vec![
+            SpaceNode::leaf("header", inline_bits::<SparseArray<T>>() - inline_bits::<PositionIndex>()),
+            self.index.space_report().renamed("index"),
+            SpaceNode::with_children("elements", vec![
+                SpaceNode::leaf("slots", vec_bits(&self.elements)),
+                SpaceNode::leaf("heap", heap_bits(&self.elements)),
+            ]),
+            SpaceNode::with_children("pending", vec![
+                SpaceNode::leaf("inserted_positions", vec_bits(&self.pending.inserted_positions)),
+                SpaceNode::leaf("inserted_slots", vec_bits(&self.pending.inserted_values)),
+                SpaceNode::leaf("inserted_heap", heap_bits(&self.pending.inserted_values)),
+                SpaceNode::leaf("removed_positions", vec_bits(&self.pending.removed_positions)),
+            ]),
+        ])
+    }
+}
 
 
+// Collects (index, element) pairs in any order into a finalized sparse
+// array whose size is one past the largest index. If an index shows up
+// more than once the last element wins.
+impl<T> FromIterator<(u64, T)> for SparseArray<T> {
+    fn from_iter<I: IntoIterator<Item = (u64, T)>>(iter: I) -> Self {
+        let mut pairs: Vec<(u64, T)> = iter.into_iter().collect();
+        // Stable so that repeated indices keep their insertion order
+        pairs.sort_by_key(|&(index, _)| index);
+
+        let mut positions: Vec<u64> = Vec::with_capacity(pairs.len());
+        let mut elements: Vec<T> = Vec::with_capacity(pairs.len());
+        for (index, elem) in pairs {
+            if positions.last() == Some(&index) {
+                *elements.last_mut().unwrap() = elem;
+            } else {
+                positions.push(index);
+                elements.push(elem);
+            }
+        }
 
+        let size = positions.last().map_or(0, |&index| index + 1);
+        Self::from_sorted_parts(size, positions, elements)
+    }
+}
 
 
+// Iterates over the present elements of a SparseArray between two ranks.
+// front_rank is the rank of the next element handed out from the front and
+// back_rank is one past the rank of the next element handed out from the back.
+pub struct SparseArrayIter<'a, T = String> {
+    sparse_array: &'a SparseArray<T>,
+    front_rank: u64,
+    back_rank: u64,
+}
 
+impl<'a, T> Iterator for SparseArrayIter<'a, T> {
+    type Item = (u64, &'a T);
 
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front_rank >= self.back_rank {
+            return None;
+        }
+        let rank = self.front_rank;
+        self.front_rank += 1;
+        Some(self.sparse_array.entry_at_rank(rank))
+    }
 
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.back_rank - self.front_rank) as usize;
+        (len, Some(len))
+    }
+}
 
+impl<'a, T> DoubleEndedIterator for SparseArrayIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front_rank >= self.back_rank {
+            return None;
+        }
+        self.back_rank -= 1;
+        let rank = self.back_rank;
+        Some(self.sparse_array.entry_at_rank(rank))
+    }
+}
 
+impl<'a, T> ExactSizeIterator for SparseArrayIter<'a, T> {}
 
+impl<'a, T> FusedIterator for SparseArrayIter<'a, T> {}
 
+impl<'a, T> IntoIterator for &'a SparseArray<T> {
+    type Item = (u64, &'a T);
+    type IntoIter = SparseArrayIter<'a, T>;
 
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
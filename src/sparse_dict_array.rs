@@ -0,0 +1,385 @@
+use crate::batch::{self, QueryOrder};
+use crate::bit_vector::BitVector;
+use crate::packed_ints::{bits_needed, PackedInts};
+use crate::position_index::{IndexKind, PlainIndex, PositionIndex};
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+use crate::sparse_array::SparseArray;
+use crate::string_arena::StringArena;
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+// A sparse array of strings for values that repeat a lot. Every distinct
+// value is stored once in a dictionary and every element only keeps its
+// code, the position of its value in the dictionary, packed into
+// ceil(log2(distinct values)) bits. An array holding the same value
+// everywhere spends no bits at all on its elements.
+//
+// While appending, values get codes in the order they first show up.
+// finalize sorts the dictionary and recodes the elements with the
+// smallest width that fits, and after that nothing can be added.
+pub struct SparseDictArray {
+    index: PositionIndex,
+    dictionary: StringArena,
+    codes: PackedInts,
+    // Finds the code of a value while appending, only kept until finalize
+    building: Option<CodeTable>,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<SparseDictArray>;
+
+// The codes of the values in the dictionary, in an open addressing table
+// placed by the hash of the value. The values themselves are only in the
+// dictionary and a lookup compares against them there, so building
+// doesn't hold a second copy of every distinct value.
+struct CodeTable {
+    // One more than the code, 0 for an empty slot. The length is a power
+    // of two and at most three quarters of the slots are taken.
+    slots: Vec<u64>,
+    hasher: RandomState,
+}
+
+impl CodeTable {
+    fn new() -> Self {
+        CodeTable { slots: Vec::new(), hasher: RandomState::new() }
+    }
+
+    // The code of value, or None after taking the slot for the value the
+    // dictionary is about to push, which gets code dictionary.len()
+    fn find_or_insert(&mut self, value: &str, dictionary: &StringArena) -> Option<u64> {
+        if (dictionary.len() + 1) * 4 > self.slots.len() * 3 {
+            self.grow(dictionary);
+        }
+        let mask = self.slots.len() - 1;
+        let mut slot = self.hasher.hash_one(value) as usize & mask;
+        while self.slots[slot] != 0 {
+            let code = self.slots[slot] - 1;
+            if dictionary.get(code as usize) == value {
+                return Some(code);
+            }
+            slot = (slot + 1) & mask;
+        }
+        self.slots[slot] = dictionary.len() as u64 + 1;
+        None
+    }
+
+    // Doubles the slots and places every code again
+    fn grow(&mut self, dictionary: &StringArena) {
+        let mut slots = vec![0; (self.slots.len() * 2).max(16)];
+        let mask = slots.len() - 1;
+        for code in 0..dictionary.len() {
+            let mut slot = self.hasher.hash_one(dictionary.get(code)) as usize & mask;
+            while slots[slot] != 0 {
+                slot = (slot + 1) & mask;
+            }
+            slots[slot] = code as u64 + 1;
+        }
+        self.slots = slots;
+    }
+}
+
+// How well the dictionary encoding did, from SparseDictArray::stats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DictStats {
+    pub num_elem: u64,
+    pub num_distinct: u64,
+    // Bits of every code
+    pub code_width: usize,
+    // Bytes of the values written out one after another, which is what
+    // a StringArena of them would hold
+    pub value_bytes: u64,
+    // Bytes of the dictionary and the codes, buffers by capacity
+    pub encoded_bytes: u64,
+}
+
+impl DictStats {
+    // value_bytes over encoded_bytes, above 1 when the encoding saves space
+    pub fn compression_ratio(&self) -> f64 {
+        if self.encoded_bytes == 0 {
+            return 1.0;
+        }
+        self.value_bytes as f64 / self.encoded_bytes as f64
+    }
+}
+
+impl SparseDictArray {
+    pub fn create(size: u64) -> Self {
+        Self::create_with_index(size, IndexKind::Plain)
+    }
+
+    pub fn create_with_index(size: u64, kind: IndexKind) -> Self {
+        SparseDictArray {
+            index: PositionIndex::new(size, kind),
+            dictionary: StringArena::new(),
+            codes: PackedInts::new(0),
+            building: Some(CodeTable::new()),
+        }
+    }
+
+    // Encodes the present elements of a sparse array, pending edits
+    // included, keeping its index kind
+    pub fn from_sparse_array(sparse_array: &SparseArray<String>) -> Self {
+        let mut dict_array = Self::create_with_index(0, sparse_array.index_kind());
+        let mut positions = Vec::with_capacity(sparse_array.num_elem() as usize);
+        for (pos, elem) in sparse_array.iter() {
+            positions.push(pos);
+            dict_array.push_value(elem);
+        }
+        dict_array.index = PositionIndex::from_sorted_positions(sparse_array.size(), positions, sparse_array.index_kind());
+        dict_array.encode();
+        dict_array
+    }
+
+    // Assuming the elements are appended in position order, like
+    // SparseArray::append. Positions past the end are ignored.
+    pub fn append(&mut self, elem: &str, pos: u64) {
+        assert!(self.building.is_some(), "can't append to a finalized SparseDictArray");
+        if pos < self.index.size() {
+            self.index.set(pos);
+            self.push_value(elem);
+        }
+    }
+
+    // Looks the value up in the dictionary, adding it if it is new, and
+    // pushes its code
+    fn push_value(&mut self, elem: &str) {
+        let building = self.building.as_mut().unwrap();
+        let code = match building.find_or_insert(elem, &self.dictionary) {
+            Some(code) => code,
+            None => {
+                self.dictionary.push(elem);
+                self.dictionary.len() as u64 - 1
+            }
+        };
+        if bits_needed(code) > self.codes.width() {
+            self.codes.widen(bits_needed(code).max(self.codes.width() * 2).min(64));
+        }
+        self.codes.push(code);
+    }
+
+    // Builds the rank tables and rebuilds the dictionary in sorted order
+    // with the narrowest codes
+    pub fn finalize(&mut self) {
+        self.finalize_with_threads(1);
+    }
+
+    pub fn finalize_with_threads(&mut self, num_threads: usize) {
+        self.index.finalize_with_threads(num_threads);
+        if self.building.is_some() {
+            self.encode();
+        }
+    }
+
+    // Sorts the dictionary, maps every code to the position of its value
+    // in the sorted dictionary and repacks the codes
+    fn encode(&mut self) {
+        self.building = None;
+        let mut order: Vec<u64> = (0..self.dictionary.len() as u64).collect();
+        order.sort_unstable_by_key(|&code| self.dictionary.get(code as usize));
+
+        let mut new_code = vec![0; order.len()];
+        for (sorted, &code) in order.iter().enumerate() {
+            new_code[code as usize] = sorted as u64;
+        }
+        let mut codes = PackedInts::new(Self::code_width(order.len()));
+        for code in self.codes.iter() {
+            codes.push(new_code[code as usize]);
+        }
+        codes.shrink_to_fit();
+
+        self.dictionary = StringArena::from_strs(order.iter().map(|&code| self.dictionary.get(code as usize)));
+        self.codes = codes;
+    }
+
+    // ceil(log2(num_distinct)) bits tell that many values apart
+    fn code_width(num_distinct: usize) -> usize {
+        bits_needed((num_distinct as u64).saturating_sub(1))
+    }
+
+    pub fn index_kind(&self) -> IndexKind {
+        self.index.kind()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.index.size()
+    }
+
+    pub fn num_elem(&self) -> u64 {
+        self.codes.len() as u64
+    }
+
+    // Number of different values, the length of the dictionary
+    pub fn num_distinct(&self) -> u64 {
+        self.dictionary.len() as u64
+    }
+
+    pub fn contains(&self, index: u64) -> bool {
+        index < self.size() && self.index.get(index)
+    }
+
+    // The value at index, None if that position is empty
+    pub fn get(&self, index: u64) -> Option<&str> {
+        if !self.contains(index) {
+            return None;
+        }
+        self.get_at_rank(self.index.rank1(index))
+    }
+
+    // The value of the r-th present element, 0 indexed
+    pub fn get_at_rank(&self, r: u64) -> Option<&str> {
+        if r < self.num_elem() {
+            return Some(self.dictionary.get(self.codes.get(r as usize) as usize));
+        }
+        None
+    }
+
+    // Number of present elements before index i
+    pub fn get_rank1(&self, i: usize) -> u64 {
+        self.index.rank1(i as u64)
+    }
+
+    // Number of present elements up to and including idx
+    pub fn num_elem_at(&self, idx: u64) -> u64 {
+        self.index.rank1(idx.saturating_add(1).min(self.size()))
+    }
+
+    // The index of the r-th present element, u64::MAX if there is none
+    pub fn get_index_of(&self, r: u64) -> u64 {
+        if r < self.num_elem() {
+            return self.index.select1(r + 1) - 1;
+        }
+        u64::MAX
+    }
+
+    // The (index, value) pairs of the present elements in position order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (u64, &str)> + ExactSizeIterator + '_ {
+        (0..self.codes.len()).map(|r| (self.get_index_of(r as u64), self.dictionary.get(self.codes.get(r) as usize)))
+    }
+
+    // The distinct values, sorted once the array is finalized
+    pub fn dictionary(&self) -> impl Iterator<Item = &str> + '_ {
+        self.dictionary.iter()
+    }
+
+    // Fills out[k] with the value at indices[k], None where that position
+    // is empty
+    pub fn get_at_index_batch<'s>(&'s self, indices: &[u64], out: &mut [Option<&'s str>], order: QueryOrder) {
+        batch::check_lengths(indices.len(), out.len());
+        batch::for_each_query(indices, order, |slot, index| out[slot] = self.get(index), |index| self.index.prefetch(index));
+    }
+
+    // Bytes taken by the index, the dictionary and the codes, the total of
+    // the space report
+    pub fn get_overhead(&self) -> usize {
+        self.space_report().bytes() as usize
+    }
+
+    fn encoded_bytes(&self) -> u64 {
+        (self.dictionary.space_report().bits + self.codes.space_report().bits) / 8
+    }
+
+    pub fn stats(&self) -> DictStats {
+        let value_bytes = self.codes.iter().map(|code| self.dictionary.get(code as usize).len() as u64).sum();
+        DictStats {
+            num_elem: self.num_elem(),
+            num_distinct: self.num_distinct(),
+            code_width: self.codes.width(),
+            value_bytes,
+            encoded_bytes: self.encoded_bytes(),
+        }
+    }
+
+    // Saves the index as a plain bit vector and its rank tables, followed
+    // by the dictionary and the codes. Has to be finalized first.
+    pub fn save(&self, fname: &str) -> std::io::Result<()> {
+        if self.building.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "the array has to be finalized before it is saved"));
+        }
+        let expanded;
+        let plain = match &self.index {
+            PositionIndex::Plain(index) => index,
+            PositionIndex::SortedList(_) => {
+                expanded = PlainIndex::from_positions(self.size(), self.index.positions());
+                &expanded
+            }
+        };
+
+        let mut file = BufWriter::new(File::create(fname)?);
+        plain.bit_vector.write_to(&mut file)?;
+        plain.write_rank_tables(&mut file)?;
+        self.dictionary.write_to(&mut file)?;
+        self.codes.write_to(&mut file)?;
+        file.flush()
+    }
+
+    // Loads a file written by save, always with a plain index
+    pub fn load(fname: &str) -> std::io::Result<Self> {
+        let mut file = BufReader::new(File::open(fname)?);
+        let bit_vector = BitVector::read_from(&mut file)?;
+        let index = PlainIndex::read_rank_tables(bit_vector, &mut file)?;
+        let dictionary = StringArena::read_from(&mut file)?;
+        let codes = PackedInts::read_from(&mut file)?;
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the codes"));
+        }
+
+        let dict_array = SparseDictArray {
+            index: PositionIndex::Plain(index),
+            dictionary,
+            codes,
+            building: None,
+        };
+        dict_array.verify()?;
+        Ok(dict_array)
+    }
+
+    // Checks the index, that there is a code for every present position,
+    // and once finalized that the dictionary is sorted without repeats and
+    // the codes are as narrow as they can be and all in it
+    pub fn verify(&self) -> std::io::Result<()> {
+        if let PositionIndex::Plain(index) = &self.index {
+            index.verify()?;
+        }
+        self.dictionary.verify()?;
+        let num_indexed = self.index.rank1(self.index.size());
+        if num_indexed != self.num_elem() {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the index has {} present positions but there are {} codes", num_indexed, self.num_elem()
+            )));
+        }
+        if self.building.is_some() {
+            return Ok(());
+        }
+
+        let num_distinct = self.dictionary.len();
+        if (1..num_distinct).any(|i| self.dictionary.get(i - 1) >= self.dictionary.get(i)) {
+            return Err(Error::new(ErrorKind::InvalidData, "the dictionary is not sorted or has repeated values"));
+        }
+        if self.codes.width() != Self::code_width(num_distinct) {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "codes take {} bits but {} distinct values need {}", self.codes.width(), num_distinct, Self::code_width(num_distinct)
+            )));
+        }
+        if let Some(code) = self.codes.iter().find(|&code| code >= num_distinct as u64) {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "code {} is past the {} values of the dictionary", code, num_distinct
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl SpaceUsage for SparseDictArray {
+    fn space_report(&self) -> SpaceNode {
+        let building_bits = self.building.as_ref().map_or(0, |building| building.slots.capacity() as u64 * 64);
+        SpaceNode::with_children("SparseDictArray", vec![
+            SpaceNode::leaf("header", inline_bits::<SparseDictArray>() - inline_bits::<PositionIndex>()
+                - inline_bits::<StringArena>() - inline_bits::<PackedInts>()),
+            self.index.space_report().renamed("index"),
+            self.dictionary.space_report().renamed("dictionary"),
+            self.codes.space_report().renamed("codes"),
+            SpaceNode::leaf("building", building_bits),
+        ])
+    }
+}
@@ -0,0 +1,342 @@
+use crate::bit_vector::BitVector;
+use crate::packed_ints::{bits_needed, PackedInts};
+use crate::rank_select::RankSelect;
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
+use crate::sparse_vector::Scalar;
+use crate::text_formats::{
+    line_error, parse_matrix_market_index, parse_matrix_market_value, read_matrix_market_header, read_matrix_market_sizes, DataLines,
+};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+// A sparse matrix in compressed sparse row form. The nonzero entries are
+// stored row after row, each row sorted by column, as a packed array of
+// columns and an array of values.
+//
+// Where every row starts is kept in a unary bit vector with a 0 for every
+// entry and a 1 closing every row, so it takes nnz + num_rows bits. Row r
+// starts after the r-th 1, which select1 finds, and the entries before
+// it are the 0s before it: select1(r) - r.
+pub struct SparseMatrix<T> {
+    num_rows: u64,
+    num_cols: u64,
+    row_ends: RankSelect,
+    columns: PackedInts,
+    values: Vec<T>,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<SparseMatrix<f64>>;
+
+impl<T: Scalar> SparseMatrix<T> {
+    // Builds the matrix from (row, column, value) entries in any order.
+    // Entries at the same place are added up and zeros are left out.
+    pub fn from_triplets<I: IntoIterator<Item = (u64, u64, T)>>(num_rows: u64, num_cols: u64, triplets: I) -> Self {
+        let mut entries: Vec<(u64, u64, T)> = triplets.into_iter().collect();
+        for &(r, c, _) in &entries {
+            assert!(r < num_rows && c < num_cols, "entry ({}, {}) is outside a {} by {} matrix", r, c, num_rows, num_cols);
+        }
+        entries.sort_by_key(|&(r, c, _)| (r, c));
+
+        let mut merged: Vec<(u64, u64, T)> = Vec::with_capacity(entries.len());
+        for (r, c, value) in entries {
+            match merged.last_mut() {
+                Some(last) if (last.0, last.1) == (r, c) => last.2 = last.2 + value,
+                _ => merged.push((r, c, value)),
+            }
+        }
+        merged.retain(|&(_, _, value)| value != T::zero());
+        Self::from_sorted_entries(num_rows, num_cols, &merged)
+    }
+
+    // The entries are sorted by row and column, with no repeats and no zeros
+    fn from_sorted_entries(num_rows: u64, num_cols: u64, entries: &[(u64, u64, T)]) -> Self {
+        let mut bit_vector = BitVector::new(entries.len() + num_rows as usize);
+        let mut k = 0;
+        for r in 0..num_rows {
+            while k < entries.len() && entries[k].0 == r {
+                k += 1;
+            }
+            bit_vector.set(k + r as usize, true);
+        }
+
+        let mut columns = PackedInts::new(Self::column_width(num_cols));
+        for &(_, c, _) in entries {
+            columns.push(c);
+        }
+        columns.shrink_to_fit();
+
+        SparseMatrix {
+            num_rows,
+            num_cols,
+            row_ends: RankSelect::new(bit_vector),
+            columns,
+            values: entries.iter().map(|&(_, _, value)| value).collect(),
+        }
+    }
+
+    fn column_width(num_cols: u64) -> usize {
+        bits_needed(num_cols.saturating_sub(1))
+    }
+
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+
+    pub fn num_cols(&self) -> u64 {
+        self.num_cols
+    }
+
+    // Number of stored, nonzero, entries
+    pub fn num_nonzero(&self) -> u64 {
+        self.values.len() as u64
+    }
+
+    // Where the entries of row r start and end in columns and values
+    fn row_range(&self, r: u64) -> (usize, usize) {
+        assert!(r < self.num_rows, "row {} out of bounds for {} rows", r, self.num_rows);
+        let start = self.row_ends.select1(r) - r;
+        let end = self.row_ends.select1(r + 1) - (r + 1);
+        (start as usize, end as usize)
+    }
+
+    pub fn row_nonzero(&self, r: u64) -> u64 {
+        let (start, end) = self.row_range(r);
+        (end - start) as u64
+    }
+
+    // The (column, value) pairs of row r in column order
+    pub fn row(&self, r: u64) -> impl Iterator<Item = (u64, T)> + '_ {
+        let (start, end) = self.row_range(r);
+        (start..end).map(|k| (self.columns.get(k), self.values[k]))
+    }
+
+    // The (row, column, value) triplets of every entry, row by row
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64, T)> + '_ {
+        (0..self.num_rows).flat_map(move |r| self.row(r).map(move |(c, value)| (r, c, value)))
+    }
+
+    // The entry at row r and column c, zero if it is not stored. Binary
+    // searches the columns of the row.
+    pub fn get(&self, r: u64, c: u64) -> T {
+        if r >= self.num_rows || c >= self.num_cols {
+            return T::zero();
+        }
+        let (mut start, mut end) = self.row_range(r);
+        while start < end {
+            let mid = (start + end) / 2;
+            let mid_col = self.columns.get(mid);
+            if mid_col == c {
+                return self.values[mid];
+            } else if mid_col < c {
+                start = mid + 1;
+            } else {
+                end = mid;
+            }
+        }
+        T::zero()
+    }
+
+    // The matrix times the dense vector x, which has one entry per column
+    pub fn matvec(&self, x: &[T]) -> Vec<T> {
+        assert_eq!(x.len() as u64, self.num_cols, "the vector needs one entry per column");
+        (0..self.num_rows)
+            .map(|r| self.row(r).fold(T::zero(), |sum, (c, value)| sum + value * x[c as usize]))
+            .collect()
+    }
+
+    pub fn transpose(&self) -> SparseMatrix<T> {
+        // The rows come out in order, so a stable sort by column keeps the
+        // rows of every column sorted
+        let mut entries: Vec<(u64, u64, T)> = self.iter().map(|(r, c, value)| (c, r, value)).collect();
+        entries.sort_by_key(|&(c, _, _)| c);
+        Self::from_sorted_entries(self.num_cols, self.num_rows, &entries)
+    }
+
+    // The entries as a dense row-major array, for small matrices
+    pub fn to_dense(&self) -> Vec<Vec<T>> {
+        let mut dense = vec![vec![T::zero(); self.num_cols as usize]; self.num_rows as usize];
+        for (r, c, value) in self.iter() {
+            dense[r as usize][c as usize] = value;
+        }
+        dense
+    }
+
+    // Saves the matrix in the crate's binary format
+    pub fn save(&self, fname: &str) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(fname)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    // The shape, the type of the values, the row ends with their rank
+    // tables, the columns and then the values in one write
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.num_rows.to_le_bytes())?;
+        writer.write_all(&self.num_cols.to_le_bytes())?;
+        writer.write_all(&type_tag::<T>())?;
+        self.row_ends.write_to(writer)?;
+        self.columns.write_to(writer)?;
+        let mut bytes = Vec::with_capacity(self.values.len() * T::BYTES);
+        for &value in &self.values {
+            value.write_le(&mut bytes);
+        }
+        writer.write_all(&bytes)
+    }
+
+    pub fn load(fname: &str) -> std::io::Result<Self> {
+        let mut file = BufReader::new(File::open(fname)?);
+        let matrix = Self::read_from(&mut file)?;
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the matrix"));
+        }
+        Ok(matrix)
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        let num_rows = u64::from_le_bytes(buf);
+        reader.read_exact(&mut buf)?;
+        let num_cols = u64::from_le_bytes(buf);
+        reader.read_exact(&mut buf)?;
+        if buf != type_tag::<T>() {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the matrix holds {} values, not {}", String::from_utf8_lossy(&buf).trim_end_matches('\0'), T::NAME
+            )));
+        }
+        let row_ends = RankSelect::read_from(reader)?;
+        let columns = PackedInts::read_from(reader)?;
+
+        // A corrupt count shouldn't allocate everything up front, the read
+        // comes up short first
+        let num_bytes = columns.len() as u64 * T::BYTES as u64;
+        let mut bytes = Vec::new();
+        reader.take(num_bytes).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != num_bytes {
+            return Err(Error::new(ErrorKind::UnexpectedEof, format!(
+                "expected {} bytes of values but found {}", num_bytes, bytes.len()
+            )));
+        }
+        let values = bytes.chunks_exact(T::BYTES).map(T::read_le).collect();
+
+        let matrix = SparseMatrix { num_rows, num_cols, row_ends, columns, values };
+        matrix.verify()?;
+        Ok(matrix)
+    }
+
+    // Checks that the row ends close every row and account for every
+    // entry, and that the columns of every row are increasing, in range
+    // and hold nonzero values
+    pub fn verify(&self) -> std::io::Result<()> {
+        self.row_ends.verify()?;
+        let nnz = self.values.len() as u64;
+        if self.columns.len() as u64 != nnz {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "{} columns for {} values", self.columns.len(), nnz
+            )));
+        }
+        if self.row_ends.size() as u64 != nnz + self.num_rows || self.row_ends.num_ones() != self.num_rows {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the row ends have {} bits and {} rows, not {} and {}",
+                self.row_ends.size(), self.row_ends.num_ones(), nnz + self.num_rows, self.num_rows
+            )));
+        }
+        if self.columns.width() != Self::column_width(self.num_cols) {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "columns take {} bits but {} columns need {}", self.columns.width(), self.num_cols, Self::column_width(self.num_cols)
+            )));
+        }
+        for r in 0..self.num_rows {
+            let mut prev = None;
+            for (c, value) in self.row(r) {
+                if c >= self.num_cols || prev.is_some_and(|prev| prev >= c) || value == T::zero() {
+                    return Err(Error::new(ErrorKind::InvalidData, format!(
+                        "entry ({}, {}) is out of order, out of range or zero", r, c
+                    )));
+                }
+                prev = Some(c);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_matrix_market(fname: &str) -> std::io::Result<Self> {
+        Self::read_matrix_market(BufReader::new(File::open(fname)?))
+    }
+
+    // Reads a Matrix Market coordinate file. Real, integer and pattern
+    // fields and general and symmetric matrices are supported. Errors name
+    // the line they were found on.
+    pub fn read_matrix_market<R: BufRead>(mut reader: R) -> std::io::Result<Self> {
+        let (pattern, symmetric) = read_matrix_market_header(&mut reader, "matrix")?;
+        // Comments and blank lines can come anywhere after the header
+        let mut lines = DataLines::new(reader, '%', 1);
+        let (line_number, sizes) = read_matrix_market_sizes(&mut lines, 3, "the number of rows, columns and entries")?;
+        let (num_rows, num_cols, num_entries) = (sizes[0], sizes[1], sizes[2]);
+        if symmetric && num_rows != num_cols {
+            return Err(line_error(line_number, "a symmetric matrix has to be square"));
+        }
+
+        let mut triplets = Vec::new();
+        let mut num_read = 0;
+        for line in lines {
+            let (line_number, line) = line?;
+            num_read += 1;
+            if num_read > num_entries {
+                return Err(line_error(line_number, format!("more than the {} entries the size line gives", num_entries)));
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let expected_words = if pattern { 2 } else { 3 };
+            if words.len() != expected_words {
+                return Err(line_error(line_number, format!("expected {} fields but found {}", expected_words, words.len())));
+            }
+            let r = parse_matrix_market_index(line_number, words[0], num_rows, "row")?;
+            let c = parse_matrix_market_index(line_number, words[1], num_cols, "column")?;
+            let value: T = parse_matrix_market_value(line_number, words.get(2).copied())?;
+            triplets.push((r, c, value));
+            if symmetric && r != c {
+                triplets.push((c, r, value));
+            }
+        }
+        if num_read != num_entries {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the size line gives {} entries but there are {}", num_entries, num_read
+            )));
+        }
+        Ok(Self::from_triplets(num_rows, num_cols, triplets))
+    }
+
+    pub fn save_matrix_market(&self, fname: &str) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(fname)?);
+        self.write_matrix_market(&mut file)?;
+        file.flush()
+    }
+
+    // Writes a general coordinate Matrix Market file with 1 based indices
+    pub fn write_matrix_market<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(writer, "%%MatrixMarket matrix coordinate {} general", T::MATRIX_MARKET_FIELD)?;
+        writeln!(writer, "{} {} {}", self.num_rows, self.num_cols, self.num_nonzero())?;
+        for (r, c, value) in self.iter() {
+            writeln!(writer, "{} {} {}", r + 1, c + 1, value)?;
+        }
+        Ok(())
+    }
+}
+
+// The name of the value type padded to 8 bytes
+fn type_tag<T: Scalar>() -> [u8; 8] {
+    let mut tag = [0u8; 8];
+    tag[..T::NAME.len()].copy_from_slice(T::NAME.as_bytes());
+    tag
+}
+
+impl<T> SpaceUsage for SparseMatrix<T> {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("SparseMatrix", vec![
+            SpaceNode::leaf("header", inline_bits::<SparseMatrix<T>>() - inline_bits::<RankSelect>() - inline_bits::<PackedInts>()),
+            self.row_ends.space_report().renamed("row_ends"),
+            self.columns.space_report().renamed("columns"),
+            SpaceNode::leaf("values", vec_bits(&self.values)),
+        ])
+    }
+}
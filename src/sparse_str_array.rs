@@ -0,0 +1,204 @@
+use crate::batch::{self, QueryOrder};
+use crate::bit_vector::BitVector;
+use crate::position_index::{IndexKind, PlainIndex, PositionIndex};
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+use crate::sparse_array::SparseArray;
+use crate::string_arena::StringArena;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+// A sparse array of strings that keeps every value in one StringArena
+// instead of a String each. Values are handed out as &str straight from
+// the arena, so nothing is cloned on access.
+//
+// The arena only grows at the end, so there is no set or remove after
+// finalize. Edit a SparseArray<String> and convert it with
+// from_sparse_array instead.
+pub struct SparseStrArray {
+    index: PositionIndex,
+    values: StringArena,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<SparseStrArray>;
+
+impl SparseStrArray {
+    pub fn create(size: u64) -> Self {
+        Self::create_with_index(size, IndexKind::Plain)
+    }
+
+    pub fn create_with_index(size: u64, kind: IndexKind) -> Self {
+        SparseStrArray {
+            index: PositionIndex::new(size, kind),
+            values: StringArena::new(),
+        }
+    }
+
+    // Copies the present elements of a sparse array, pending edits
+    // included, keeping its index kind
+    pub fn from_sparse_array(sparse_array: &SparseArray<String>) -> Self {
+        let mut positions = Vec::with_capacity(sparse_array.num_elem() as usize);
+        let mut values = StringArena::new();
+        for (pos, elem) in sparse_array.iter() {
+            positions.push(pos);
+            values.push(elem);
+        }
+        values.shrink_to_fit();
+        SparseStrArray {
+            index: PositionIndex::from_sorted_positions(sparse_array.size(), positions, sparse_array.index_kind()),
+            values,
+        }
+    }
+
+    // Assuming the elements are appended in position order, like
+    // SparseArray::append. Positions past the end are ignored.
+    pub fn append(&mut self, elem: &str, pos: u64) {
+        if pos < self.index.size() {
+            self.index.set(pos);
+            self.values.push(elem);
+        }
+    }
+
+    // Builds the rank tables and gives back the spare room in the arena
+    pub fn finalize(&mut self) {
+        self.finalize_with_threads(1);
+    }
+
+    pub fn finalize_with_threads(&mut self, num_threads: usize) {
+        self.index.finalize_with_threads(num_threads);
+        self.values.shrink_to_fit();
+    }
+
+    pub fn index_kind(&self) -> IndexKind {
+        self.index.kind()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.index.size()
+    }
+
+    pub fn num_elem(&self) -> u64 {
+        self.values.len() as u64
+    }
+
+    pub fn contains(&self, index: u64) -> bool {
+        index < self.size() && self.index.get(index)
+    }
+
+    // The value at index, None if that position is empty
+    pub fn get(&self, index: u64) -> Option<&str> {
+        if !self.contains(index) {
+            return None;
+        }
+        Some(self.values.get(self.index.rank1(index) as usize))
+    }
+
+    // The value of the r-th present element, 0 indexed
+    pub fn get_at_rank(&self, r: u64) -> Option<&str> {
+        if r < self.num_elem() {
+            return Some(self.values.get(r as usize));
+        }
+        None
+    }
+
+    // Number of present elements before index i
+    pub fn get_rank1(&self, i: usize) -> u64 {
+        self.index.rank1(i as u64)
+    }
+
+    // Number of present elements up to and including idx
+    pub fn num_elem_at(&self, idx: u64) -> u64 {
+        self.index.rank1(idx.saturating_add(1).min(self.size()))
+    }
+
+    // The index of the r-th present element, u64::MAX if there is none
+    pub fn get_index_of(&self, r: u64) -> u64 {
+        if r < self.num_elem() {
+            return self.index.select1(r + 1) - 1;
+        }
+        u64::MAX
+    }
+
+    // The (index, value) pairs of the present elements in position order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (u64, &str)> + ExactSizeIterator + '_ {
+        (0..self.values.len()).map(|r| (self.get_index_of(r as u64), self.values.get(r)))
+    }
+
+    // Fills out[k] with the value at indices[k], None where that position
+    // is empty
+    pub fn get_at_index_batch<'s>(&'s self, indices: &[u64], out: &mut [Option<&'s str>], order: QueryOrder) {
+        batch::check_lengths(indices.len(), out.len());
+        batch::for_each_query(indices, order, |slot, index| out[slot] = self.get(index), |index| self.index.prefetch(index));
+    }
+
+    // Bytes taken by the index and the arena, the total of the space report
+    pub fn get_overhead(&self) -> usize {
+        self.space_report().bytes() as usize
+    }
+
+    // Saves the index as a plain bit vector and its rank tables, followed
+    // by the arena, whose buffer goes out in a single write
+    pub fn save(&self, fname: &str) -> std::io::Result<()> {
+        let expanded;
+        let plain = match &self.index {
+            PositionIndex::Plain(index) => index,
+            PositionIndex::SortedList(_) => {
+                expanded = PlainIndex::from_positions(self.size(), self.index.positions());
+                &expanded
+            }
+        };
+
+        let mut file = BufWriter::new(File::create(fname)?);
+        plain.bit_vector.write_to(&mut file)?;
+        plain.write_rank_tables(&mut file)?;
+        self.values.write_to(&mut file)?;
+        file.flush()
+    }
+
+    // Loads a file written by save, always with a plain index
+    pub fn load(fname: &str) -> std::io::Result<Self> {
+        let mut file = BufReader::new(File::open(fname)?);
+        let bit_vector = BitVector::read_from(&mut file)?;
+        let index = PlainIndex::read_rank_tables(bit_vector, &mut file)?;
+        let values = StringArena::read_from(&mut file)?;
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the strings"));
+        }
+
+        let num_present = index.rank1(index.bit_vector.size());
+        if values.len() as u64 != num_present {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "{} strings saved for {} present positions", values.len(), num_present
+            )));
+        }
+        Ok(SparseStrArray {
+            index: PositionIndex::Plain(index),
+            values,
+        })
+    }
+
+    // Checks the index, the arena and that there is one string for every
+    // present position
+    pub fn verify(&self) -> std::io::Result<()> {
+        if let PositionIndex::Plain(index) = &self.index {
+            index.verify()?;
+        }
+        self.values.verify()?;
+        let num_indexed = self.index.rank1(self.index.size());
+        if num_indexed != self.num_elem() {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the index has {} present positions but there are {} strings", num_indexed, self.num_elem()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl SpaceUsage for SparseStrArray {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("SparseStrArray", vec![
+            SpaceNode::leaf("header", inline_bits::<SparseStrArray>() - inline_bits::<PositionIndex>() - inline_bits::<StringArena>()),
+            self.index.space_report().renamed("index"),
+            self.values.space_report().renamed("values"),
+        ])
+    }
+}
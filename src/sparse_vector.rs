@@ -0,0 +1,295 @@
+use crate::position_index::{IndexKind, PositionIndex};
+use crate::space_report::{inline_bits, vec_bits, SpaceNode, SpaceUsage};
+use std::fmt::Display;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+// The numbers a SparseVector can hold. Zero is the value of every
+// position that is not stored. Integer arithmetic overflows the same way
+// it does on plain integers.
+pub trait Scalar: Copy + PartialEq + Default + Display + FromStr + Add<Output = Self> + Mul<Output = Self> {
+    // Name of the type, saved in files so they are read back as the same type
+    const NAME: &'static str;
+    // Bytes of one value in a saved file
+    const BYTES: usize;
+    // The field of a Matrix Market file with values of this type
+    const MATRIX_MARKET_FIELD: &'static str;
+
+    fn zero() -> Self {
+        Self::default()
+    }
+
+    // The value as an f64, for the norms
+    fn to_f64(self) -> f64;
+
+    // Appends the value in little endian order
+    fn write_le(self, bytes: &mut Vec<u8>);
+
+    // Reads back a value written by write_le, bytes is BYTES long
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($field:literal: $($t:ty),*) => {
+        $(impl Scalar for $t {
+            const NAME: &'static str = stringify!($t);
+            const BYTES: usize = std::mem::size_of::<$t>();
+            const MATRIX_MARKET_FIELD: &'static str = $field;
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn write_le(self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().unwrap())
+            }
+        })*
+    };
+}
+
+impl_scalar!("real": f32, f64);
+impl_scalar!("integer": i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+// A vector of numbers where most entries are zero, stored like a
+// SparseArray: a PositionIndex of the nonzero positions and their values
+// in position order.
+//
+// Operations between two vectors walk both position lists at once, so
+// they take time in the number of stored entries rather than the size.
+// Entries that come out as zero are left out of the result.
+pub struct SparseVector<T> {
+    index: PositionIndex,
+    values: Vec<T>,
+}
+
+const _: fn() = crate::rank_select::assert_shareable::<SparseVector<f64>>;
+
+impl<T: Scalar> SparseVector<T> {
+    pub fn create(size: u64) -> Self {
+        Self::create_with_index(size, IndexKind::Plain)
+    }
+
+    pub fn create_with_index(size: u64, kind: IndexKind) -> Self {
+        SparseVector {
+            index: PositionIndex::new(size, kind),
+            values: Vec::new(),
+        }
+    }
+
+    // Keeps the nonzero entries of a dense slice, with the index picked
+    // from the density
+    pub fn from_dense(dense: &[T]) -> Self {
+        let mut positions = Vec::new();
+        let mut values = Vec::new();
+        for (i, &value) in dense.iter().enumerate() {
+            if value != T::zero() {
+                positions.push(i as u64);
+                values.push(value);
+            }
+        }
+        Self::from_sorted_parts(dense.len() as u64, positions, values)
+    }
+
+    // Builds the vector from (position, value) entries in any order.
+    // Entries at the same position are added up and zeros are left out.
+    pub fn from_entries<I: IntoIterator<Item = (u64, T)>>(size: u64, entries: I) -> Self {
+        let mut entries: Vec<(u64, T)> = entries.into_iter().collect();
+        for &(pos, _) in &entries {
+            assert!(pos < size, "position {} out of bounds for size {}", pos, size);
+        }
+        entries.sort_by_key(|&(pos, _)| pos);
+
+        let mut positions: Vec<u64> = Vec::with_capacity(entries.len());
+        let mut values: Vec<T> = Vec::with_capacity(entries.len());
+        for (pos, value) in entries {
+            if positions.last() == Some(&pos) {
+                let last = values.last_mut().unwrap();
+                *last = *last + value;
+            } else {
+                positions.push(pos);
+                values.push(value);
+            }
+        }
+        let (positions, values) = positions.into_iter().zip(values).filter(|&(_, value)| value != T::zero()).unzip();
+        Self::from_sorted_parts(size, positions, values)
+    }
+
+    fn from_sorted_parts(size: u64, positions: Vec<u64>, values: Vec<T>) -> Self {
+        let kind = IndexKind::for_density(size, positions.len() as u64);
+        SparseVector {
+            index: PositionIndex::from_sorted_positions(size, positions, kind),
+            values,
+        }
+    }
+
+    // Assuming the entries are appended in position order, like
+    // SparseArray::append. Zeros and positions past the end are ignored.
+    pub fn append(&mut self, value: T, pos: u64) {
+        if pos < self.index.size() && value != T::zero() {
+            self.index.set(pos);
+            self.values.push(value);
+        }
+    }
+
+    // Builds the rank tables once all the entries are appended
+    pub fn finalize(&mut self) {
+        self.index.finalize();
+    }
+
+    pub fn size(&self) -> u64 {
+        self.index.size()
+    }
+
+    // Number of stored, nonzero, entries
+    pub fn num_nonzero(&self) -> u64 {
+        self.values.len() as u64
+    }
+
+    pub fn index_kind(&self) -> IndexKind {
+        self.index.kind()
+    }
+
+    // The entry at position i, zero if it is not stored
+    pub fn get(&self, i: u64) -> T {
+        if i < self.size() && self.index.get(i) {
+            return self.values[self.index.rank1(i) as usize];
+        }
+        T::zero()
+    }
+
+    // The (position, value) pairs of the stored entries in position order
+    pub fn iter(&self) -> impl Iterator<Item = (u64, T)> + '_ {
+        self.index.positions_iter().zip(self.values.iter().copied())
+    }
+
+    pub fn to_dense(&self) -> Vec<T> {
+        let mut dense = vec![T::zero(); self.size() as usize];
+        for (i, value) in self.iter() {
+            dense[i as usize] = value;
+        }
+        dense
+    }
+
+    // Sum of the products of the entries stored in both vectors
+    pub fn dot(&self, other: &SparseVector<T>) -> T {
+        self.check_same_size(other.size());
+        let mut sum = T::zero();
+        let mut theirs = other.iter().peekable();
+        for (i, value) in self.iter() {
+            while theirs.next_if(|&(j, _)| j < i).is_some() {}
+            if let Some((_, other_value)) = theirs.next_if(|&(j, _)| j == i) {
+                sum = sum + value * other_value;
+            }
+        }
+        sum
+    }
+
+    // Dot product with a dense vector of the same size
+    pub fn dot_dense(&self, dense: &[T]) -> T {
+        self.check_same_size(dense.len() as u64);
+        self.iter().fold(T::zero(), |sum, (i, value)| sum + value * dense[i as usize])
+    }
+
+    // a * self + y
+    pub fn axpy(&self, a: T, y: &SparseVector<T>) -> SparseVector<T> {
+        self.merge(y, true, |x, y| a * x + y)
+    }
+
+    // y += a * self, for a dense y of the same size
+    pub fn axpy_dense(&self, a: T, y: &mut [T]) {
+        self.check_same_size(y.len() as u64);
+        for (i, value) in self.iter() {
+            y[i as usize] = a * value + y[i as usize];
+        }
+    }
+
+    // Element-wise sum, stored wherever either vector has an entry
+    pub fn add(&self, other: &SparseVector<T>) -> SparseVector<T> {
+        self.merge(other, true, |x, y| x + y)
+    }
+
+    // Element-wise product, stored only where both vectors have an entry
+    pub fn mul(&self, other: &SparseVector<T>) -> SparseVector<T> {
+        self.merge(other, false, |x, y| x * y)
+    }
+
+    // Multiplies every entry by a. Scaling by zero leaves nothing stored.
+    pub fn scale(&mut self, a: T) {
+        if a == T::zero() {
+            *self = Self::from_sorted_parts(self.size(), Vec::new(), Vec::new());
+            return;
+        }
+        for value in &mut self.values {
+            *value = *value * a;
+        }
+    }
+
+    // Sum of the absolute values of the entries
+    pub fn l1_norm(&self) -> f64 {
+        self.values.iter().map(|&value| value.to_f64().abs()).sum()
+    }
+
+    // Square root of the sum of the squares of the entries
+    pub fn l2_norm(&self) -> f64 {
+        self.values.iter().map(|&value| value.to_f64() * value.to_f64()).sum::<f64>().sqrt()
+    }
+
+    // Walks the entries of both vectors in position order and keeps
+    // f(x, y) for every position where both have an entry, and also where
+    // only one does if union is set, with zero for the missing one
+    fn merge<F: Fn(T, T) -> T>(&self, other: &SparseVector<T>, union: bool, f: F) -> SparseVector<T> {
+        self.check_same_size(other.size());
+        let mut positions = Vec::new();
+        let mut values = Vec::new();
+        let mut keep = |pos: u64, value: T| {
+            if value != T::zero() {
+                positions.push(pos);
+                values.push(value);
+            }
+        };
+
+        let mut ours = self.iter().peekable();
+        let mut theirs = other.iter().peekable();
+        loop {
+            match (ours.peek().copied(), theirs.peek().copied()) {
+                (Some((i, x)), Some((j, y))) if i == j => {
+                    keep(i, f(x, y));
+                    ours.next();
+                    theirs.next();
+                }
+                (Some((i, x)), next) if next.is_none_or(|(j, _)| i < j) => {
+                    if union {
+                        keep(i, f(x, T::zero()));
+                    }
+                    ours.next();
+                }
+                (_, Some((j, y))) => {
+                    if union {
+                        keep(j, f(T::zero(), y));
+                    }
+                    theirs.next();
+                }
+                _ => break,
+            }
+        }
+        Self::from_sorted_parts(self.size(), positions, values)
+    }
+
+    fn check_same_size(&self, other_size: u64) {
+        assert_eq!(self.size(), other_size, "vectors of different sizes");
+    }
+}
+
+impl<T> SpaceUsage for SparseVector<T> {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("SparseVector", vec![
+            SpaceNode::leaf("header", inline_bits::<SparseVector<T>>() - inline_bits::<PositionIndex>()),
+            self.index.space_report().renamed("index"),
+            SpaceNode::leaf("values", vec_bits(&self.values)),
+        ])
+    }
+}
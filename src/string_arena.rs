@@ -0,0 +1,151 @@
+use crate::packed_ints::{bits_needed, PackedInts};
+use crate::space_report::{inline_bits, SpaceNode, SpaceUsage};
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Many strings stored back to back in one buffer. ends holds where every
+// string stops, packed into as many bits as the length of the buffer
+// needs, so a string costs its bytes plus about log2(total bytes) bits
+// instead of a String header and an allocation of its own.
+#[derive(Clone)]
+pub struct StringArena {
+    bytes: String,
+    ends: PackedInts,
+}
+
+impl Default for StringArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StringArena {
+    pub fn new() -> Self {
+        Self {
+            bytes: String::new(),
+            ends: PackedInts::new(0),
+        }
+    }
+
+    pub fn from_strs<I, S>(strs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut arena = Self::new();
+        for s in strs {
+            arena.push(s.as_ref());
+        }
+        arena.shrink_to_fit();
+        arena
+    }
+
+    // Adds s after the last string. The ends get repacked whenever the
+    // buffer grows past what their width can address, which happens at
+    // most once per bit of the final width.
+    pub fn push(&mut self, s: &str) {
+        self.bytes.push_str(s);
+        let end = self.bytes.len() as u64;
+        if bits_needed(end) > self.ends.width() {
+            // Doubling the reach keeps the number of repacks down while
+            // the buffer is small
+            self.ends.widen(bits_needed(end).max(self.ends.width() * 2).min(64));
+        }
+        self.ends.push(end);
+    }
+
+    // Gives back the spare capacity of the buffers once every string is in
+    pub fn shrink_to_fit(&mut self) {
+        let width = bits_needed(self.bytes.len() as u64);
+        if width < self.ends.width() {
+            self.ends = PackedInts::from_values(&self.ends.iter().collect::<Vec<_>>());
+        }
+        self.bytes.shrink_to_fit();
+        self.ends.shrink_to_fit();
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    // Total bytes of all the strings
+    pub fn num_bytes(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn get(&self, i: usize) -> &str {
+        let start = if i == 0 { 0 } else { self.ends.get(i - 1) as usize };
+        &self.bytes[start..self.ends.get(i) as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    // Bytes the arena takes, the total of its space report
+    pub fn overhead(&self) -> usize {
+        self.space_report().bytes() as usize
+    }
+
+    // Writes the ends and then the length of the buffer and the buffer itself
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.ends.write_to(writer)?;
+        writer.write_all(&(self.bytes.len() as u64).to_le_bytes())?;
+        writer.write_all(self.bytes.as_bytes())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let ends = PackedInts::read_from(reader)?;
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        let num_bytes = u64::from_le_bytes(buf);
+
+        // A corrupt length shouldn't allocate everything up front, the
+        // read comes up short first
+        let mut bytes = Vec::new();
+        reader.take(num_bytes).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != num_bytes {
+            return Err(Error::new(ErrorKind::UnexpectedEof, format!(
+                "expected {} bytes of strings but found {}", num_bytes, bytes.len()
+            )));
+        }
+        let bytes = String::from_utf8(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        let arena = Self { bytes, ends };
+        arena.verify()?;
+        Ok(arena)
+    }
+
+    // Checks that the ends go up, stop at the end of the buffer and never
+    // split a character
+    pub fn verify(&self) -> std::io::Result<()> {
+        let mut start = 0;
+        for (i, end) in self.ends.iter().enumerate() {
+            if end < start || !self.bytes.is_char_boundary(end as usize) {
+                return Err(Error::new(ErrorKind::InvalidData, format!(
+                    "string {} ends at byte {}, which is not a string boundary", i, end
+                )));
+            }
+            start = end;
+        }
+        if start as usize != self.bytes.len() {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the strings take {} bytes but the buffer has {}", start, self.bytes.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl SpaceUsage for StringArena {
+    fn space_report(&self) -> SpaceNode {
+        SpaceNode::with_children("StringArena", vec![
+            SpaceNode::leaf("header", inline_bits::<StringArena>() - inline_bits::<PackedInts>()),
+            SpaceNode::leaf("bytes", self.bytes.capacity() as u64 * 8),
+            self.ends.space_report().renamed("ends"),
+        ])
+    }
+}
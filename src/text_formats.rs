@@ -0,0 +1,513 @@
+// Reading and writing bit vectors, sparse arrays and sparse vectors as
+// text. The readers go line by line and report the line of the first
+// problem they find. The builders stream entries straight into the
+// structure when the size is known and the entries come in increasing
+// order, and only hold on to the entries themselves when they have to.
+//
+// positions   one position of a 1 per line
+// bits        a string of 0s and 1s, whitespace is ignored
+// tsv         index<TAB>value per line, the value is everything after the tab
+// Matrix Market coordinate vectors, with 1 based indices
+//
+// Blank lines are skipped everywhere, and so are lines starting with # in
+// the positions and tsv formats and with % in Matrix Market files.
+use crate::bit_vector::BitVector;
+use crate::position_index::IndexKind;
+use crate::sparse_array::SparseArray;
+use crate::sparse_vector::{Scalar, SparseVector};
+use std::fmt::Display;
+use std::io::{self, BufRead, Error, ErrorKind, Lines, Write};
+use std::marker::PhantomData;
+
+pub(crate) fn line_error<M: Display>(line_number: usize, msg: M) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("line {}: {}", line_number, msg))
+}
+
+// The lines of a reader with their line numbers, 1 based, leaving out
+// blank lines and the ones that start with the comment character
+pub(crate) struct DataLines<R> {
+    lines: Lines<R>,
+    line_number: usize,
+    comment: char,
+}
+
+impl<R: BufRead> DataLines<R> {
+    // lines_before is the number of lines already read from the reader
+    pub(crate) fn new(reader: R, comment: char, lines_before: usize) -> Self {
+        Self {
+            lines: reader.lines(),
+            line_number: lines_before,
+            comment,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for DataLines<R> {
+    type Item = io::Result<(usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for line in self.lines.by_ref() {
+            self.line_number += 1;
+            match line {
+                Ok(line) if line.trim().is_empty() || line.trim_start().starts_with(self.comment) => continue,
+                Ok(line) => return Some(Ok((self.line_number, line))),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+//
+// Readers
+//
+
+// The positions of a positions file, in the order they are given
+pub fn parse_positions<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<u64>> {
+    numbered_positions(reader).map(|entry| entry.map(|(_, pos)| pos))
+}
+
+// The bits of a 0/1 file, in order. They come straight out of the
+// reader's buffer, so a file that is one long line never has to fit in
+// memory.
+pub fn parse_bits<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<bool>> {
+    Bits { reader, line_number: 1, done: false }
+}
+
+struct Bits<R> {
+    reader: R,
+    line_number: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for Bits<R> {
+    type Item = io::Result<bool>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let byte = match self.reader.fill_buf() {
+                Ok([]) => break,
+                Ok(buf) => buf[0],
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            self.reader.consume(1);
+            match byte {
+                b'0' => return Some(Ok(false)),
+                b'1' => return Some(Ok(true)),
+                b'\n' => self.line_number += 1,
+                _ if byte.is_ascii_whitespace() => {}
+                _ => {
+                    self.done = true;
+                    let found = if byte.is_ascii() { format!("{:?}", byte as char) } else { format!("byte {:#04x}", byte) };
+                    return Some(Err(line_error(self.line_number, format!("{} is not a 0 or a 1", found))));
+                }
+            }
+        }
+        self.done = true;
+        None
+    }
+}
+
+// The (index, value) pairs of a tsv file, in the order they are given
+pub fn parse_tsv<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<(u64, String)>> {
+    numbered_tsv(reader).map(|entry| entry.map(|(_, pair)| pair))
+}
+
+// Reads the %%MatrixMarket header line of a coordinate file holding the
+// given object and returns whether its field is pattern and whether it
+// is symmetric. Only real, integer and pattern fields are supported.
+pub(crate) fn read_matrix_market_header<R: BufRead>(reader: &mut R, object: &str) -> io::Result<(bool, bool)> {
+    let mut header = String::new();
+    if reader.read_line(&mut header)? == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "empty Matrix Market file"));
+    }
+    let header = header.to_lowercase();
+    let words: Vec<&str> = header.split_whitespace().collect();
+    if words.len() != 5 || words[0] != "%%matrixmarket" || words[1] != object {
+        return Err(line_error(1, format!("expected a %%MatrixMarket {} header", object)));
+    }
+    if words[2] != "coordinate" {
+        return Err(line_error(1, format!("only coordinate {}s are supported, not {}", object, words[2])));
+    }
+    let pattern = match words[3] {
+        "real" | "integer" => false,
+        "pattern" => true,
+        field => return Err(line_error(1, format!("unsupported field {}", field))),
+    };
+    let symmetric = match words[4] {
+        "general" => false,
+        "symmetric" => true,
+        symmetry => return Err(line_error(1, format!("unsupported symmetry {}", symmetry))),
+    };
+    Ok((pattern, symmetric))
+}
+
+// Reads the line after the header and comments, which holds count sizes
+pub(crate) fn read_matrix_market_sizes<R: BufRead>(lines: &mut DataLines<R>, count: usize, what: &str) -> io::Result<(usize, Vec<u64>)> {
+    let (line_number, line) = lines.next().ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing the size line"))??;
+    let sizes: Vec<u64> = line.split_whitespace()
+        .map(|word| word.parse().map_err(|_| line_error(line_number, format!("bad size {:?}", word))))
+        .collect::<io::Result<_>>()?;
+    if sizes.len() != count {
+        return Err(line_error(line_number, format!("expected {}", what)));
+    }
+    Ok((line_number, sizes))
+}
+
+// Parses a 1 based index of a Matrix Market entry into a 0 based one
+pub(crate) fn parse_matrix_market_index(line_number: usize, word: &str, bound: u64, what: &str) -> io::Result<u64> {
+    match word.parse::<u64>() {
+        Ok(i) if i >= 1 && i <= bound => Ok(i - 1),
+        _ => Err(line_error(line_number, format!("{} {:?} is not between 1 and {}", what, word, bound))),
+    }
+}
+
+// Parses the value of a Matrix Market entry, which is a 1 in pattern files
+pub(crate) fn parse_matrix_market_value<T: Scalar>(line_number: usize, word: Option<&str>) -> io::Result<T> {
+    let word = word.unwrap_or("1");
+    word.parse().map_err(|_| line_error(line_number, format!("{:?} is not a {} value", word, T::NAME)))
+}
+
+// The entries of a Matrix Market coordinate vector, with 0 based indices,
+// in the order they are given. The size and the number of entries come
+// from the size line, and there is an error at the end if the number of
+// entries doesn't match it.
+pub struct MatrixMarketVectorReader<T, R> {
+    lines: DataLines<R>,
+    size: u64,
+    num_entries: u64,
+    num_read: u64,
+    pattern: bool,
+    done: bool,
+    values: PhantomData<T>,
+}
+
+impl<T: Scalar, R: BufRead> MatrixMarketVectorReader<T, R> {
+    // Reads the header and the size line
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let (pattern, symmetric) = read_matrix_market_header(&mut reader, "vector")?;
+        if symmetric {
+            return Err(line_error(1, "a vector can't be symmetric"));
+        }
+        let mut lines = DataLines::new(reader, '%', 1);
+        let (_, sizes) = read_matrix_market_sizes(&mut lines, 2, "the size and the number of entries")?;
+        Ok(Self {
+            lines,
+            size: sizes[0],
+            num_entries: sizes[1],
+            num_read: 0,
+            pattern,
+            done: false,
+            values: PhantomData,
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn num_entries(&self) -> u64 {
+        self.num_entries
+    }
+
+    fn parse_entry(&self, line_number: usize, line: &str) -> io::Result<(u64, T)> {
+        if self.num_read > self.num_entries {
+            return Err(line_error(line_number, format!("more than the {} entries the size line gives", self.num_entries)));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let expected_words = if self.pattern { 1 } else { 2 };
+        if words.len() != expected_words {
+            return Err(line_error(line_number, format!("expected {} fields but found {}", expected_words, words.len())));
+        }
+        let index = parse_matrix_market_index(line_number, words[0], self.size, "index")?;
+        let value = parse_matrix_market_value(line_number, words.get(1).copied())?;
+        Ok((index, value))
+    }
+}
+
+impl<T: Scalar, R: BufRead> Iterator for MatrixMarketVectorReader<T, R> {
+    type Item = io::Result<(u64, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = match self.lines.next() {
+            Some(Ok((line_number, line))) => {
+                self.num_read += 1;
+                self.parse_entry(line_number, &line)
+            }
+            Some(Err(e)) => Err(e),
+            None if self.num_read != self.num_entries => Err(Error::new(ErrorKind::InvalidData, format!(
+                "the size line gives {} entries but there are {}", self.num_entries, self.num_read
+            ))),
+            None => return None,
+        };
+        self.done = entry.is_err();
+        Some(entry)
+    }
+}
+
+//
+// Builders
+//
+
+// Builds a bit vector with a 1 at every position of a positions file. The
+// size defaults to one past the largest position, which means holding on
+// to the positions until the end. With a size they go straight into the
+// bit vector, in any order.
+pub fn bit_vector_from_positions<R: BufRead>(reader: R, size: Option<u64>) -> io::Result<BitVector> {
+    bit_vector_from_numbered(numbered_positions(reader), size)
+}
+
+// Same as bit_vector_from_positions, with a 1 at every index of a tsv file
+pub fn bit_vector_from_tsv<R: BufRead>(reader: R, size: Option<u64>) -> io::Result<BitVector> {
+    bit_vector_from_numbered(numbered_tsv(reader).map(|entry| entry.map(|(line_number, (index, _))| (line_number, index))), size)
+}
+
+fn bit_vector_from_numbered<I>(entries: I, size: Option<u64>) -> io::Result<BitVector>
+where
+    I: Iterator<Item = io::Result<(usize, u64)>>,
+{
+    let Some(size) = size else {
+        let positions: Vec<u64> = entries.map(|entry| entry.map(|(_, pos)| pos)).collect::<io::Result<_>>()?;
+        let size = size_past(positions.iter().copied().max())?;
+        let mut bit_vector = zeroed_bit_vector(size)?;
+        for pos in positions {
+            bit_vector.set(pos as usize, true);
+        }
+        return Ok(bit_vector);
+    };
+
+    let mut bit_vector = zeroed_bit_vector(size)?;
+    for entry in entries {
+        let (line_number, pos) = entry?;
+        check_fits(line_number, pos, size)?;
+        bit_vector.set(pos as usize, true);
+    }
+    Ok(bit_vector)
+}
+
+// Builds a bit vector from a 0/1 file, a word at a time. If a size is
+// given the file has to have exactly that many bits.
+pub fn bit_vector_from_bits<R: BufRead>(reader: R, size: Option<u64>) -> io::Result<BitVector> {
+    let mut data = Vec::new();
+    let mut len = 0;
+    for bit in parse_bits(reader) {
+        if len % 64 == 0 {
+            data.push(0);
+        }
+        if bit? {
+            data[len / 64] |= 1 << (len % 64);
+        }
+        len += 1;
+    }
+    if let Some(size) = size {
+        if size != len as u64 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("expected {} bits but the input has {}", size, len)));
+        }
+    }
+    // A bit vector always has a word past its last full one
+    data.resize(len / 64 + 1, 0);
+    Ok(BitVector { data, size: len })
+}
+
+// Builds a finalized sparse array from a tsv file. With a size, entries
+// in increasing index order go straight into the array. Without one, or
+// once an index is out of order or repeated, the entries are collected
+// and sorted first. If an index is repeated the last value wins. Without
+// a size the position index is picked from the density, so a few large
+// indices don't cost a bit for every position below them.
+pub fn sparse_array_from_tsv<R: BufRead>(reader: R, size: Option<u64>, num_threads: usize) -> io::Result<SparseArray> {
+    let mut entries = numbered_tsv(reader);
+    let Some(size) = size else {
+        let pairs: Vec<(u64, String)> = entries.map(|entry| entry.map(|(_, pair)| pair)).collect::<io::Result<_>>()?;
+        let size = size_past(pairs.iter().map(|&(index, _)| index).max())?;
+        let kind = IndexKind::for_density(size, pairs.len() as u64);
+        return Ok(sparse_array_from_pairs(pairs, size, kind, num_threads));
+    };
+
+    // The plain index takes a bit for every position
+    dense_size(size)?;
+    let mut sparse_array = SparseArray::create(size);
+    let mut last = None;
+    let out_of_order = loop {
+        let Some(entry) = entries.next() else {
+            sparse_array.finalize_with_threads(num_threads);
+            return Ok(sparse_array);
+        };
+        let (line_number, (index, value)) = entry?;
+        check_fits(line_number, index, size)?;
+        if last.is_some_and(|last| index <= last) {
+            break (index, value);
+        }
+        last = Some(index);
+        sparse_array.append(value, index);
+    };
+
+    // Out of order, so the rest has to be sorted in with what came before
+    sparse_array.finalize();
+    let mut pairs: Vec<(u64, String)> = sparse_array.iter().map(|(index, value)| (index, value.clone())).collect();
+    drop(sparse_array);
+    pairs.push(out_of_order);
+    for entry in entries {
+        let (line_number, (index, value)) = entry?;
+        check_fits(line_number, index, size)?;
+        pairs.push((index, value));
+    }
+    Ok(sparse_array_from_pairs(pairs, size, IndexKind::Plain, num_threads))
+}
+
+// Builds a sparse vector from a Matrix Market coordinate vector. Entries in
+// increasing index order go straight into the vector, and once one is out
+// of order or repeated the entries are collected and sorted first.
+// Repeated entries are added up.
+pub fn sparse_vector_from_matrix_market<T: Scalar, R: BufRead>(reader: R) -> io::Result<SparseVector<T>> {
+    let mut entries = MatrixMarketVectorReader::<T, R>::new(reader)?;
+    let size = entries.size();
+    let kind = IndexKind::for_density(size, entries.num_entries());
+    let mut vector = SparseVector::create_with_index(size, kind);
+    let mut last = None;
+    let out_of_order = loop {
+        let Some(entry) = entries.next() else {
+            vector.finalize();
+            return Ok(vector);
+        };
+        let (index, value) = entry?;
+        if last.is_some_and(|last| index <= last) {
+            break (index, value);
+        }
+        last = Some(index);
+        vector.append(value, index);
+    };
+
+    vector.finalize();
+    let mut all: Vec<(u64, T)> = vector.iter().collect();
+    drop(vector);
+    all.push(out_of_order);
+    for entry in entries {
+        all.push(entry?);
+    }
+    Ok(SparseVector::from_entries(size, all))
+}
+
+fn numbered_positions<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<(usize, u64)>> {
+    DataLines::new(reader, '#', 0).map(|line| {
+        let (line_number, line) = line?;
+        let line = line.trim();
+        let pos = line.parse().map_err(|_| line_error(line_number, format!("{} is not a position", line)))?;
+        Ok((line_number, pos))
+    })
+}
+
+fn numbered_tsv<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<(usize, (u64, String))>> {
+    DataLines::new(reader, '#', 0).map(|line| {
+        let (line_number, line) = line?;
+        let Some((index, value)) = line.split_once('\t') else {
+            return Err(line_error(line_number, "expected index<TAB>value"));
+        };
+        let index = index.trim().parse().map_err(|_| line_error(line_number, format!("{} is not an index", index)))?;
+        Ok((line_number, (index, value.to_string())))
+    })
+}
+
+fn check_fits(line_number: usize, index: u64, size: u64) -> io::Result<()> {
+    if index >= size {
+        return Err(line_error(line_number, format!("{} doesn't fit in size {}", index, size)));
+    }
+    Ok(())
+}
+
+// One past the largest index, the size when none is given
+fn size_past(largest: Option<u64>) -> io::Result<u64> {
+    match largest {
+        None => Ok(0),
+        Some(largest) => largest.checked_add(1)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} leaves no room for a size past it", largest))),
+    }
+}
+
+// A size that has a bit for every position has to fit in a usize
+fn dense_size(size: u64) -> io::Result<usize> {
+    usize::try_from(size).map_err(|_| Error::new(ErrorKind::InvalidData, format!("size {} is too large for a bit vector", size)))
+}
+
+// An all 0 bit vector, with an error instead of an abort when there is no
+// memory for it
+fn zeroed_bit_vector(size: u64) -> io::Result<BitVector> {
+    let size = dense_size(size)?;
+    let num_words = size / 64 + 1;
+    let mut data = Vec::new();
+    data.try_reserve_exact(num_words)
+        .map_err(|_| Error::new(ErrorKind::OutOfMemory, format!("no memory for a bit vector of size {}", size)))?;
+    data.resize(num_words, 0);
+    Ok(BitVector { data, size })
+}
+
+// Sorts the pairs and keeps the last value of every index
+fn sparse_array_from_pairs(mut pairs: Vec<(u64, String)>, size: u64, kind: IndexKind, num_threads: usize) -> SparseArray {
+    // Stable so that the last value given for an index wins
+    pairs.sort_by_key(|&(index, _)| index);
+    let mut sparse_array = SparseArray::create_with_index(size, kind);
+    let mut pairs = pairs.into_iter().peekable();
+    while let Some((index, value)) = pairs.next() {
+        if pairs.peek().is_none_or(|&(next, _)| next != index) {
+            sparse_array.append(value, index);
+        }
+    }
+    sparse_array.finalize_with_threads(num_threads);
+    sparse_array
+}
+
+//
+// Writers
+//
+
+// One position per line for every 1 of the bit vector
+pub fn write_positions<W: Write>(bit_vector: &BitVector, writer: &mut W) -> io::Result<()> {
+    for pos in (0..bit_vector.size()).filter(|&i| bit_vector.get(i)) {
+        writeln!(writer, "{}", pos)?;
+    }
+    Ok(())
+}
+
+// The bits as 0s and 1s, 64 to a line
+pub fn write_bits<W: Write>(bit_vector: &BitVector, writer: &mut W) -> io::Result<()> {
+    let mut line = String::with_capacity(65);
+    for i in 0..bit_vector.size() {
+        line.push(if bit_vector.get(i) { '1' } else { '0' });
+        if line.len() == 64 || i + 1 == bit_vector.size() {
+            line.push('\n');
+            writer.write_all(line.as_bytes())?;
+            line.clear();
+        }
+    }
+    Ok(())
+}
+
+// index<TAB>value for every present element. A value with a line break in
+// it couldn't be read back, so it is an error.
+pub fn write_tsv<T: Display, W: Write>(sparse_array: &SparseArray<T>, writer: &mut W) -> io::Result<()> {
+    for (index, value) in sparse_array.iter() {
+        let value = value.to_string();
+        if value.contains('\n') || value.contains('\r') {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("the value at {} has a line break", index)));
+        }
+        writeln!(writer, "{}\t{}", index, value)?;
+    }
+    Ok(())
+}
+
+// A general coordinate Matrix Market vector with 1 based indices
+pub fn write_matrix_market_vector<T: Scalar, W: Write>(vector: &SparseVector<T>, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "%%MatrixMarket vector coordinate {} general", T::MATRIX_MARKET_FIELD)?;
+    writeln!(writer, "{} {}", vector.size(), vector.num_nonzero())?;
+    for (index, value) in vector.iter() {
+        writeln!(writer, "{} {}", index + 1, value)?;
+    }
+    Ok(())
+}
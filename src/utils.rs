@@ -8,11 +8,12 @@ use std::fs::File;
 // the nearest integer and then rounded up to the
 // nearest even integer
 // 2*\celing{\floor{log(n)}/2}
+// There is no log of 0, it gets the log of 1 which is 0
 pub fn my_log(size: usize) -> usize {
     let mut copy_of_size: usize = size;
     let mut result: usize = 0;
-    while copy_of_size != 1 {
-        copy_of_size = copy_of_size >> 1;
+    while copy_of_size > 1 {
+        copy_of_size >>= 1;
         result += 1
     }
     // Make result even
@@ -20,6 +21,22 @@ pub fn my_log(size: usize) -> usize {
     result 
 }
 
+// Smallest chunk and superchunk sizes the rank tables use. Below 8 bits
+// the log is 2 or less, which would give chunks of 0 bits.
+pub const MIN_CHUNK_SIZE: usize = 1;
+pub const MIN_SUPERCHUNK_SIZE: usize = 4;
+
+// Returns (chunk_size, superchunk_size) for the rank tables of a bit
+// vector of size bits. The chunk is .5*log(n) and the superchunk is
+// (logn)^2 rounded to a multiple of the chunk, but never smaller than
+// the minimums, so every size from 0 up gets usable tables.
+pub fn rank_block_sizes(size: usize) -> (usize, usize) {
+    let log_of_size = my_log(size);
+    let chunk_size = std::cmp::max(log_of_size / 2, MIN_CHUNK_SIZE);
+    let superchunk_size = std::cmp::max(2 * chunk_size * log_of_size, MIN_SUPERCHUNK_SIZE);
+    (chunk_size, superchunk_size)
+}
+
 
 //
 //
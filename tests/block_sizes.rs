@@ -0,0 +1,92 @@
+// The block size presets of RankSupport, what sizes are rejected and how
+// the sizes travel with the saved tables
+use std::io::ErrorKind;
+
+use hw2_code::bit_vector::BitVector;
+use hw2_code::rank_support::{BlockSizes, RankSupport, MAX_CHUNK_SIZE};
+use hw2_code::utils::rank_block_sizes;
+
+fn every_third_bit(size: usize) -> BitVector {
+    let mut bit_vector = BitVector::new(size);
+    for i in (0..size).step_by(3) {
+        bit_vector.set(i, true);
+    }
+    bit_vector
+}
+
+fn custom(chunk_size: usize, superchunk_size: usize) -> BlockSizes {
+    BlockSizes::Custom { chunk_size, superchunk_size }
+}
+
+#[test]
+fn presets_resolve_to_their_sizes() {
+    for size in [0, 1, 100, 1 << 20] {
+        assert_eq!(BlockSizes::Theoretical.resolve(size).unwrap(), rank_block_sizes(size));
+        assert_eq!(BlockSizes::Speed.resolve(size).unwrap(), (64, 512));
+        assert_eq!(custom(16, 256).resolve(size).unwrap(), (16, 256));
+    }
+    let bit_vector = every_third_bit(1000);
+    let rank_support = RankSupport::with_block_sizes(&bit_vector, BlockSizes::Theoretical).unwrap();
+    let default = RankSupport::new(&bit_vector);
+    assert_eq!((rank_support.chunk_size(), rank_support.superchunk_size()), (default.chunk_size(), default.superchunk_size()));
+}
+
+#[test]
+fn sizes_that_do_not_fit_the_tables_are_rejected() {
+    let largest_superchunk = u16::MAX as usize + 1;
+    for (chunk_size, superchunk_size) in [
+        (0, 8),
+        (MAX_CHUNK_SIZE + 1, 2 * (MAX_CHUNK_SIZE + 1)),
+        (8, 0),
+        (8, 12),
+        (4, 2),
+        (1, largest_superchunk + 1),
+    ] {
+        let err = custom(chunk_size, superchunk_size).resolve(100).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}/{}", chunk_size, superchunk_size);
+        assert!(RankSupport::with_block_sizes(&every_third_bit(100), custom(chunk_size, superchunk_size)).is_err());
+    }
+    // The largest sizes that still fit
+    assert!(custom(MAX_CHUNK_SIZE, MAX_CHUNK_SIZE).resolve(100).is_ok());
+    assert!(custom(1, largest_superchunk).resolve(100).is_ok());
+}
+
+#[test]
+fn larger_blocks_use_less_space() {
+    let bit_vector = every_third_bit(1 << 16);
+    let theoretical = RankSupport::with_block_sizes(&bit_vector, BlockSizes::Theoretical).unwrap();
+    let speed = RankSupport::with_block_sizes(&bit_vector, BlockSizes::Speed).unwrap();
+    // 128 superchunks of 64 bits and 1024 chunks of 16 bits
+    assert_eq!(speed.overhead(), 128 * 64 + 1024 * 16);
+    assert!(speed.overhead() < theoretical.overhead());
+}
+
+#[test]
+fn saved_tables_keep_their_block_sizes() {
+    let bit_vector = every_third_bit(5000);
+    for block_sizes in [BlockSizes::Theoretical, BlockSizes::Speed, custom(32, 96), custom(1, 4)] {
+        let rank_support = RankSupport::with_block_sizes(&bit_vector, block_sizes).unwrap();
+        let mut saved = Vec::new();
+        rank_support.write_to(&mut saved).unwrap();
+
+        let loaded = RankSupport::read_from(&bit_vector, &mut &saved[..]).unwrap();
+        loaded.verify().unwrap();
+        assert_eq!(loaded.chunk_size(), rank_support.chunk_size(), "{:?}", block_sizes);
+        assert_eq!(loaded.superchunk_size(), rank_support.superchunk_size(), "{:?}", block_sizes);
+        for i in (0..=5000).step_by(7) {
+            assert_eq!(loaded.rank1(i), rank_support.rank1(i));
+        }
+    }
+}
+
+#[test]
+fn loading_sizes_that_do_not_fit_fails() {
+    let bit_vector = every_third_bit(100);
+    let rank_support = RankSupport::with_block_sizes(&bit_vector, BlockSizes::Speed).unwrap();
+    let mut saved = Vec::new();
+    rank_support.write_to(&mut saved).unwrap();
+    // The chunk size is the first u64 of the tables
+    saved[..8].copy_from_slice(&128u64.to_le_bytes());
+    let err = RankSupport::read_from(&bit_vector, &mut &saved[..]).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
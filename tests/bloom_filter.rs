@@ -0,0 +1,164 @@
+// The filters never miss an inserted item, stay near their false positive
+// rate, estimate how many items went in, and save and load.
+use hw2_code::bloom_filter::{optimal_parameters, BlockedBloomFilter, BloomFilter, CountingBloomFilter};
+
+mod common;
+use common::temp_file;
+
+// The share of 100000 items never inserted that contains reports
+fn false_positive_rate<F: Fn(u64) -> bool>(contains: F) -> f64 {
+    (1_000_000..1_100_000).filter(|&i| contains(i)).count() as f64 / 100_000.0
+}
+
+#[test]
+fn parameters_follow_the_formulas() {
+    // 1% needs about 9.59 bits an item and 7 hashes
+    let (num_bits, num_hashes) = optimal_parameters(1000, 0.01);
+    assert_eq!(num_bits, 9586);
+    assert_eq!(num_hashes, 7);
+    assert_eq!(optimal_parameters(0, 0.5), (2, 1));
+}
+
+#[test]
+fn bloom_filter_has_no_misses_and_the_rate_it_was_sized_for() {
+    for rate in [0.1, 0.01, 0.001] {
+        let mut filter = BloomFilter::with_rate(20_000, rate);
+        for i in 0..20_000u64 {
+            filter.insert(&i);
+        }
+        assert!((0..20_000u64).all(|i| filter.contains(&i)));
+        let measured = false_positive_rate(|i| filter.contains(&i));
+        assert!(measured < rate * 1.5, "{} measured for {}", measured, rate);
+        assert!((filter.estimated_false_positive_rate() - rate).abs() < rate * 0.5);
+
+        let estimate = filter.estimated_cardinality();
+        assert!((estimate - 20_000.0).abs() < 20_000.0 * 0.03, "estimated {}", estimate);
+    }
+}
+
+#[test]
+fn strings_and_slices_hash_like_their_contents() {
+    let mut filter = BloomFilter::with_rate(100, 0.01);
+    filter.insert("ACGT");
+    filter.insert(&b"chr1"[..]);
+    assert!(filter.contains(&"ACGT".to_string()));
+    assert!(filter.contains(&b"chr1".to_vec()[..]));
+    assert!(!filter.contains("TTTT"));
+}
+
+#[test]
+fn merging_is_the_filter_of_both() {
+    let mut evens = BloomFilter::with_rate(10_000, 0.01);
+    let mut odds = BloomFilter::with_rate(10_000, 0.01);
+    let mut all = BloomFilter::with_rate(10_000, 0.01);
+    for i in 0..10_000u64 {
+        if i % 2 == 0 { evens.insert(&i) } else { odds.insert(&i) }
+        all.insert(&i);
+    }
+    evens.merge(&odds);
+    assert_eq!(evens.bit_vector().data, all.bit_vector().data);
+    assert!((evens.estimated_cardinality() - 10_000.0).abs() < 300.0);
+
+    let mut blocked_evens = BlockedBloomFilter::with_rate(10_000, 0.01);
+    let mut blocked_odds = BlockedBloomFilter::with_rate(10_000, 0.01);
+    for i in 0..10_000u64 {
+        if i % 2 == 0 { blocked_evens.insert(&i) } else { blocked_odds.insert(&i) }
+    }
+    blocked_evens.merge(&blocked_odds);
+    assert!((0..10_000u64).all(|i| blocked_evens.contains(&i)));
+}
+
+#[test]
+#[should_panic(expected = "different shapes")]
+fn merging_needs_the_same_shape() {
+    BloomFilter::new(1000, 3).merge(&BloomFilter::new(1000, 4));
+}
+
+#[test]
+fn blocked_filter_stays_close_to_the_rate() {
+    let mut filter = BlockedBloomFilter::with_rate(50_000, 0.01);
+    assert_eq!(filter.num_bits() % 512, 0);
+    for i in 0..50_000u64 {
+        filter.insert(&i);
+    }
+    assert!((0..50_000u64).all(|i| filter.contains(&i)));
+    let measured = false_positive_rate(|i| filter.contains(&i));
+    assert!(measured < 0.02, "{} measured", measured);
+    let estimate = filter.estimated_cardinality();
+    assert!((estimate - 50_000.0).abs() < 50_000.0 * 0.05, "estimated {}", estimate);
+}
+
+#[test]
+fn counting_filter_removes_items() {
+    let mut counting = CountingBloomFilter::with_rate(5000, 0.01);
+    let mut plain = BloomFilter::with_rate(5000, 0.01);
+    for i in 0..5000u64 {
+        counting.insert(&i);
+    }
+    for i in 0..5000u64 {
+        if i % 5 == 0 {
+            assert!(counting.remove(&i));
+        } else {
+            plain.insert(&i);
+        }
+    }
+    assert!((0..5000u64).filter(|i| i % 5 != 0).all(|i| counting.contains(&i)));
+    assert!(!counting.remove(&"never inserted"));
+    // Without saturated counters the removals leave exactly the filter of
+    // what is still in
+    assert!((0..counting.num_counters()).all(|i| counting.count(i) < 15));
+    assert_eq!(counting.to_bloom_filter().bit_vector().data, plain.bit_vector().data);
+    assert!((counting.estimated_cardinality() - 4000.0).abs() < 200.0);
+
+    // A saturated counter is never taken back to 0
+    let mut tiny = CountingBloomFilter::new(1, 1);
+    for _ in 0..20 {
+        tiny.insert(&1u64);
+    }
+    for _ in 0..20 {
+        tiny.remove(&1u64);
+    }
+    assert_eq!(tiny.count(0), 15);
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    let mut filter = BloomFilter::with_rate(1000, 0.05);
+    let mut blocked = BlockedBloomFilter::with_rate(1000, 0.05);
+    let mut counting = CountingBloomFilter::with_rate(1000, 0.05);
+    for i in 0..1000u64 {
+        filter.insert(&i);
+        blocked.insert(&i);
+        counting.insert(&i);
+    }
+
+    let file = temp_file("plain");
+    filter.save(&file).unwrap();
+    let loaded = BloomFilter::load(&file).unwrap();
+    assert_eq!((loaded.num_bits(), loaded.num_hashes()), (filter.num_bits(), filter.num_hashes()));
+    assert_eq!(loaded.bit_vector().data, filter.bit_vector().data);
+    // The tag keeps one kind of filter from loading as another
+    assert!(BlockedBloomFilter::load(&file).is_err());
+    let mut bytes = std::fs::read(&file).unwrap();
+    bytes.push(0);
+    std::fs::write(&file, &bytes).unwrap();
+    assert!(BloomFilter::load(&file).is_err());
+    std::fs::remove_file(&file).unwrap();
+
+    let file = temp_file("blocked");
+    blocked.save(&file).unwrap();
+    let loaded = BlockedBloomFilter::load(&file).unwrap();
+    assert!((0..1000u64).all(|i| loaded.contains(&i)));
+    std::fs::remove_file(&file).unwrap();
+
+    let mut bytes = Vec::new();
+    counting.write_to(&mut bytes).unwrap();
+    let loaded = CountingBloomFilter::read_from(&mut bytes.as_slice()).unwrap();
+    assert!((0..loaded.num_counters()).all(|i| loaded.count(i) == counting.count(i)));
+
+    // A zero hash count is not a filter save wrote
+    let mut bytes = Vec::new();
+    filter.write_to(&mut bytes).unwrap();
+    bytes[8..16].copy_from_slice(&0u64.to_le_bytes());
+    assert!(BloomFilter::read_from(&mut bytes.as_slice()).is_err());
+}
@@ -0,0 +1,124 @@
+// Every broadword primitive is checked against a bit by bit loop, for
+// every k or bit position of many random words. The words are drawn at
+// several densities so select sees words with few and with many ones.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::broadword::{self, portable};
+
+const NUM_WORDS: usize = 20_000;
+
+fn bit(word: u64, i: usize) -> bool {
+    (word >> i) & 1 == 1
+}
+
+fn naive_rank(word: u64, i: usize) -> u64 {
+    (0..i).filter(|&j| bit(word, j)).count() as u64
+}
+
+fn naive_select(word: u64, k: usize) -> usize {
+    (0..64).filter(|&j| bit(word, j)).nth(k).unwrap_or(64)
+}
+
+fn naive_extract(word: u64, mask: u64) -> u64 {
+    (0..64).filter(|&j| bit(mask, j)).enumerate().map(|(out, j)| (bit(word, j) as u64) << out).sum()
+}
+
+fn naive_deposit(word: u64, mask: u64) -> u64 {
+    (0..64).filter(|&j| bit(mask, j)).enumerate().map(|(i, j)| (bit(word, i) as u64) << j).sum()
+}
+
+fn naive_reverse(word: u64, len: usize) -> u64 {
+    (0..len).map(|i| (bit(word, i) as u64) << (len - 1 - i)).sum()
+}
+
+// Random words with each bit set with one of a few probabilities, plus
+// the words that tend to break bit tricks
+fn words(seed: u64) -> Vec<u64> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut words = vec![0, u64::MAX, 1, 1 << 63, 0x5555_5555_5555_5555, 0xAAAA_AAAA_AAAA_AAAA, 0x8000_0000_0000_0001];
+    words.extend((0..64).map(|i| 1u64 << i));
+    words.extend((0..=64).map(broadword::low_mask));
+    for density in [0.02, 0.1, 0.5, 0.9, 0.98] {
+        for _ in 0..NUM_WORDS / 5 {
+            words.push((0..64).filter(|_| rng.gen_bool(density)).map(|i| 1u64 << i).sum());
+        }
+    }
+    words
+}
+
+#[test]
+fn rank_in_word_counts_the_ones_below() {
+    for word in words(1) {
+        for i in 0..=64 {
+            assert_eq!(broadword::rank_in_word(word, i), naive_rank(word, i), "word {:#x} i {}", word, i);
+        }
+    }
+}
+
+#[test]
+fn select_in_word_finds_every_one() {
+    for word in words(2) {
+        for k in 0..=65 {
+            let expected = naive_select(word, k);
+            assert_eq!(broadword::select_in_word(word, k), expected, "word {:#x} k {}", word, k);
+            assert_eq!(portable::select_in_word(word, k), expected, "portable, word {:#x} k {}", word, k);
+        }
+    }
+}
+
+#[test]
+fn select_undoes_rank() {
+    for word in words(3) {
+        for k in 0..word.count_ones() as usize {
+            let pos = broadword::select_in_word(word, k);
+            assert!(bit(word, pos));
+            assert_eq!(broadword::rank_in_word(word, pos), k as u64);
+        }
+    }
+}
+
+#[test]
+fn extract_and_deposit_match_the_bit_loops() {
+    let masks = words(4);
+    for (i, word) in words(5).into_iter().enumerate() {
+        let mask = masks[(i * 7919) % masks.len()];
+        let extracted = naive_extract(word, mask);
+        assert_eq!(broadword::extract_bits(word, mask), extracted, "word {:#x} mask {:#x}", word, mask);
+        assert_eq!(portable::extract_bits(word, mask), extracted, "portable, word {:#x} mask {:#x}", word, mask);
+
+        let deposited = naive_deposit(word, mask);
+        assert_eq!(broadword::deposit_bits(word, mask), deposited, "word {:#x} mask {:#x}", word, mask);
+        assert_eq!(portable::deposit_bits(word, mask), deposited, "portable, word {:#x} mask {:#x}", word, mask);
+
+        // Depositing what was extracted gives back the bits under the mask
+        assert_eq!(broadword::deposit_bits(extracted, mask), word & mask);
+    }
+}
+
+#[test]
+fn reverse_and_high_bits() {
+    for word in words(6) {
+        for len in 0..=64 {
+            assert_eq!(broadword::reverse_bits(word, len), naive_reverse(word, len), "word {:#x} len {}", word, len);
+            let high = if len == 0 { 0 } else { word >> (64 - len) };
+            assert_eq!(broadword::high_bits(word, len), high, "word {:#x} len {}", word, len);
+        }
+        assert_eq!(broadword::reverse_bits(broadword::reverse_bits(word, 64), 64), word);
+    }
+}
+
+#[test]
+fn read_bits_straddles_words() {
+    let words = words(7);
+    let words = &words[words.len() - 8..];
+    for start in 0..words.len() * 64 - 64 {
+        for len in 0..=64 {
+            let expected: u64 = (0..len).map(|i| {
+                let pos = start + i;
+                (bit(words[pos / 64], pos % 64) as u64) << i
+            }).sum();
+            assert_eq!(broadword::read_bits(words, start, len), expected, "start {} len {}", start, len);
+        }
+    }
+}
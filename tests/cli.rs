@@ -0,0 +1,109 @@
+// The command line tool end to end: build from text files, then verify,
+// query and inspect what was saved.
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+mod common;
+use common::{run_err, run_ok, temp_file};
+
+#[test]
+fn rank_build_verify_query() {
+    let input = temp_file("rank_positions.txt");
+    let output = temp_file("rank.bin");
+    std::fs::write(&input, "3\n0\n64\n65\n").unwrap();
+
+    let built = run_ok(&["build", "rank", &input, &output, "--size", "100"]);
+    assert_eq!(built, "built a bit vector of size 100 with 4 ones\n");
+    assert_eq!(run_ok(&["verify", "rank", &output]), "ok: rank index of size 100 with 4 ones\n");
+
+    assert_eq!(run_ok(&["query", "rank", &output, "access", "0", "1", "64"]), "1\n0\n1\n");
+    assert_eq!(run_ok(&["query", "rank", &output, "rank", "0", "4", "66", "100"]), "0\n2\n4\n4\n");
+    assert_eq!(run_ok(&["query", "rank", &output, "select", "0", "1", "3", "5"]), "0\n1\n65\n-\n");
+    run_err(&["query", "rank", &output, "access", "100"]);
+
+    // Queries from stdin, one per line
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hw2_code"))
+        .args(["query", "rank", &output])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"rank 65\n\nselect 4\naccess 2\n").unwrap();
+    let result = child.wait_with_output().unwrap();
+    assert!(result.status.success());
+    assert_eq!(String::from_utf8(result.stdout).unwrap(), "3\n66\n0\n");
+
+    for blocks in ["theoretical", "speed", "16/256"] {
+        run_ok(&["build", "rank", &input, &output, "--size", "100", "--blocks", blocks]);
+        run_ok(&["verify", "rank", &output]);
+        assert_eq!(run_ok(&["query", "rank", &output, "rank", "66"]), "4\n");
+    }
+    run_err(&["build", "rank", &input, &output, "--blocks", "space"]);
+
+    let json = run_ok(&["info", "rank", &output, "--report", "json"]);
+    let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(report["name"], "RankSelect");
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn sparse_build_verify_query() {
+    let input = temp_file("sparse.tsv");
+    let output = temp_file("sparse.bin");
+    std::fs::write(&input, "2\ttwo\n7\tseven\n5\tfive\n").unwrap();
+
+    assert_eq!(
+        run_ok(&["build", "sparse", &input, &output, "--size", "10"]),
+        "built a sparse array of size 10 with 3 elements\n"
+    );
+    assert_eq!(run_ok(&["verify", "sparse", &output]), "ok: sparse array of size 10 with 3 elements\n");
+    assert_eq!(run_ok(&["query", "sparse", &output, "get", "5", "6"]), "five\n-\n");
+    assert_eq!(run_ok(&["query", "sparse", &output, "at-rank", "0", "2", "3"]), "two\nseven\n-\n");
+    assert_eq!(run_ok(&["query", "sparse", &output, "index-of", "1"]), "5\n");
+    assert_eq!(run_ok(&["query", "sparse", &output, "num-at", "5"]), "2\n");
+    assert_eq!(run_ok(&["query", "sparse", &output, "range", "3", "10"]), "5\tfive\n7\tseven\n");
+
+    // A corrupt element length is an error, not an allocation of that size
+    let mut bytes = std::fs::read(&output).unwrap();
+    // The size and two words of the bit vector, then the number of elements
+    let first_len = 8 + 2 * 8 + 8;
+    bytes[first_len..first_len + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    std::fs::write(&output, &bytes).unwrap();
+    let error = run_err(&["verify", "sparse", &output]);
+    assert!(error.contains(&output), "{}", error);
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn bit_vector_build_verify_query() {
+    let input = temp_file("bits.txt");
+    let output = temp_file("bits.bin");
+    std::fs::write(&input, "0110 1\n").unwrap();
+
+    assert_eq!(
+        run_ok(&["build", "bitvector", &input, &output, "--format", "bits"]),
+        "built a bit vector of size 5 with 3 ones\n"
+    );
+    assert_eq!(run_ok(&["verify", "bitvector", &output]), "ok: bit vector of size 5 with 3 ones\n");
+    assert_eq!(run_ok(&["query", "bitvector", &output, "access", "0", "1", "4"]), "0\n1\n1\n");
+
+    // A truncated file is reported as such
+    let bytes = std::fs::read(&output).unwrap();
+    std::fs::write(&output, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(run_err(&["verify", "bitvector", &output]).contains("truncated"));
+
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn usage_errors() {
+    run_err(&["build", "rank"]);
+    run_err(&["verify", "nothing", "file"]);
+    run_err(&["build", "bitvector", "in", "out", "--blocks", "speed"]);
+    assert!(run_ok(&[]).starts_with("usage:"));
+}
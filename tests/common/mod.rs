@@ -0,0 +1,54 @@
+// Helpers shared by the integration tests. Each test file is its own
+// crate, so not every file uses every helper.
+#![allow(dead_code)]
+
+use std::process::{Command, Output};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::bit_vector::BitVector;
+
+// A path in the temp directory for name. Every test binary is its own
+// process, so the pid keeps files of different test files apart and the
+// name only has to be unique within one file.
+pub fn temp_file(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("hw2_code_{}_{}", std::process::id(), name))
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+// A seeded bit vector of size bits, each set with probability density
+pub fn random_bit_vector(seed: u64, size: usize, density: f64) -> BitVector {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut bit_vector = BitVector::new(size);
+    for i in 0..size {
+        if rng.gen_bool(density) {
+            bit_vector.set(i, true);
+        }
+    }
+    bit_vector
+}
+
+// Runs the command line tool with args
+pub fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hw2_code")).args(args).output().unwrap()
+}
+
+// Runs the tool and returns its stdout, failing the test if it fails
+pub fn run_ok(args: &[&str]) -> String {
+    let output = run(args);
+    assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// Runs the tool expecting it to fail with an error rather than a panic
+pub fn run_err(args: &[&str]) -> String {
+    let output = run(args);
+    assert_eq!(output.status.code(), Some(1), "{:?} should fail", args);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error: "), "{:?}: {}", args, stderr);
+    stderr
+}
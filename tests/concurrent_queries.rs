@@ -0,0 +1,124 @@
+// Many threads querying the same finalized structures at once. Every
+// answer is checked against one computed up front on the main thread.
+use std::sync::Arc;
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::batch::QueryOrder;
+use hw2_code::bit_vector::BitVector;
+use hw2_code::rank_select::RankSelect;
+use hw2_code::rank_support::RankSupport;
+use hw2_code::select_support::SelectSupport;
+use hw2_code::sparse_array::SparseArray;
+
+mod common;
+use common::random_bit_vector;
+
+const NUM_THREADS: usize = 16;
+const QUERIES_PER_THREAD: usize = 20_000;
+const SIZE: usize = 200_000;
+
+// The rank of every position and the position of every one
+fn expected_answers(bit_vector: &BitVector) -> (Vec<u64>, Vec<u64>) {
+    let mut ranks = vec![0];
+    let mut selects = vec![0];
+    for i in 0..bit_vector.size() {
+        if bit_vector.get(i) {
+            selects.push(i as u64 + 1);
+        }
+        ranks.push(selects.len() as u64 - 1);
+    }
+    (ranks, selects)
+}
+
+#[test]
+fn shared_rank_select_from_spawned_threads() {
+    let bit_vector = random_bit_vector(1, SIZE, 0.2);
+    let (ranks, selects) = expected_answers(&bit_vector);
+    let rank_select = Arc::new(RankSelect::new(bit_vector));
+    let expected = Arc::new((ranks, selects));
+
+    let handles: Vec<_> = (0..NUM_THREADS).map(|t| {
+        let rank_select = Arc::clone(&rank_select);
+        let expected = Arc::clone(&expected);
+        thread::spawn(move || {
+            let (ranks, selects) = &*expected;
+            let mut rng = StdRng::seed_from_u64(t as u64);
+            for _ in 0..QUERIES_PER_THREAD {
+                let i = rng.gen_range(0..=SIZE);
+                assert_eq!(rank_select.rank1(i), ranks[i]);
+                let r = rng.gen_range(0..selects.len() as u64);
+                assert_eq!(rank_select.select1(r), selects[r as usize]);
+            }
+            // And batches, which every thread answers into its own output
+            let positions: Vec<usize> = (0..1000).map(|_| rng.gen_range(0..=SIZE)).collect();
+            let mut out = vec![0; positions.len()];
+            rank_select.rank1_batch(&positions, &mut out, QueryOrder::Sorted);
+            for (&i, &rank) in positions.iter().zip(&out) {
+                assert_eq!(rank, ranks[i]);
+            }
+        })
+    }).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn borrowed_rank_and_select_support_from_scoped_threads() {
+    let bit_vector = random_bit_vector(2, SIZE, 0.2);
+    let (ranks, selects) = expected_answers(&bit_vector);
+    let rank_support = RankSupport::new(&bit_vector);
+    let select_support = SelectSupport::new(&rank_support);
+
+    thread::scope(|scope| {
+        for t in 0..NUM_THREADS {
+            let (ranks, selects, rank_support, select_support) = (&ranks, &selects, &rank_support, &select_support);
+            scope.spawn(move || {
+                let mut rng = StdRng::seed_from_u64(100 + t as u64);
+                for _ in 0..QUERIES_PER_THREAD {
+                    let i = rng.gen_range(0..=SIZE);
+                    assert_eq!(rank_support.rank1(i), ranks[i]);
+                    let r = rng.gen_range(0..selects.len() as u64);
+                    assert_eq!(select_support.select1(r), selects[r as usize]);
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn shared_sparse_array_from_spawned_threads() {
+    let bit_vector = random_bit_vector(3, SIZE, 0.2);
+    let mut sparse_array = SparseArray::create(SIZE as u64);
+    for i in 0..SIZE {
+        if bit_vector.get(i) {
+            sparse_array.append(format!("value {}", i), i as u64);
+        }
+    }
+    sparse_array.finalize();
+    let sparse_array = Arc::new(sparse_array);
+    let bit_vector = Arc::new(bit_vector);
+
+    let handles: Vec<_> = (0..NUM_THREADS).map(|t| {
+        let sparse_array = Arc::clone(&sparse_array);
+        let bit_vector = Arc::clone(&bit_vector);
+        thread::spawn(move || {
+            let mut rng = StdRng::seed_from_u64(200 + t as u64);
+            let mut s = String::new();
+            for _ in 0..QUERIES_PER_THREAD {
+                let i = rng.gen_range(0..SIZE);
+                let present = sparse_array.get_at_index(i as u64, &mut s);
+                assert_eq!(present, bit_vector.get(i));
+                if present {
+                    assert_eq!(s, format!("value {}", i));
+                }
+            }
+        })
+    }).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+// The dictionary-encoded sparse array against the SparseArray<String> it
+// encodes, its code widths and what its stats report.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::batch::QueryOrder;
+use hw2_code::position_index::IndexKind;
+use hw2_code::space_report::SpaceUsage;
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::sparse_dict_array::SparseDictArray;
+
+mod common;
+use common::temp_file;
+
+// A sparse array whose values are drawn from num_distinct labels
+fn labelled_sparse_array(seed: u64, size: u64, num_distinct: usize, kind: IndexKind) -> SparseArray {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut sparse_array = SparseArray::create_with_index(size, kind);
+    for i in 0..size {
+        if rng.gen_bool(0.2) {
+            sparse_array.append(format!("label_{}", rng.gen_range(0..num_distinct)), i);
+        }
+    }
+    sparse_array.finalize();
+    sparse_array
+}
+
+#[test]
+fn answers_like_the_sparse_array() {
+    for (seed, kind) in [(1, IndexKind::Plain), (2, IndexKind::SortedList)] {
+        let sparse_array = labelled_sparse_array(seed, 5000, 37, kind);
+        let dict_array = SparseDictArray::from_sparse_array(&sparse_array);
+        dict_array.verify().unwrap();
+        assert_eq!(dict_array.index_kind(), kind);
+        assert_eq!(dict_array.num_elem(), sparse_array.num_elem());
+        for i in 0..dict_array.size() + 1 {
+            assert_eq!(dict_array.get(i), sparse_array.get(i).map(|s| s.as_str()), "get({})", i);
+            assert_eq!(dict_array.num_elem_at(i), sparse_array.num_elem_at(i), "num_elem_at({})", i);
+        }
+        assert_eq!(dict_array.num_elem_at(u64::MAX), dict_array.num_elem());
+        for r in 0..=dict_array.num_elem() {
+            assert_eq!(dict_array.get_at_rank(r), sparse_array.iter().nth(r as usize).map(|(_, s)| s.as_str()));
+            assert_eq!(dict_array.get_index_of(r), sparse_array.get_index_of(r));
+        }
+
+        let indices: Vec<u64> = (0..dict_array.size()).rev().step_by(7).collect();
+        let expected: Vec<Option<&str>> = indices.iter().map(|&i| sparse_array.get(i).map(|s| s.as_str())).collect();
+        for order in [QueryOrder::AsGiven, QueryOrder::Sorted] {
+            let mut out = vec![None; indices.len()];
+            dict_array.get_at_index_batch(&indices, &mut out, order);
+            assert_eq!(out, expected, "{:?}", order);
+        }
+    }
+}
+
+#[test]
+fn appending_then_finalizing_sorts_the_dictionary() {
+    let values = ["pear", "apple", "pear", "fig", "apple", "pear"];
+    let mut dict_array = SparseDictArray::create(20);
+    for (k, value) in values.iter().enumerate() {
+        dict_array.append(value, 3 * k as u64);
+    }
+    // Before finalize the codes follow the order values first showed up in
+    assert_eq!(dict_array.dictionary().collect::<Vec<_>>(), ["pear", "apple", "fig"]);
+    dict_array.finalize();
+    dict_array.verify().unwrap();
+    assert_eq!(dict_array.dictionary().collect::<Vec<_>>(), ["apple", "fig", "pear"]);
+    let expected: Vec<(u64, &str)> = values.iter().enumerate().map(|(k, &v)| (3 * k as u64, v)).collect();
+    assert_eq!(dict_array.iter().collect::<Vec<_>>(), expected);
+    assert_eq!(dict_array.stats().code_width, 2);
+}
+
+// Building keeps the distinct values in the dictionary only, with a slot
+// per value to find them again
+#[test]
+fn building_holds_each_value_once() {
+    let mut rng = StdRng::seed_from_u64(6);
+    let size = 50_000;
+    let long_value = |label: u64| format!("{:0>100}", label);
+    let mut dict_array = SparseDictArray::create(size);
+    let mut labels = Vec::new();
+    for pos in 0..size {
+        let label = rng.gen_range(0..5000);
+        dict_array.append(&long_value(label), pos);
+        labels.push(label);
+    }
+
+    let report = dict_array.space_report();
+    let dictionary_bits = report.child("dictionary").unwrap().bits;
+    let building_bits = report.child("building").unwrap().bits;
+    assert!(building_bits * 4 < dictionary_bits, "{} bits to find {} bits of values", building_bits, dictionary_bits);
+
+    dict_array.finalize();
+    dict_array.verify().unwrap();
+    assert_eq!(dict_array.num_distinct(), 5000);
+    for (pos, &label) in labels.iter().enumerate() {
+        assert_eq!(dict_array.get(pos as u64), Some(long_value(label).as_str()));
+    }
+}
+
+#[test]
+fn codes_take_ceil_log2_distinct_bits() {
+    for (num_distinct, width) in [(1, 0), (2, 1), (3, 2), (4, 2), (5, 3), (200, 8), (257, 9)] {
+        let mut dict_array = SparseDictArray::create(1000);
+        for i in 0..1000 {
+            dict_array.append(&(i % num_distinct).to_string(), i as u64);
+        }
+        dict_array.finalize();
+        let stats = dict_array.stats();
+        assert_eq!(stats.num_distinct, num_distinct as u64);
+        assert_eq!(stats.code_width, width, "{} distinct values", num_distinct);
+    }
+}
+
+#[test]
+fn repeated_values_compress() {
+    let mut sparse_array = SparseArray::create(100_000);
+    for i in (0..100_000).step_by(3) {
+        sparse_array.append("hello world".to_string(), i);
+    }
+    sparse_array.finalize();
+    let dict_array = SparseDictArray::from_sparse_array(&sparse_array);
+    let stats = dict_array.stats();
+    assert_eq!(stats.num_distinct, 1);
+    assert_eq!(stats.code_width, 0);
+    assert_eq!(stats.value_bytes, 11 * sparse_array.num_elem());
+    assert!(stats.compression_ratio() > 1000.0, "ratio {}", stats.compression_ratio());
+    assert!(dict_array.get_overhead() < sparse_array.get_overhead() / 10);
+
+    // All different values can't be compressed, the codes only add to them
+    let sparse_array = labelled_sparse_array(3, 10_000, usize::MAX, IndexKind::Plain);
+    let stats = SparseDictArray::from_sparse_array(&sparse_array).stats();
+    assert!(stats.compression_ratio() < 1.0, "ratio {}", stats.compression_ratio());
+
+    // An empty array has nothing to compress
+    let mut empty = SparseDictArray::create(10);
+    empty.finalize();
+    assert_eq!(empty.stats().num_elem, 0);
+    assert!(empty.stats().compression_ratio() <= 1.0);
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    for (seed, kind) in [(4, IndexKind::Plain), (5, IndexKind::SortedList)] {
+        let dict_array = SparseDictArray::from_sparse_array(&labelled_sparse_array(seed, 4000, 10, kind));
+        let file = temp_file(&format!("round_trip_{}", seed));
+        dict_array.save(&file).unwrap();
+        let loaded = SparseDictArray::load(&file).unwrap();
+        assert_eq!(loaded.size(), dict_array.size());
+        assert_eq!(loaded.stats().num_distinct, dict_array.stats().num_distinct);
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), dict_array.iter().collect::<Vec<_>>());
+
+        // The last byte holds the code of the last element, setting its
+        // high bits points it past the dictionary
+        let mut bytes = std::fs::read(&file).unwrap();
+        *bytes.last_mut().unwrap() |= 0xF0;
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(SparseDictArray::load(&file).is_err());
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    // Nothing to save until the dictionary is sorted
+    let unfinished = SparseDictArray::create(10);
+    assert!(unfinished.save(&temp_file("unfinished")).is_err());
+}
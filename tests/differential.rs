@@ -0,0 +1,571 @@
+// Differential tests: every structure is built from seeded random inputs
+// and every query is compared against a naive Vec<bool> / Vec<Option<String>>
+// model. A mismatch (or a panic) is shrunk to the smallest input that still
+// fails before the test reports it.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::panic::{self, AssertUnwindSafe};
+
+use hw2_code::batch::QueryOrder;
+use hw2_code::bit_vector::BitVector;
+use hw2_code::dynamic_bit_vector::DynamicBitVector;
+use hw2_code::position_index::IndexKind;
+use hw2_code::rank_select::RankSelect;
+use hw2_code::rank_support::{BlockSizes, RankSupport};
+use hw2_code::select_support::SelectSupport;
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::sparse_dict_array::SparseDictArray;
+use hw2_code::sparse_str_array::SparseStrArray;
+
+//
+// Naive reference implementations
+//
+
+// Bits in a Vec<bool>. The ranks and the positions of the ones are
+// counted once up front so the model answers every query by a lookup.
+struct NaiveBits<'a> {
+    bits: &'a [bool],
+    // ranks[i] is the number of ones before i, for i in 0..=len
+    ranks: Vec<u64>,
+    ones: Vec<u64>,
+}
+
+impl<'a> NaiveBits<'a> {
+    fn new(bits: &'a [bool]) -> Self {
+        let mut ranks = vec![0];
+        let mut ones = Vec::new();
+        for (i, &bit) in bits.iter().enumerate() {
+            if bit {
+                ones.push(i as u64);
+            }
+            ranks.push(ones.len() as u64);
+        }
+        Self { bits, ranks, ones }
+    }
+
+    fn access(&self, i: usize) -> bool {
+        self.bits[i]
+    }
+
+    fn num_ones(&self) -> u64 {
+        self.ones.len() as u64
+    }
+
+    // Number of ones before i
+    fn rank1(&self, i: usize) -> u64 {
+        self.ranks[i]
+    }
+
+    // The first j with rank1(j) = i, u64::MAX if there are fewer than i ones
+    fn select1(&self, i: u64) -> u64 {
+        match i {
+            0 => 0,
+            i if i <= self.num_ones() => self.ones[i as usize - 1] + 1,
+            _ => u64::MAX,
+        }
+    }
+}
+
+// An edit made to a sparse array after it is finalized
+#[derive(Clone, Debug)]
+enum Edit {
+    Set(u64, String),
+    Remove(u64),
+}
+
+// The dense array a sparse array stands for
+struct NaiveSparse {
+    values: Vec<Option<String>>,
+}
+
+impl NaiveSparse {
+    fn apply(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Set(i, value) => self.values[*i as usize] = Some(value.clone()),
+            Edit::Remove(i) => self.values[*i as usize] = None,
+        }
+    }
+
+    fn present(&self) -> Vec<bool> {
+        self.values.iter().map(|v| v.is_some()).collect()
+    }
+}
+
+//
+// Comparing a structure against the model
+//
+
+// Runs a check and turns a panic into a failure so it can be shrunk too
+fn run_check<F: FnOnce() -> Result<(), String>>(check: F) -> Result<(), String> {
+    match panic::catch_unwind(AssertUnwindSafe(check)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Err(format!("panicked: {}", message))
+        }
+    }
+}
+
+fn expect_eq<T: PartialEq + std::fmt::Debug>(what: &str, got: T, expected: T) -> Result<(), String> {
+    if got == expected {
+        Ok(())
+    } else {
+        Err(format!("{}: got {:?}, expected {:?}", what, got, expected))
+    }
+}
+
+fn check_bits(bits: &[bool]) -> Result<(), String> {
+    run_check(|| {
+        let naive = NaiveBits::new(bits);
+        let size = bits.len();
+        let num_ones = naive.num_ones();
+
+        let mut bit_vector = BitVector::new(size);
+        for (i, &bit) in bits.iter().enumerate() {
+            bit_vector.set(i, bit);
+        }
+        for i in 0..size {
+            expect_eq(&format!("BitVector::get({})", i), bit_vector.get(i), naive.access(i))?;
+        }
+        expect_eq("BitVector::count_ones()", bit_vector.count_ones() as u64, num_ones)?;
+
+        // Every preset has to answer the same, the space optimal one is
+        // also the one the rest of the checks use
+        for block_sizes in [BlockSizes::Speed, BlockSizes::Custom { chunk_size: 8, superchunk_size: 40 }] {
+            let rank_support = RankSupport::with_block_sizes(&bit_vector, block_sizes).unwrap();
+            for i in 0..=size {
+                expect_eq(&format!("{:?} RankSupport::rank1({})", block_sizes, i), rank_support.rank1(i), naive.rank1(i))?;
+            }
+        }
+        let rank_support = RankSupport::new(&bit_vector);
+        for i in 0..=size {
+            expect_eq(&format!("RankSupport::rank1({})", i), rank_support.rank1(i), naive.rank1(i))?;
+        }
+
+        let select_support = SelectSupport::new(&rank_support);
+        for i in 0..=num_ones + 1 {
+            expect_eq(&format!("SelectSupport::select1({})", i), select_support.select1(i), naive.select1(i))?;
+        }
+
+        // Batches in both orders, with the queries back to front so
+        // sorting them actually moves them
+        let positions: Vec<usize> = (0..=size).rev().collect();
+        let ranks: Vec<u64> = (0..=num_ones + 1).rev().collect();
+        for order in [QueryOrder::AsGiven, QueryOrder::Sorted] {
+            let mut out = vec![0; positions.len()];
+            rank_support.rank1_batch(&positions, &mut out, order);
+            let expected: Vec<u64> = positions.iter().map(|&i| naive.rank1(i)).collect();
+            expect_eq(&format!("RankSupport::rank1_batch {:?}", order), out, expected)?;
+
+            let mut out = vec![0; ranks.len()];
+            select_support.select1_batch(&ranks, &mut out, order);
+            let expected: Vec<u64> = ranks.iter().map(|&i| naive.select1(i)).collect();
+            expect_eq(&format!("SelectSupport::select1_batch {:?}", order), out, expected)?;
+        }
+
+        // What was saved has to answer the same after loading it back
+        let mut saved = Vec::new();
+        bit_vector.write_to(&mut saved).unwrap();
+        rank_support.write_to(&mut saved).unwrap();
+        let mut reader = &saved[..];
+        let loaded_bit_vector = BitVector::read_from(&mut reader).map_err(|e| e.to_string())?;
+        let loaded_rank_support = RankSupport::read_from(&loaded_bit_vector, &mut reader).map_err(|e| e.to_string())?;
+        loaded_rank_support.verify().map_err(|e| format!("loaded rank support: {}", e))?;
+        for i in 0..=size {
+            expect_eq(&format!("loaded RankSupport::rank1({})", i), loaded_rank_support.rank1(i), naive.rank1(i))?;
+        }
+
+        // The owned version reads the same bytes back
+        let mut saved = Vec::new();
+        RankSelect::from_rank_support(&rank_support).write_to(&mut saved).unwrap();
+        let rank_select = RankSelect::read_from(&mut &saved[..]).map_err(|e| e.to_string())?;
+        rank_select.verify().map_err(|e| format!("loaded rank select: {}", e))?;
+        for i in 0..=size {
+            expect_eq(&format!("RankSelect::rank1({})", i), rank_select.rank1(i), naive.rank1(i))?;
+        }
+        for i in 0..=num_ones + 1 {
+            expect_eq(&format!("RankSelect::select1({})", i), rank_select.select1(i), naive.select1(i))?;
+        }
+
+        let dynamic = DynamicBitVector::from_bit_vector(&bit_vector);
+        for i in 0..size {
+            expect_eq(&format!("DynamicBitVector::get({})", i), dynamic.get(i), naive.access(i))?;
+        }
+        for i in 0..=size {
+            expect_eq(&format!("DynamicBitVector::rank1({})", i), dynamic.rank1(i), naive.rank1(i))?;
+        }
+        for i in 0..=num_ones + 1 {
+            expect_eq(&format!("DynamicBitVector::select1({})", i), dynamic.select1(i), naive.select1(i))?;
+        }
+        Ok(())
+    })
+}
+
+// How a sparse array under test is built
+#[derive(Clone, Copy, Debug)]
+enum Build {
+    Plain,
+    SortedList,
+    // from_dense, which picks the index from the density
+    Dense,
+}
+
+#[derive(Clone, Debug)]
+struct SparseCase {
+    values: Vec<Option<String>>,
+    build: Build,
+    edits: Vec<Edit>,
+}
+
+fn build_sparse_array(case: &SparseCase) -> SparseArray {
+    let size = case.values.len() as u64;
+    let kind = match case.build {
+        Build::Plain => IndexKind::Plain,
+        Build::SortedList => IndexKind::SortedList,
+        Build::Dense => return SparseArray::from_dense(case.values.iter().cloned()),
+    };
+    let mut sparse_array = SparseArray::create_with_index(size, kind);
+    for (i, value) in case.values.iter().enumerate() {
+        if let Some(value) = value {
+            sparse_array.append(value.clone(), i as u64);
+        }
+    }
+    sparse_array.finalize();
+    sparse_array
+}
+
+fn check_sparse(case: &SparseCase) -> Result<(), String> {
+    run_check(|| {
+        let mut sparse_array = build_sparse_array(case);
+        let mut naive = NaiveSparse { values: case.values.clone() };
+        for edit in &case.edits {
+            match edit {
+                Edit::Set(i, value) => {
+                    let old = naive.values[*i as usize].clone();
+                    expect_eq(&format!("set({})", i), sparse_array.set(*i, value.clone()), Ok(old))?;
+                }
+                Edit::Remove(i) => {
+                    let had = naive.values[*i as usize].is_some();
+                    expect_eq(&format!("remove({})", i), sparse_array.remove(*i), had)?;
+                }
+            }
+            naive.apply(edit);
+        }
+
+        let size = naive.values.len() as u64;
+        let present_bits = naive.present();
+        let model = NaiveBits::new(&present_bits);
+        let present = &model.ones;
+        let num_elem = model.num_ones();
+        expect_eq("size()", sparse_array.size(), size)?;
+        expect_eq("num_elem()", sparse_array.num_elem(), num_elem)?;
+
+        let mut s = String::new();
+        for i in 0..size {
+            let expected = naive.values[i as usize].as_ref();
+            expect_eq(&format!("get({})", i), sparse_array.get(i), expected)?;
+            expect_eq(&format!("contains({})", i), sparse_array.contains(i), expected.is_some())?;
+            expect_eq(&format!("get_at_index({})", i), sparse_array.get_at_index(i, &mut s), expected.is_some())?;
+            if let Some(expected) = expected {
+                expect_eq(&format!("get_at_index({}) value", i), &s, expected)?;
+            }
+            let rank = model.rank1(i as usize);
+            let count = model.rank1(i as usize + 1);
+            expect_eq(&format!("num_elem_at({})", i), sparse_array.num_elem_at(i), count)?;
+            let next = present.get(rank as usize).copied();
+            expect_eq(&format!("next_present({})", i), sparse_array.next_present(i), next)?;
+            let prev = count.checked_sub(1).map(|r| present[r as usize]);
+            expect_eq(&format!("prev_present({})", i), sparse_array.prev_present(i), prev)?;
+        }
+        for i in 0..=size {
+            expect_eq(&format!("get_rank1({})", i), sparse_array.get_rank1(i as usize), model.rank1(i as usize))?;
+        }
+
+        let indices: Vec<u64> = (0..size + 2).rev().collect();
+        let expected: Vec<Option<&String>> = indices.iter().map(|&i| naive.values.get(i as usize).and_then(|v| v.as_ref())).collect();
+        for order in [QueryOrder::AsGiven, QueryOrder::Sorted] {
+            let mut out = vec![None; indices.len()];
+            sparse_array.get_at_index_batch(&indices, &mut out, order);
+            expect_eq(&format!("get_at_index_batch {:?}", order), &out, &expected)?;
+        }
+        for r in 0..=num_elem + 1 {
+            expect_eq(&format!("get_select1({})", r), sparse_array.get_select1(r), model.select1(r))?;
+            let expected_index = present.get(r as usize).copied().unwrap_or(u64::MAX);
+            expect_eq(&format!("get_index_of({})", r), sparse_array.get_index_of(r), expected_index)?;
+            let found = sparse_array.get_at_rank(r, &mut s);
+            expect_eq(&format!("get_at_rank({})", r), found, r < num_elem)?;
+            if found {
+                expect_eq(&format!("get_at_rank({}) value", r), Some(&s), naive.values[present[r as usize] as usize].as_ref())?;
+            }
+        }
+
+        let expected: Vec<(u64, &String)> = present.iter().map(|&p| (p, naive.values[p as usize].as_ref().unwrap())).collect();
+        expect_eq("iter()", sparse_array.iter().collect::<Vec<_>>(), expected.clone())?;
+        expect_eq("iter().rev()", sparse_array.iter().rev().collect::<Vec<_>>(), expected.iter().rev().cloned().collect())?;
+        // Ranges on a grid of about ten starts and ends, including ones past the size
+        let step = (size as usize / 10).max(1);
+        for start in (0..=size).step_by(step) {
+            for end in (start..=size + 2).step_by(step) {
+                let in_range: Vec<(u64, &String)> = expected.iter().filter(|(p, _)| *p >= start && *p < end).cloned().collect();
+                expect_eq(&format!("count_in_range({}..{})", start, end), sparse_array.count_in_range(start..end), in_range.len() as u64)?;
+                expect_eq(&format!("range({}..{})", start, end), sparse_array.range(start..end).collect::<Vec<_>>(), in_range)?;
+            }
+        }
+        expect_eq("to_dense()", sparse_array.to_dense(), naive.values.clone())?;
+
+        // The string arena copy answers the same, with the edits applied
+        let str_array = SparseStrArray::from_sparse_array(&sparse_array);
+        expect_eq("SparseStrArray::verify()", str_array.verify().map_err(|e| e.to_string()), Ok(()))?;
+        expect_eq("SparseStrArray::num_elem()", str_array.num_elem(), num_elem)?;
+        for i in 0..size + 2 {
+            let expected = naive.values.get(i as usize).and_then(|v| v.as_deref());
+            expect_eq(&format!("SparseStrArray::get({})", i), str_array.get(i), expected)?;
+        }
+        for r in 0..=num_elem {
+            let expected = present.get(r as usize).map(|&p| naive.values[p as usize].as_deref().unwrap());
+            expect_eq(&format!("SparseStrArray::get_at_rank({})", r), str_array.get_at_rank(r), expected)?;
+        }
+        let expected_str: Vec<(u64, &str)> = expected.iter().map(|&(p, v)| (p, v.as_str())).collect();
+        expect_eq("SparseStrArray::iter()", str_array.iter().collect::<Vec<_>>(), expected_str.clone())?;
+
+        // And so does the dictionary encoded one
+        let dict_array = SparseDictArray::from_sparse_array(&sparse_array);
+        expect_eq("SparseDictArray::verify()", dict_array.verify().map_err(|e| e.to_string()), Ok(()))?;
+        expect_eq("SparseDictArray::iter()", dict_array.iter().collect::<Vec<_>>(), expected_str)?;
+        Ok(())
+    })
+}
+
+//
+// Shrinking
+//
+
+// Repeatedly replaces the case by the first smaller candidate that still
+// fails until none does. The result fails but none of its candidates do.
+fn shrink<C, S, F>(mut case: C, candidates: S, fails: F) -> C
+where
+    S: Fn(&C) -> Vec<C>,
+    F: Fn(&C) -> bool,
+{
+    'outer: loop {
+        for candidate in candidates(&case) {
+            if fails(&candidate) {
+                case = candidate;
+                continue 'outer;
+            }
+        }
+        return case;
+    }
+}
+
+// Shorter bit vectors first, then fewer ones
+fn smaller_bits(bits: &[bool]) -> Vec<Vec<bool>> {
+    let mut candidates = Vec::new();
+    let n = bits.len();
+    for len in [0, n / 2, n - n / 4, n.saturating_sub(1)] {
+        if len < n {
+            candidates.push(bits[..len].to_vec());
+            candidates.push(bits[n - len..].to_vec());
+        }
+    }
+    for (i, &bit) in bits.iter().enumerate() {
+        if bit {
+            let mut fewer = bits.to_vec();
+            fewer[i] = false;
+            candidates.push(fewer);
+        }
+    }
+    candidates
+}
+
+// Fewer edits, then shorter arrays, then fewer and shorter elements
+fn smaller_sparse(case: &SparseCase) -> Vec<SparseCase> {
+    let mut candidates = Vec::new();
+    if !case.edits.is_empty() {
+        let half = case.edits.len() / 2;
+        candidates.push(SparseCase { edits: case.edits[..half].to_vec(), ..case.clone() });
+        candidates.push(SparseCase { edits: case.edits[half..].to_vec(), ..case.clone() });
+        for i in 0..case.edits.len() {
+            let mut edits = case.edits.clone();
+            edits.remove(i);
+            candidates.push(SparseCase { edits, ..case.clone() });
+        }
+    }
+    let n = case.values.len();
+    for len in [0, n / 2, n.saturating_sub(1)] {
+        if len < n {
+            let edits = case.edits.iter().filter(|edit| match edit {
+                Edit::Set(i, _) | Edit::Remove(i) => (*i as usize) < len,
+            });
+            candidates.push(SparseCase { values: case.values[..len].to_vec(), edits: edits.cloned().collect(), ..case.clone() });
+        }
+    }
+    for i in 0..n {
+        match &case.values[i] {
+            Some(value) if !value.is_empty() => {
+                let mut values = case.values.clone();
+                values[i] = Some(String::new());
+                candidates.push(SparseCase { values, ..case.clone() });
+            }
+            Some(_) => {
+                let mut values = case.values.clone();
+                values[i] = None;
+                candidates.push(SparseCase { values, ..case.clone() });
+            }
+            None => {}
+        }
+    }
+    candidates
+}
+
+fn ones(bits: &[bool]) -> Vec<usize> {
+    (0..bits.len()).filter(|&i| bits[i]).collect()
+}
+
+// Shrinks a failing bit vector and panics with the minimal one
+fn assert_bits_pass(bits: Vec<bool>, origin: &str) {
+    let Err(error) = check_bits(&bits) else {
+        return;
+    };
+    let minimal = shrink(bits, |bits| smaller_bits(bits), |bits| check_bits(bits).is_err());
+    panic!(
+        "{} failed: {}\nminimal failing case: size {} with ones at {:?}\nwhich fails with: {}",
+        origin,
+        error,
+        minimal.len(),
+        ones(&minimal),
+        check_bits(&minimal).unwrap_err()
+    );
+}
+
+fn assert_sparse_passes(case: SparseCase, origin: &str) {
+    let Err(error) = check_sparse(&case) else {
+        return;
+    };
+    let minimal = shrink(case, smaller_sparse, |case| check_sparse(case).is_err());
+    panic!(
+        "{} failed: {}\nminimal failing case: {:?}\nwhich fails with: {}",
+        origin,
+        error,
+        minimal,
+        check_sparse(&minimal).unwrap_err()
+    );
+}
+
+//
+// Inputs
+//
+
+// Sizes around word boundaries and around every power of two, where
+// my_log and so the chunk sizes change
+fn edge_sizes() -> Vec<usize> {
+    let mut sizes = vec![0, 1, 2, 3, 63, 64, 65, 127, 128, 129];
+    for k in 2..=13 {
+        sizes.extend([(1 << k) - 1, 1 << k, (1 << k) + 1]);
+    }
+    sizes.sort();
+    sizes.dedup();
+    sizes
+}
+
+const DENSITIES: [f64; 6] = [0.0, 0.01, 0.1, 0.5, 0.9, 1.0];
+
+fn random_bits(rng: &mut StdRng, size: usize, density: f64) -> Vec<bool> {
+    (0..size).map(|_| rng.gen_bool(density)).collect()
+}
+
+// Sizes are drawn log-uniformly so small vectors, where the edge cases
+// are, come up as often as large ones
+fn random_size(rng: &mut StdRng, max_size: usize) -> usize {
+    let log = rng.gen_range(0.0..((max_size + 1) as f64).ln());
+    log.exp() as usize - 1
+}
+
+fn random_sparse_case(rng: &mut StdRng, size: usize, density: f64, num_edits: usize) -> SparseCase {
+    let values = (0..size).map(|i| rng.gen_bool(density).then(|| format!("v{}", i))).collect();
+    let build = [Build::Plain, Build::SortedList, Build::Dense][rng.gen_range(0..3)];
+    // There is nowhere to edit in an empty array
+    let num_edits = if size == 0 { 0 } else { num_edits };
+    let edits = (0..num_edits)
+        .map(|k| {
+            let i = rng.gen_range(0..size as u64);
+            if rng.gen_bool(0.5) {
+                Edit::Set(i, format!("e{}", k))
+            } else {
+                Edit::Remove(i)
+            }
+        })
+        .collect();
+    SparseCase { values, build, edits }
+}
+
+//
+// Tests
+//
+
+#[test]
+fn bit_structures_at_edge_sizes() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for size in edge_sizes() {
+        for density in DENSITIES {
+            let bits = random_bits(&mut rng, size, density);
+            assert_bits_pass(bits, &format!("size {} density {}", size, density));
+        }
+    }
+}
+
+#[test]
+fn bit_structures_on_random_inputs() {
+    for seed in 0..2000 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let size = random_size(&mut rng, 4096);
+        let density = DENSITIES[rng.gen_range(0..DENSITIES.len())];
+        let bits = random_bits(&mut rng, size, density);
+        assert_bits_pass(bits, &format!("seed {}", seed));
+    }
+}
+
+#[test]
+fn sparse_arrays_at_edge_sizes() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for size in edge_sizes().into_iter().filter(|&size| size <= 1025) {
+        for density in DENSITIES {
+            let case = random_sparse_case(&mut rng, size, density, 0);
+            assert_sparse_passes(case, &format!("size {} density {}", size, density));
+        }
+    }
+}
+
+#[test]
+fn sparse_arrays_on_random_inputs_with_edits() {
+    for seed in 0..1000 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let size = random_size(&mut rng, 1024);
+        let density = DENSITIES[rng.gen_range(0..DENSITIES.len())];
+        // Enough edits that some cases go past the merge threshold
+        let num_edits = rng.gen_range(0..200);
+        let case = random_sparse_case(&mut rng, size, density, num_edits);
+        assert_sparse_passes(case, &format!("seed {}", seed));
+    }
+}
+
+// The shrinker has to end on a case that fails with no failing candidate
+#[test]
+fn shrinking_finds_a_minimal_case() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let bits = random_bits(&mut rng, 1000, 0.3);
+    // Pretend anything with two ones at least 10 apart is a bug
+    let fails = |bits: &Vec<bool>| {
+        let ones = ones(bits);
+        ones.len() >= 2 && ones[ones.len() - 1] - ones[0] >= 10
+    };
+    assert!(fails(&bits));
+    let minimal = shrink(bits, |bits| smaller_bits(bits), fails);
+    assert!(fails(&minimal));
+    assert_eq!(ones(&minimal).len(), 2);
+    assert!(smaller_bits(&minimal).iter().all(|candidate| !fails(candidate)));
+}
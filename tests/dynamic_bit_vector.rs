@@ -0,0 +1,169 @@
+// Random inserts, deletes, sets and pushes on a DynamicBitVector against
+// a Vec<bool>, checking every query, the AVL height bound and that leaves
+// stay at least half full after each batch of edits.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::bit_vector::BitVector;
+use hw2_code::dynamic_bit_vector::DynamicBitVector;
+
+// Every leaf but a lone root holds at least half a word of bits
+fn max_leaves(size: usize) -> usize {
+    (size / 32).max(1)
+}
+
+// An AVL tree with n leaves is at most 1.4405 log2(n + 2) - 0.3277 high
+fn max_height(size: usize) -> usize {
+    let leaves = max_leaves(size) as f64;
+    (1.4405 * (leaves + 2.0).log2() - 0.3277).floor() as usize
+}
+
+fn check(dynamic: &DynamicBitVector, model: &[bool], context: &str) {
+    assert_eq!(dynamic.size(), model.len(), "{}: size", context);
+    let ones: Vec<usize> = (0..model.len()).filter(|&i| model[i]).collect();
+    assert_eq!(dynamic.num_ones(), ones.len(), "{}: num_ones", context);
+    assert!(
+        dynamic.height() <= max_height(model.len()),
+        "{}: height {} for {} bits", context, dynamic.height(), model.len()
+    );
+    assert!(
+        dynamic.num_leaves() <= max_leaves(model.len()),
+        "{}: {} leaves for {} bits", context, dynamic.num_leaves(), model.len()
+    );
+
+    let mut rank = 0;
+    for (i, &bit) in model.iter().enumerate() {
+        assert_eq!(dynamic.get(i), bit, "{}: get({})", context, i);
+        assert_eq!(dynamic.rank1(i), rank, "{}: rank1({})", context, i);
+        rank += bit as u64;
+    }
+    assert_eq!(dynamic.rank1(model.len()), rank, "{}: rank1 of the size", context);
+
+    assert_eq!(dynamic.select1(0), 0, "{}: select1(0)", context);
+    for (r, &pos) in ones.iter().enumerate() {
+        assert_eq!(dynamic.select1(r as u64 + 1), pos as u64 + 1, "{}: select1({})", context, r + 1);
+    }
+    assert_eq!(dynamic.select1(ones.len() as u64 + 1), u64::MAX, "{}: select1 past the ones", context);
+
+    let bit_vector = dynamic.to_bit_vector();
+    assert_eq!(bit_vector.size(), model.len(), "{}: to_bit_vector size", context);
+    assert!((0..model.len()).all(|i| bit_vector.get(i) == model[i]), "{}: to_bit_vector bits", context);
+}
+
+// A batch of random edits, mostly inserts while grow is true and mostly
+// deletes otherwise, applied to both
+fn random_batch(rng: &mut StdRng, dynamic: &mut DynamicBitVector, model: &mut Vec<bool>, num_edits: usize, grow: bool) {
+    let insert_share = if grow { 0.6 } else { 0.15 };
+    for _ in 0..num_edits {
+        let value = rng.gen_bool(0.4);
+        let roll: f64 = rng.gen();
+        if model.is_empty() || roll < insert_share {
+            let index = rng.gen_range(0..=model.len());
+            dynamic.insert(index, value);
+            model.insert(index, value);
+        } else if roll < insert_share + 0.1 {
+            dynamic.push(value);
+            model.push(value);
+        } else if roll < insert_share + 0.25 {
+            let index = rng.gen_range(0..model.len());
+            dynamic.set(index, value);
+            model[index] = value;
+        } else {
+            let index = rng.gen_range(0..model.len());
+            assert_eq!(dynamic.delete(index), model.remove(index), "delete({})", index);
+        }
+    }
+}
+
+#[test]
+fn random_edits_match_a_vec() {
+    for seed in 0..20 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut model: Vec<bool> = (0..rng.gen_range(0..300)).map(|_| rng.gen_bool(0.5)).collect();
+        let mut bit_vector = BitVector::new(model.len());
+        for (i, &bit) in model.iter().enumerate() {
+            bit_vector.set(i, bit);
+        }
+        let mut dynamic = if seed % 2 == 0 { DynamicBitVector::from_bit_vector(&bit_vector) } else {
+            let mut dynamic = DynamicBitVector::new();
+            for &bit in &model {
+                dynamic.push(bit);
+            }
+            dynamic
+        };
+        check(&dynamic, &model, &format!("seed {} start", seed));
+
+        // Grow, then shrink back down, so leaves both split and merge
+        for batch in 0..30 {
+            let grow = batch < 20;
+            let num_edits = rng.gen_range(1..200);
+            random_batch(&mut rng, &mut dynamic, &mut model, num_edits, grow);
+            check(&dynamic, &model, &format!("seed {} batch {}", seed, batch));
+        }
+    }
+}
+
+#[test]
+fn deleting_everything_leaves_an_empty_vector() {
+    let mut rng = StdRng::seed_from_u64(100);
+    let mut dynamic = DynamicBitVector::new();
+    let mut model = Vec::new();
+    for _ in 0..1000 {
+        let value = rng.gen_bool(0.5);
+        dynamic.push(value);
+        model.push(value);
+    }
+    while !model.is_empty() {
+        let index = rng.gen_range(0..model.len());
+        assert_eq!(dynamic.delete(index), model.remove(index));
+        if model.len() % 97 == 0 {
+            check(&dynamic, &model, &format!("{} left", model.len()));
+        }
+    }
+    check(&dynamic, &model, "empty");
+    dynamic.insert(0, true);
+    check(&dynamic, &[true], "one again");
+}
+
+// Inserting at the same end over and over is what unbalances a tree that
+// does not rotate
+#[test]
+fn one_sided_inserts_stay_balanced() {
+    let mut front = DynamicBitVector::new();
+    let mut back = DynamicBitVector::new();
+    let mut middle = DynamicBitVector::new();
+    let mut model = Vec::new();
+    for i in 0..20_000 {
+        let value = i % 3 == 0;
+        front.insert(0, value);
+        back.push(value);
+        middle.insert(middle.size() / 2, value);
+        model.push(value);
+    }
+    let bound = max_height(model.len());
+    for dynamic in [&front, &back, &middle] {
+        assert!(dynamic.height() <= bound, "height {} for {} bits", dynamic.height(), model.len());
+    }
+    check(&back, &model, "pushed");
+    model.reverse();
+    check(&front, &model, "inserted at the front");
+}
+
+// Deleting most bits at random spreads the deletes over every leaf, which
+// leaves nearly empty leaves behind unless they are merged
+#[test]
+fn scattered_deletes_merge_leaves() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut model: Vec<bool> = (0..20_000).map(|_| rng.gen_bool(0.3)).collect();
+    let mut bit_vector = BitVector::new(model.len());
+    for (i, &bit) in model.iter().enumerate() {
+        bit_vector.set(i, bit);
+    }
+    let mut dynamic = DynamicBitVector::from_bit_vector(&bit_vector);
+    while model.len() > 500 {
+        let index = rng.gen_range(0..model.len());
+        assert_eq!(dynamic.delete(index), model.remove(index));
+    }
+    check(&dynamic, &model, "after deleting");
+    assert!(dynamic.memory_usage() < bit_vector.memory_usage(), "{} bytes", dynamic.memory_usage());
+}
@@ -0,0 +1,193 @@
+// The benchmark runner through the command line tool: a tiny seeded config
+// gives the rows it should, the same inputs every run, and tables that
+// compare and plot.
+mod common;
+use common::{run_err, run_ok, temp_file};
+
+// A config small enough to run in a test, with every experiment
+const CONFIG: &[&str] = &[
+    "--experiments", "rank,select,sparse", "--sizes", "100,2000", "--sparsities", "0.1,0.5",
+    "--operations", "50", "--seeds", "1,2", "--warmup", "0", "--repetitions", "5",
+];
+
+// Rank and select give one row per size, sparsity and seed, the sparse
+// experiment five
+const NUM_ROWS: usize = 2 * 2 * 2 * (1 + 1 + 5);
+
+// The header and the rows of a results table
+fn read_table(file: &str) -> (Vec<String>, Vec<Vec<String>>) {
+    let contents = std::fs::read_to_string(file).unwrap();
+    let mut lines = contents.lines();
+    let header: Vec<String> = lines.next().unwrap().split(',').map(|s| s.to_string()).collect();
+    let rows = lines.map(|line| line.split(',').map(|s| s.to_string()).collect()).collect();
+    (header, rows)
+}
+
+fn column(header: &[String], name: &str) -> usize {
+    header.iter().position(|h| h == name).unwrap_or_else(|| panic!("no {} column", name))
+}
+
+fn write_table(file: &str, header: &[String], rows: &[Vec<String>]) {
+    let mut contents = header.join(",") + "\n";
+    for row in rows {
+        contents += &(row.join(",") + "\n");
+    }
+    std::fs::write(file, contents).unwrap();
+}
+
+fn run_config(csv: &str, extra: &[&str]) {
+    let mut args = vec!["experiment", "run", "--csv", csv];
+    args.extend_from_slice(CONFIG);
+    args.extend_from_slice(extra);
+    run_ok(&args);
+}
+
+#[test]
+fn seeded_runs_give_ordered_percentiles_and_the_same_inputs() {
+    let csv = temp_file("results.csv");
+    let json = temp_file("results.json");
+    run_config(&csv, &["--json", &json]);
+
+    let (header, rows) = read_table(&csv);
+    assert_eq!(rows.len(), NUM_ROWS);
+    let stats = ["min_ns", "p5_ns", "p25_ns", "median_ns", "p75_ns", "p95_ns", "max_ns"].map(|name| column(&header, name));
+    for row in &rows {
+        assert_eq!(row.len(), header.len());
+        let values: Vec<f64> = stats.iter().map(|&c| row[c].parse().unwrap()).collect();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]), "percentiles out of order in {:?}", row);
+        assert_eq!(row[column(&header, "repetitions")], "5");
+    }
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    assert_eq!(report["results"].as_array().unwrap().len(), NUM_ROWS);
+    assert_eq!(report["config"]["seeds"], serde_json::json!([1, 2]));
+    assert_eq!(report["config"]["repetitions"], 5);
+
+    // Everything but the timings comes from the seeds, so a second run
+    // measures the same structures
+    let again = temp_file("again.csv");
+    run_config(&again, &[]);
+    let (_, rows_again) = read_table(&again);
+    let fixed = ["experiment", "structure", "operation", "size", "sparsity", "seed", "operations", "space_bits"]
+        .map(|name| column(&header, name));
+    for (row, row_again) in rows.iter().zip(&rows_again) {
+        for &c in &fixed {
+            assert_eq!(row[c], row_again[c], "{} differs between runs", header[c]);
+        }
+    }
+
+    for file in [csv, json, again] {
+        std::fs::remove_file(file).unwrap();
+    }
+}
+
+#[test]
+fn one_repetition_is_every_percentile() {
+    let csv = temp_file("one_repetition.csv");
+    run_ok(&["experiment", "run", "--experiments", "rank", "--sizes", "64", "--sparsities", "0.5",
+        "--operations", "10", "--repetitions", "1", "--csv", &csv]);
+    let (header, rows) = read_table(&csv);
+    assert_eq!(rows.len(), 1);
+    let median = &rows[0][column(&header, "median_ns")];
+    for name in ["min_ns", "p5_ns", "p25_ns", "p75_ns", "p95_ns", "max_ns"] {
+        assert_eq!(&rows[0][column(&header, name)], median, "{}", name);
+    }
+    std::fs::remove_file(csv).unwrap();
+}
+
+#[test]
+fn compare_finds_regressions_only_where_there_are_some() {
+    let csv = temp_file("compare.csv");
+    run_config(&csv, &[]);
+
+    let same = run_ok(&["experiment", "compare", &csv, &csv]);
+    assert!(same.ends_with(&format!("{} measurements compared, 0 slower than 1.1x\n", NUM_ROWS)), "{}", same);
+    assert!(!same.contains("SLOWER"));
+
+    // Three times slower everywhere, with the columns in another order, is
+    // still matched row by row
+    let (mut header, mut rows) = read_table(&csv);
+    let median = column(&header, "median_ns");
+    for row in &mut rows {
+        let slower: f64 = row[median].parse::<f64>().unwrap() * 3.0 + 1.0;
+        row[median] = slower.to_string();
+        row.reverse();
+    }
+    header.reverse();
+    let slower = temp_file("compare_slower.csv");
+    write_table(&slower, &header, &rows);
+    let compared = run_ok(&["experiment", "compare", &csv, &slower, "--threshold", "2"]);
+    assert!(compared.ends_with(&format!("{} measurements compared, {} slower than 2x\n", NUM_ROWS, NUM_ROWS)), "{}", compared);
+    assert_eq!(compared.matches("SLOWER").count(), NUM_ROWS);
+
+    // A table missing a column it needs, here the commit, is an error
+    rows.iter_mut().for_each(|row| row.truncate(row.len() - 1));
+    let truncated = temp_file("compare_truncated.csv");
+    write_table(&truncated, &header[..header.len() - 1], &rows);
+    assert!(run_err(&["experiment", "compare", &csv, &truncated]).contains("commit"));
+
+    for file in [csv, slower, truncated] {
+        std::fs::remove_file(file).unwrap();
+    }
+}
+
+#[test]
+fn bad_configs_are_errors() {
+    run_err(&["experiment", "run", "--sparsities", "1.5"]);
+    run_err(&["experiment", "run", "--experiments", "sort"]);
+    run_err(&["experiment", "run", "--repetitions", "0"]);
+    run_err(&["experiment", "run", "--sizes", "0"]);
+
+    let config = temp_file("bad.conf");
+    std::fs::write(&config, "# sizes\nsizes = 100\nrepetitions\n").unwrap();
+    assert!(run_err(&["experiment", "run", "--config", &config]).contains("line 3"));
+    std::fs::remove_file(config).unwrap();
+}
+
+// A results table small enough to write out, where one point took no time
+const TINY_CSV: &str = "\
+commit,experiment,structure,operation,size,sparsity,seed,operations,repetitions,median_ns,p5_ns,p25_ns,p75_ns,p95_ns,min_ns,max_ns,space_bits
+abc,rank,rank_support,rank1,1000,0.1,1,10,3,0.000,0.000,0.000,0.000,0.000,0.000,0.000,1300
+abc,rank,rank_support,rank1,10000,0.1,1,10,3,20.000,15.000,18.000,22.000,30.000,14.000,31.000,13000
+abc,rank,rank_support,rank1,1000,0.5,1,10,3,12.000,10.000,11.000,13.000,14.000,9.000,15.000,1300
+abc,rank,rank_support,rank1,10000,0.5,1,10,3,25.000,20.000,22.000,26.000,28.000,19.000,29.000,13000
+";
+
+// The contents of every text element of an SVG, in order
+fn svg_text(svg: &str) -> Vec<&str> {
+    svg.split("<text").skip(1).map(|text| text.split('>').nth(1).unwrap().split('<').next().unwrap().trim()).collect()
+}
+
+#[test]
+fn plots_every_chart_of_a_table() {
+    let csv = temp_file("plot.csv");
+    let out_dir = temp_file("plots");
+    std::fs::write(&csv, TINY_CSV).unwrap();
+
+    let output = run_ok(&["experiment", "plot", &csv, &out_dir]);
+    let written: Vec<&str> = output.lines().map(|line| line.strip_prefix("wrote ").unwrap()).collect();
+    // Time and space against the size for both sparsities, and time
+    // against the sparsity for both sizes
+    assert_eq!(written.len(), 6, "{}", output);
+    for file in &written {
+        assert!(file.ends_with(".svg"));
+        let svg = std::fs::read_to_string(file).unwrap();
+        assert!(svg.starts_with("<svg"), "{}", file);
+    }
+
+    // The point at 0 ns sits a decade below the smallest other one instead
+    // of taking the axis down to the smallest float
+    let chart = written.iter().find(|file| file.ends_with("rank_time_vs_size_sparsity_0.1.svg")).unwrap();
+    let svg = std::fs::read_to_string(chart).unwrap();
+    let labels = svg_text(&svg);
+    // The title and the axis descriptions come first, then the y labels
+    let y_labels: Vec<&str> = labels.iter().copied().skip_while(|&label| label != "size").skip(1).take(3).collect();
+    assert_eq!(y_labels, ["1", "10", "100"], "{:?}", labels);
+
+    run_err(&["experiment", "plot", &csv, &out_dir, "--format", "gif"]);
+    std::fs::write(&csv, TINY_CSV.replace("1300\n", "lots\n")).unwrap();
+    assert!(run_err(&["experiment", "plot", &csv, &out_dir]).contains("line 2"));
+
+    std::fs::remove_file(csv).unwrap();
+    std::fs::remove_dir_all(out_dir).unwrap();
+}
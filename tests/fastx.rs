@@ -0,0 +1,128 @@
+// The FASTA/FASTQ reader on hand written files, and the packed sequences
+// against the records the reader gives back.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::fastx::{FastxFormat, FastxReader, Record};
+use hw2_code::packed_sequences::PackedSequences;
+
+fn read_all(text: &str) -> std::io::Result<Vec<Record>> {
+    FastxReader::new(text.as_bytes()).collect()
+}
+
+fn error_message(text: &str) -> String {
+    match read_all(text) {
+        Ok(_) => panic!("expected an error for {:?}", text),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn reads_multi_line_fasta() {
+    let text = ">chr1 first one\r\nACGT\r\nNNac\r\n\n;old comment\n>empty\n>chr2\nGG\nT\n";
+    let records = read_all(text).unwrap();
+    let summary: Vec<(&str, &str, &[u8])> = records.iter().map(|r| (r.name.as_str(), r.description.as_str(), r.sequence.as_slice())).collect();
+    assert_eq!(summary, vec![("chr1", "first one", &b"ACGTNNac"[..]), ("empty", "", &b""[..]), ("chr2", "", &b"GGT"[..])]);
+    assert!(records.iter().all(|r| r.quality.is_none()));
+}
+
+#[test]
+fn reads_fastq_with_wrapped_and_tricky_quality() {
+    // The second quality line starts with @ and the third record's with +
+    let text = "@r1\nACG\nT\n+\nII\nII\n@r2 x\nGA\n+r2 x\n@@\n@r3\nC\n+\n+\n";
+    let records = read_all(text).unwrap();
+    let summary: Vec<(&str, &[u8], &[u8])> = records.iter().map(|r| (r.name.as_str(), r.sequence.as_slice(), r.quality.as_deref().unwrap())).collect();
+    assert_eq!(summary, vec![("r1", &b"ACGT"[..], &b"IIII"[..]), ("r2", &b"GA"[..], &b"@@"[..]), ("r3", &b"C"[..], &b"+"[..])]);
+
+    let mut reader = FastxReader::new(text.as_bytes());
+    let mut bases = Vec::new();
+    let header = reader.read_record_with(|line| bases.push(line.to_vec())).unwrap().unwrap();
+    assert_eq!(header.name, "r1");
+    assert_eq!(bases, vec![b"ACG".to_vec(), b"T".to_vec()]);
+    assert_eq!(reader.format(), Some(FastxFormat::Fastq));
+}
+
+#[test]
+fn errors_name_the_line() {
+    let cases = [
+        ("ACGT\n", "line 1:"),
+        (">a\nACGT\nAC GT\n", "line 3:"),
+        (">a\nAC\n@b\nAC\n+\nII\n", "line 3:"),
+        ("@a\nACGT\n+\nIII\n", "line 1:"),
+        ("@a\nACGT\n+\nIIIII\n", "line 4:"),
+        ("@a\nACGT\n", "line 1:"),
+        ("@a\nAC\n+\nI\u{7f}\n", "line 4:"),
+    ];
+    for (text, expected) in cases {
+        let message = error_message(text);
+        assert!(message.starts_with(expected), "{:?} gave {}", text, message);
+    }
+
+    // The iterator stops after an error
+    let mut reader = FastxReader::new(">a\nAC\n>b\nA1\n>c\nGG\n".as_bytes());
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_err());
+    assert!(reader.next().is_none());
+}
+
+fn random_fasta(seed: u64, num_records: usize) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let alphabet = b"ACGTACGTACGTacgtNNRY";
+    let mut text = String::new();
+    for r in 0..num_records {
+        text.push_str(&format!(">seq{} random\n", r));
+        let len = if rng.gen_bool(0.1) { 0 } else { rng.gen_range(1..500) };
+        let sequence: String = (0..len).map(|_| alphabet[rng.gen_range(0..alphabet.len())] as char).collect();
+        for line in sequence.as_bytes().chunks(rng.gen_range(10..80)) {
+            text.push_str(std::str::from_utf8(line).unwrap());
+            text.push('\n');
+        }
+    }
+    text
+}
+
+#[test]
+fn packed_sequences_match_the_records() {
+    for seed in 1..4 {
+        let text = random_fasta(seed, 40);
+        let records = read_all(&text).unwrap();
+        let packed = PackedSequences::from_fastx(text.as_bytes()).unwrap();
+        assert_eq!(packed.num_records(), records.len());
+
+        let expected_text: Vec<u8> = records.iter().flat_map(|r| r.sequence.iter().map(|&b| match b.to_ascii_uppercase() {
+            b @ (b'A' | b'C' | b'G' | b'T') => b,
+            _ => b'N',
+        })).collect();
+        assert_eq!(packed.len(), expected_text.len() as u64);
+        assert_eq!(packed.bases(0, packed.len()).collect::<Vec<_>>(), expected_text);
+        assert_eq!(packed.num_n(), expected_text.iter().filter(|&&b| b == b'N').count() as u64);
+
+        let mut start = 0;
+        for (r, record) in records.iter().enumerate() {
+            let end = start + record.sequence.len() as u64;
+            assert_eq!(packed.name(r), record.name);
+            assert_eq!(packed.record_range(r), (start, end));
+            assert_eq!(packed.record_sequence(r), expected_text[start as usize..end as usize]);
+            for pos in start..end {
+                assert_eq!(packed.record_of(pos), r, "position {}", pos);
+            }
+            let n = expected_text[start as usize..end as usize].iter().filter(|&&b| b == b'N').count() as u64;
+            assert_eq!(packed.n_in_range(start, end), n);
+            start = end;
+        }
+    }
+}
+
+#[test]
+fn packed_sequences_from_fastq() {
+    let packed = PackedSequences::from_fastx("@a\nACNT\n+\nIIII\n@b\n\n+\n\n@c\nGG\n+\nII\n".as_bytes()).unwrap();
+    assert_eq!(packed.num_records(), 3);
+    assert_eq!(packed.record_range(1), (4, 4));
+    assert_eq!(packed.record_of(4), 2);
+    assert_eq!(packed.bases(0, 6).collect::<Vec<_>>(), b"ACNTGG");
+    assert_eq!(packed.packed_bases().iter().collect::<Vec<_>>(), vec![0, 1, 0, 3, 2, 2]);
+
+    let empty = PackedSequences::from_fastx("".as_bytes()).unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.num_records(), 0);
+}
@@ -0,0 +1,150 @@
+// Genomic masks against a Vec<bool> per contig built from the same intervals
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::genomic_mask::GenomicMask;
+
+type Naive = Vec<(String, Vec<bool>)>;
+
+// Random overlapping intervals on a few contigs, as BED text and covered bases
+fn random_bed(seed: u64, num_intervals: usize) -> (String, Naive) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut naive: Naive = Vec::new();
+    let mut bed = String::from("track name=random\n# a comment\n");
+    for _ in 0..num_intervals {
+        let name = format!("chr{}", rng.gen_range(1..4));
+        let start = rng.gen_range(0..5000u64);
+        let end = start + rng.gen_range(0..300);
+        bed.push_str(&format!("{}\t{}\t{}\tfeature\t0\t+\n", name, start, end));
+
+        let i = match naive.iter().position(|(n, _)| *n == name) {
+            Some(i) => i,
+            None => {
+                naive.push((name, Vec::new()));
+                naive.len() - 1
+            }
+        };
+        let bases = &mut naive[i].1;
+        if bases.len() < end as usize {
+            bases.resize(end as usize, false);
+        }
+        bases[start as usize..end as usize].iter_mut().for_each(|base| *base = true);
+    }
+    (bed, naive)
+}
+
+fn naive_intervals(bases: &[bool]) -> Vec<(u64, u64)> {
+    let mut intervals = Vec::new();
+    let mut i = 0;
+    while i < bases.len() {
+        if bases[i] {
+            let start = i;
+            while i < bases.len() && bases[i] {
+                i += 1;
+            }
+            intervals.push((start as u64, i as u64));
+        }
+        i += 1;
+    }
+    intervals
+}
+
+fn check_against(mask: &GenomicMask, naive: &Naive) {
+    assert_eq!(mask.num_contigs(), naive.len());
+    for (name, bases) in naive {
+        let contig = mask.contig(name).unwrap();
+        assert_eq!(contig.length(), bases.len() as u64, "{}", name);
+        assert_eq!(contig.intervals().collect::<Vec<_>>(), naive_intervals(bases), "{}", name);
+
+        let covered: Vec<u64> = (0..bases.len() as u64).filter(|&i| bases[i as usize]).collect();
+        assert_eq!(contig.covered_bases(), covered.len() as u64);
+        for (k, &pos) in covered.iter().enumerate() {
+            assert_eq!(contig.kth_covered(k as u64), Some(pos));
+            assert_eq!(contig.to_compressed(pos), Some(k as u64));
+            assert_eq!(contig.to_genomic(k as u64), Some(pos));
+        }
+        assert_eq!(contig.kth_covered(covered.len() as u64), None);
+        for pos in (0..bases.len() as u64 + 10).step_by(7) {
+            assert_eq!(contig.is_covered(pos), bases.get(pos as usize) == Some(&true));
+            let expected = covered.iter().filter(|&&c| (pos..pos + 97).contains(&c)).count() as u64;
+            assert_eq!(contig.coverage(pos, pos + 97), expected, "{} [{}, {})", name, pos, pos + 97);
+        }
+    }
+}
+
+#[test]
+fn mask_matches_the_intervals() {
+    for seed in 1..4 {
+        let (bed, naive) = random_bed(seed, 200);
+        let mask = GenomicMask::read_bed(bed.as_bytes()).unwrap();
+        check_against(&mask, &naive);
+        assert_eq!(mask.covered_bases(), naive.iter().map(|(_, b)| b.iter().filter(|&&x| x).count() as u64).sum());
+        assert_eq!(mask.coverage("chrX", 0, 100), 0);
+    }
+}
+
+#[test]
+fn overlapping_and_touching_intervals_merge() {
+    let bed = "chr1\t10\t20\nchr1\t15\t30\nchr1\t30\t35\nchr1\t40\t40\nchr1\t63\t129\n";
+    let mask = GenomicMask::read_bed(bed.as_bytes()).unwrap();
+    let mut out = Vec::new();
+    mask.write_bed(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "chr1\t10\t35\nchr1\t63\t129\n");
+}
+
+#[test]
+fn set_operations_match_the_naive_ones() {
+    let (bed_a, naive_a) = random_bed(4, 150);
+    let (bed_b, naive_b) = random_bed(5, 150);
+    let a = GenomicMask::read_bed(bed_a.as_bytes()).unwrap();
+    let b = GenomicMask::read_bed(bed_b.as_bytes()).unwrap();
+
+    let combine = |f: fn(bool, bool) -> bool| -> Naive {
+        let mut names: Vec<&String> = naive_a.iter().map(|(n, _)| n).collect();
+        names.extend(naive_b.iter().map(|(n, _)| n).filter(|n| !naive_a.iter().any(|(m, _)| m == *n)));
+        names.into_iter().map(|name| {
+            let get = |naive: &Naive| naive.iter().find(|(n, _)| n == name).map(|(_, b)| b.clone()).unwrap_or_default();
+            let (x, y) = (get(&naive_a), get(&naive_b));
+            let len = x.len().max(y.len());
+            let bases = (0..len).map(|i| f(x.get(i) == Some(&true), y.get(i) == Some(&true))).collect();
+            (name.clone(), bases)
+        }).collect()
+    };
+    check_against(&a.union(&b), &combine(|x, y| x || y));
+    check_against(&a.intersection(&b), &combine(|x, y| x && y));
+    check_against(&a.subtract(&b), &combine(|x, y| x && !y));
+
+    // The BED output reads back as the same mask
+    let mut out = Vec::new();
+    a.union(&b).write_bed(&mut out).unwrap();
+    let read = GenomicMask::read_bed(out.as_slice()).unwrap();
+    let expected: Naive = combine(|x, y| x || y).into_iter().map(|(n, mut bases)| {
+        // Without sizes a contig ends where its last interval does
+        while bases.last() == Some(&false) {
+            bases.pop();
+        }
+        (n, bases)
+    }).collect();
+    check_against(&read, &expected);
+}
+
+#[test]
+fn genome_sizes_fix_the_contigs() {
+    let sizes = GenomicMask::read_genome_sizes("chr1\t100\nchr2\t50\nchrM\t16\n".as_bytes()).unwrap();
+    let mask = GenomicMask::read_bed_with_sizes("chr2\t0\t50\nchr1\t90\t100\n".as_bytes(), &sizes).unwrap();
+    let lengths: Vec<(&str, u64, u64)> = mask.contigs().map(|c| (c.name(), c.length(), c.covered_bases())).collect();
+    assert_eq!(lengths, vec![("chr1", 100, 10), ("chr2", 50, 50), ("chrM", 16, 0)]);
+
+    let error = GenomicMask::read_bed_with_sizes("chr1\t0\t10\n\nchr2\t0\t51\n".as_bytes(), &sizes).err().unwrap();
+    assert!(error.to_string().starts_with("line 3:"), "{}", error);
+    let error = GenomicMask::read_bed_with_sizes("chr3\t0\t10\n".as_bytes(), &sizes).err().unwrap();
+    assert!(error.to_string().starts_with("line 1:"), "{}", error);
+}
+
+#[test]
+fn bad_lines_are_reported() {
+    for (bed, line) in [("chr1\t0\t10\nchr1\t5\n", 2), ("chr1\t10\t5\n", 1), ("\nchr1\tx\t5\n", 2)] {
+        let error = GenomicMask::read_bed(bed.as_bytes()).err().unwrap();
+        assert!(error.to_string().starts_with(&format!("line {}:", line)), "{:?} gave {}", bed, error);
+    }
+}
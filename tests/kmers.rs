@@ -0,0 +1,163 @@
+// Elias-Fano sets against sorted Vecs, and k-mer sets and maps against
+// a HashMap counting the canonical k-mers as strings.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+use hw2_code::elias_fano::EliasFano;
+use hw2_code::kmers::{canonical, decode_kmer, encode_kmer, reverse_complement, KmerMap, KmerSet};
+
+fn check_elias_fano(values: &[u64]) {
+    let set = EliasFano::from_sorted(values);
+    assert_eq!(set.len(), values.len() as u64);
+    assert_eq!(set.iter().collect::<Vec<_>>(), values);
+    for (i, &value) in values.iter().enumerate() {
+        assert_eq!(set.get(i as u64), value);
+        assert_eq!(set.index_of(value), Some(i as u64));
+        assert_eq!(set.rank(value), i as u64);
+    }
+    let mut rng = StdRng::seed_from_u64(values.len() as u64);
+    let probes = (0..200).map(|_| rng.gen::<u64>()).chain(values.iter().map(|v| v.wrapping_add(1))).chain([0, u64::MAX]);
+    for probe in probes {
+        let rank = values.partition_point(|&v| v < probe) as u64;
+        assert_eq!(set.rank(probe), rank, "rank({})", probe);
+        assert_eq!(set.contains(probe), values.binary_search(&probe).is_ok(), "contains({})", probe);
+    }
+}
+
+#[test]
+fn elias_fano_matches_a_sorted_vec() {
+    check_elias_fano(&[]);
+    check_elias_fano(&[0]);
+    check_elias_fano(&[u64::MAX]);
+    check_elias_fano(&[0, 1, 2, 3, 4, 5]);
+    check_elias_fano(&[7, u64::MAX - 1, u64::MAX]);
+
+    let mut rng = StdRng::seed_from_u64(1);
+    for (count, bits) in [(1000, 64), (1000, 20), (5000, 14), (300, 40)] {
+        let mut values: Vec<u64> = (0..count).map(|_| rng.gen::<u64>() >> (64 - bits)).collect();
+        values.sort_unstable();
+        values.dedup();
+        check_elias_fano(&values);
+    }
+}
+
+#[test]
+fn elias_fano_takes_a_few_bits_a_value() {
+    use hw2_code::space_report::SpaceUsage;
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut values: Vec<u64> = (0..100_000).map(|_| rng.gen()).collect();
+    values.sort_unstable();
+    values.dedup();
+    // About 2 + log2(2^64 / 100000) = 49 bits a value plus the rank tables,
+    // against 64 for a plain Vec
+    let bits = EliasFano::from_sorted(&values).space_report().bits;
+    assert!(bits < values.len() as u64 * 56, "{} bits a value", bits / values.len() as u64);
+}
+
+#[test]
+fn codes_and_reverse_complements() {
+    assert_eq!(encode_kmer(b"ACGT"), Some(0b00_01_10_11));
+    assert_eq!(encode_kmer(b"acgt"), encode_kmer(b"ACGT"));
+    assert_eq!(encode_kmer(b"ACNT"), None);
+    assert_eq!(decode_kmer(0b00_01_10_11, 4), "ACGT");
+
+    let mut rng = StdRng::seed_from_u64(3);
+    for k in [1, 2, 7, 31, 32] {
+        for _ in 0..100 {
+            let kmer: Vec<u8> = (0..k).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect();
+            let reversed: Vec<u8> = kmer.iter().rev().map(|&b| match b {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            }).collect();
+            let code = encode_kmer(&kmer).unwrap();
+            assert_eq!(decode_kmer(code, k), String::from_utf8(kmer.clone()).unwrap());
+            assert_eq!(reverse_complement(code, k), encode_kmer(&reversed).unwrap());
+            assert_eq!(canonical(code, k), canonical(encode_kmer(&reversed).unwrap(), k));
+        }
+    }
+}
+
+fn random_sequences(seed: u64, count: usize) -> Vec<Vec<u8>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let alphabet = b"ACGTACGTACGTacgtN";
+    (0..count).map(|_| {
+        let len = rng.gen_range(0..400);
+        (0..len).map(|_| alphabet[rng.gen_range(0..alphabet.len())]).collect()
+    }).collect()
+}
+
+// Canonical k-mers of the sequences as upper case strings with their counts
+fn naive_counts(sequences: &[Vec<u8>], k: usize) -> HashMap<String, u64> {
+    let mut counts = HashMap::new();
+    for sequence in sequences {
+        for window in sequence.windows(k) {
+            if let Some(code) = encode_kmer(window) {
+                *counts.entry(decode_kmer(canonical(code, k), k)).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+#[test]
+fn kmer_map_matches_naive_counts() {
+    let sequences = random_sequences(4, 50);
+    for k in [1, 3, 8, 21, 32] {
+        let naive = naive_counts(&sequences, k);
+        let map = KmerMap::from_sequences(k, sequences.iter().map(|s| s.as_slice()));
+        assert_eq!(map.len(), naive.len() as u64, "k = {}", k);
+
+        let mut expected: Vec<(u64, u64)> = naive.iter().map(|(kmer, &count)| (encode_kmer(kmer.as_bytes()).unwrap(), count)).collect();
+        expected.sort_unstable();
+        assert_eq!(map.iter().collect::<Vec<_>>(), expected);
+
+        for (id, &(code, count)) in expected.iter().enumerate() {
+            let kmer = decode_kmer(code, k);
+            let other_strand = decode_kmer(reverse_complement(code, k), k);
+            assert_eq!(map.set().id(kmer.as_bytes()), Some(id as u64));
+            assert_eq!(map.set().id(other_strand.as_bytes()), Some(id as u64));
+            assert_eq!(map.count(other_strand.to_lowercase().as_bytes()), count);
+            assert_eq!(map.set().code(id as u64), code);
+            assert_eq!(map.set().rank_of_code(code), id as u64);
+        }
+    }
+}
+
+#[test]
+fn absent_kmers_have_no_id() {
+    let set = KmerSet::from_sequences(4, [&b"AAAACCCC"[..]]);
+    assert!(set.contains(b"AAAA"));
+    assert!(set.contains(b"TTTT"));
+    assert!(set.contains(b"GGGT"));
+    assert!(!set.contains(b"ACGT"));
+    assert!(!set.contains(b"AANA"));
+    assert_eq!(set.len(), 5);
+
+    let from_codes = KmerSet::from_codes(4, set.iter().map(|code| reverse_complement(code, 4)));
+    assert_eq!(from_codes.iter().collect::<Vec<_>>(), set.iter().collect::<Vec<_>>());
+    assert!(KmerSet::from_sequences(5, [&b"ACGT"[..]]).is_empty());
+}
+
+#[test]
+fn fasta_lines_are_joined_but_records_are_not() {
+    let sequences = random_sequences(5, 20);
+    let mut fasta = String::new();
+    for (r, sequence) in sequences.iter().enumerate() {
+        fasta.push_str(&format!(">r{}\n", r));
+        for line in sequence.chunks(13) {
+            fasta.push_str(std::str::from_utf8(line).unwrap());
+            fasta.push('\n');
+        }
+    }
+    for k in [5, 31] {
+        let from_fasta = KmerMap::from_fastx(fasta.as_bytes(), k).unwrap();
+        let from_sequences = KmerMap::from_sequences(k, sequences.iter().map(|s| s.as_slice()));
+        assert_eq!(from_fasta.iter().collect::<Vec<_>>(), from_sequences.iter().collect::<Vec<_>>());
+        let set = KmerSet::from_fastx(fasta.as_bytes(), k).unwrap();
+        assert_eq!(set.iter().collect::<Vec<_>>(), from_sequences.set().iter().collect::<Vec<_>>());
+    }
+    assert!(KmerSet::from_fastx(">a\nAC1\n".as_bytes(), 3).is_err());
+}
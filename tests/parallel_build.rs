@@ -0,0 +1,80 @@
+// Building the rank tables with several threads has to give exactly the
+// bytes the single threaded build gives, for every block size and for
+// more threads than there are superchunks
+use hw2_code::position_index::IndexKind;
+use hw2_code::rank_support::{BlockSizes, RankSupport};
+use hw2_code::sparse_array::SparseArray;
+
+mod common;
+use common::random_bit_vector;
+
+const THREADS: [usize; 6] = [0, 2, 3, 4, 8, 1000];
+
+fn saved(rank_support: &RankSupport) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    rank_support.write_to(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn rank_tables_do_not_depend_on_the_threads() {
+    let block_sizes = [
+        BlockSizes::Theoretical,
+        BlockSizes::Speed,
+        BlockSizes::Custom { chunk_size: 1, superchunk_size: 4 },
+        BlockSizes::Custom { chunk_size: 3, superchunk_size: 21 },
+    ];
+    for (seed, size) in [0, 1, 7, 100, 4096, 100_003, (1 << 20) + 17].into_iter().enumerate() {
+        for density in [0.01, 0.5, 1.0] {
+            let bit_vector = random_bit_vector(seed as u64, size, density);
+            for blocks in block_sizes {
+                let sequential = RankSupport::with_block_sizes(&bit_vector, blocks).unwrap();
+                let expected = saved(&sequential);
+                for num_threads in THREADS {
+                    let parallel = RankSupport::build_with_threads(&bit_vector, blocks, num_threads).unwrap();
+                    assert!(saved(&parallel) == expected, "size {} density {} {:?} {} threads", size, density, blocks, num_threads);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn parallel_tables_answer_rank() {
+    let bit_vector = random_bit_vector(7, 300_000, 0.3);
+    let rank_support = RankSupport::build_with_threads(&bit_vector, BlockSizes::Theoretical, 4).unwrap();
+    rank_support.verify().unwrap();
+    let mut rank = 0;
+    for i in 0..bit_vector.size() {
+        assert_eq!(rank_support.rank1(i), rank);
+        rank += bit_vector.get(i) as u64;
+    }
+    assert_eq!(rank_support.rank1(bit_vector.size()), rank);
+}
+
+#[test]
+fn sparse_arrays_finalize_the_same_with_threads() {
+    let dir = std::env::temp_dir();
+    for kind in [IndexKind::Plain, IndexKind::SortedList] {
+        let build = |num_threads: Option<usize>| {
+            let mut sparse_array: SparseArray = SparseArray::create_with_index(250_000, kind);
+            for i in (0..250_000).step_by(37) {
+                sparse_array.append(format!("v{}", i), i);
+            }
+            match num_threads {
+                Some(num_threads) => sparse_array.finalize_with_threads(num_threads),
+                None => sparse_array.finalize(),
+            }
+            let file = dir.join(format!("hw2_code_parallel_{}_{:?}_{:?}", std::process::id(), kind, num_threads));
+            let file = file.to_str().unwrap();
+            sparse_array.save(file).unwrap();
+            let bytes = std::fs::read(file).unwrap();
+            std::fs::remove_file(file).unwrap();
+            bytes
+        };
+        let expected = build(None);
+        for num_threads in THREADS {
+            assert!(build(Some(num_threads)) == expected, "{:?} {} threads", kind, num_threads);
+        }
+    }
+}
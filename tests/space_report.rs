@@ -0,0 +1,140 @@
+// Space reports of built structures add up, and the text, JSON and HTML
+// renderings carry every part. The older size methods agree with them.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::dynamic_bit_vector::DynamicBitVector;
+use hw2_code::position_index::IndexKind;
+use hw2_code::rank_select::RankSelect;
+use hw2_code::rank_support::RankSupport;
+use hw2_code::select_support::SelectSupport;
+use hw2_code::space_report::{SpaceNode, SpaceUsage};
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::sparse_dict_array::SparseDictArray;
+use hw2_code::sparse_str_array::SparseStrArray;
+
+mod common;
+use common::random_bit_vector;
+
+fn random_sparse_array(rng: &mut StdRng, size: u64, kind: IndexKind) -> SparseArray {
+    let mut sparse_array = SparseArray::create_with_index(size, kind);
+    for i in 0..size {
+        if rng.gen_bool(0.1) {
+            sparse_array.append(format!("value {}", i % 7), i);
+        }
+    }
+    sparse_array.finalize();
+    sparse_array
+}
+
+// Every part with children takes exactly the sum of its children
+fn check_sums(node: &SpaceNode) {
+    if !node.children.is_empty() {
+        let sum: u64 = node.children.iter().map(|child| child.bits).sum();
+        assert_eq!(node.bits, sum, "{} is not the sum of its children", node.name);
+    }
+    node.children.iter().for_each(check_sums);
+}
+
+// The names of the part and every part below it
+fn names(node: &SpaceNode) -> Vec<&str> {
+    let mut names = vec![node.name.as_str()];
+    for child in &node.children {
+        names.extend(self::names(child));
+    }
+    names
+}
+
+// The parsed JSON has the same tree as the report
+fn check_json(node: &SpaceNode, json: &serde_json::Value) {
+    assert_eq!(json["name"].as_str(), Some(node.name.as_str()));
+    assert_eq!(json["bits"].as_u64(), Some(node.bits));
+    let children = json["children"].as_array().expect("children is an array");
+    assert_eq!(children.len(), node.children.len(), "children of {}", node.name);
+    for (child, child_json) in node.children.iter().zip(children) {
+        check_json(child, child_json);
+    }
+}
+
+fn check_report(report: &SpaceNode) {
+    check_sums(report);
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).expect("the JSON parses");
+    check_json(report, &json);
+
+    let html = report.to_html();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    for name in names(report) {
+        assert!(html.contains(name), "the HTML has no {}", name);
+    }
+
+    let text = report.to_text();
+    assert_eq!(text.lines().count(), names(report).len());
+}
+
+#[test]
+fn reports_add_up_and_render() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for size in [0, 1, 100, 5000] {
+        let bit_vector = random_bit_vector(size as u64, size, 0.3);
+        check_report(&bit_vector.space_report());
+        let rank_support = RankSupport::new(&bit_vector);
+        check_report(&rank_support.space_report());
+        check_report(&SelectSupport::new(&rank_support).space_report());
+        check_report(&RankSelect::new(bit_vector.clone()).space_report());
+        check_report(&DynamicBitVector::from_bit_vector(&bit_vector).space_report());
+
+        for kind in [IndexKind::Plain, IndexKind::SortedList] {
+            let mut sparse_array = random_sparse_array(&mut rng, size as u64, kind);
+            check_report(&sparse_array.space_report());
+            check_report(&SparseStrArray::from_sparse_array(&sparse_array).space_report());
+            check_report(&SparseDictArray::from_sparse_array(&sparse_array).space_report());
+
+            // Pending edits are a part of their own
+            if let Some(empty) = (0..size as u64).find(|&i| !sparse_array.contains(i)) {
+                assert_eq!(sparse_array.set(empty, "edited".to_string()), Ok(None));
+                let report = sparse_array.space_report();
+                check_report(&report);
+                assert!(report.child("pending").unwrap().bits > 0);
+            }
+        }
+    }
+}
+
+#[test]
+fn names_are_escaped() {
+    let report = SpaceNode::with_children("a \"quoted\" <name> & more", vec![
+        SpaceNode::leaf("line\nbreak", 8),
+        SpaceNode::leaf("back\\slash", 16),
+    ]);
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    check_json(&report, &json);
+    let html = report.to_html();
+    assert!(html.contains("a &quot;quoted&quot; &lt;name&gt; &amp; more"));
+    assert!(!html.contains("<name>"));
+}
+
+#[test]
+fn size_methods_come_from_the_reports() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let bit_vector = random_bit_vector(2, 10_000, 0.5);
+    assert_eq!(bit_vector.memory_usage() as u64, bit_vector.space_report().bytes());
+
+    // overhead is the bits of the rank tables, without the bit vector
+    let rank_support = RankSupport::new(&bit_vector);
+    let report = rank_support.space_report();
+    let tables = report.child("superchunk_data").unwrap().bits + report.child("chunk_data").unwrap().bits;
+    assert_eq!(rank_support.overhead() as u64, tables);
+    assert_eq!(SelectSupport::new(&rank_support).overhead(), tables);
+    assert_eq!(RankSelect::new(bit_vector.clone()).overhead() as u64, tables);
+
+    let dynamic = DynamicBitVector::from_bit_vector(&bit_vector);
+    assert_eq!(dynamic.memory_usage() as u64, dynamic.space_report().bytes());
+
+    let sparse_array = random_sparse_array(&mut rng, 10_000, IndexKind::Plain);
+    assert_eq!(sparse_array.get_overhead() as u64, sparse_array.space_report().bytes());
+    let str_array = SparseStrArray::from_sparse_array(&sparse_array);
+    assert_eq!(str_array.get_overhead() as u64, str_array.space_report().bytes());
+    let dict_array = SparseDictArray::from_sparse_array(&sparse_array);
+    assert_eq!(dict_array.get_overhead() as u64, dict_array.space_report().bytes());
+}
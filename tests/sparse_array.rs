@@ -0,0 +1,299 @@
+// Range and neighbour queries, dense conversions and edits after finalize
+// on SparseArray, checked against the sorted positions or the dense array
+// it stands for.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::position_index::{IndexKind, PlainIndex, SortedListIndex};
+use hw2_code::sparse_array::{SparseArray, SparseArrayIter};
+
+mod common;
+use common::temp_file;
+
+// A finalized sparse array with a value at every one of positions
+fn sparse_array_at(size: u64, positions: &[u64], kind: IndexKind) -> SparseArray {
+    let mut sparse_array = SparseArray::create_with_index(size, kind);
+    for &pos in positions {
+        sparse_array.append(format!("v{}", pos), pos);
+    }
+    sparse_array.finalize();
+    sparse_array
+}
+
+fn indices(iter: SparseArrayIter<'_>) -> Vec<u64> {
+    iter.map(|(i, _)| i).collect()
+}
+
+fn random_positions(rng: &mut StdRng, size: u64, density: f64) -> Vec<u64> {
+    (0..size).filter(|_| rng.gen_bool(density)).collect()
+}
+
+#[test]
+fn iteration_goes_both_ways() {
+    for kind in [IndexKind::Plain, IndexKind::SortedList] {
+        let sparse_array = sparse_array_at(20, &[0, 3, 4, 19], kind);
+        let forward: Vec<(u64, String)> = sparse_array.iter().map(|(i, v)| (i, v.clone())).collect();
+        assert_eq!(forward, vec![(0, "v0".to_string()), (3, "v3".to_string()), (4, "v4".to_string()), (19, "v19".to_string())]);
+        let backward: Vec<u64> = sparse_array.iter().rev().map(|(i, _)| i).collect();
+        assert_eq!(backward, vec![19, 4, 3, 0]);
+        assert_eq!(sparse_array.iter().len(), 4);
+        assert_eq!((&sparse_array).into_iter().count(), 4);
+
+        // Taking from both ends meets in the middle
+        let mut iter = sparse_array.iter();
+        assert_eq!(iter.next().map(|(i, _)| i), Some(0));
+        assert_eq!(iter.next_back().map(|(i, _)| i), Some(19));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next().map(|(i, _)| i), Some(3));
+        assert_eq!(iter.next_back().map(|(i, _)| i), Some(4));
+        assert!(iter.next().is_none() && iter.next_back().is_none());
+
+        assert_eq!(sparse_array.first().map(|(i, v)| (i, v.as_str())), Some((0, "v0")));
+        assert_eq!(sparse_array.last().map(|(i, v)| (i, v.as_str())), Some((19, "v19")));
+    }
+}
+
+#[test]
+fn empty_arrays_have_nothing_to_find() {
+    for size in [0, 10] {
+        let sparse_array = sparse_array_at(size, &[], IndexKind::Plain);
+        assert!(sparse_array.iter().next().is_none());
+        assert!(sparse_array.first().is_none() && sparse_array.last().is_none());
+        assert_eq!(sparse_array.range(..).count(), 0);
+        assert_eq!(sparse_array.count_in_range(..), 0);
+        assert_eq!(sparse_array.next_present(0), None);
+        assert_eq!(sparse_array.prev_present(u64::MAX), None);
+    }
+}
+
+#[test]
+fn ranges_match_a_filter_over_the_positions() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for (size, density) in [(1, 1.0), (64, 0.5), (200, 0.05), (1000, 0.9)] {
+        let positions = random_positions(&mut rng, size, density);
+        for kind in [IndexKind::Plain, IndexKind::SortedList] {
+            let sparse_array = sparse_array_at(size, &positions, kind);
+            let in_range = |start: u64, end: u64| -> Vec<u64> {
+                positions.iter().copied().filter(|&p| start <= p && p < end).collect()
+            };
+            for _ in 0..100 {
+                let a = rng.gen_range(0..size + 5);
+                let b = rng.gen_range(0..size + 5);
+                assert_eq!(indices(sparse_array.range(a..b)), in_range(a, b), "{}..{}", a, b);
+                assert_eq!(indices(sparse_array.range(a..=b)), in_range(a, b.saturating_add(1)), "{}..={}", a, b);
+                assert_eq!(indices(sparse_array.range(a..)), in_range(a, u64::MAX), "{}..", a);
+                assert_eq!(indices(sparse_array.range(..b)), in_range(0, b), "..{}", b);
+                assert_eq!(sparse_array.count_in_range(a..b), in_range(a, b).len() as u64);
+                assert_eq!(sparse_array.num_elem_at(a), in_range(0, a + 1).len() as u64, "num_elem_at({})", a);
+                let backward: Vec<u64> = sparse_array.range(a..b).rev().map(|(i, _)| i).collect();
+                assert!(backward.iter().rev().eq(in_range(a, b).iter()));
+            }
+            assert_eq!(sparse_array.range(..).count(), positions.len());
+            assert_eq!(sparse_array.range(..=u64::MAX).count(), positions.len());
+            assert_eq!(sparse_array.num_elem_at(u64::MAX), positions.len() as u64);
+        }
+    }
+}
+
+#[test]
+fn neighbours_match_a_scan() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for (size, density) in [(1, 0.0), (1, 1.0), (130, 0.03), (500, 0.5)] {
+        let positions = random_positions(&mut rng, size, density);
+        for kind in [IndexKind::Plain, IndexKind::SortedList] {
+            let sparse_array = sparse_array_at(size, &positions, kind);
+            for i in 0..size + 3 {
+                let next = positions.iter().copied().find(|&p| p >= i);
+                let prev = positions.iter().copied().rev().find(|&p| p <= i);
+                assert_eq!(sparse_array.next_present(i), next, "next_present({})", i);
+                assert_eq!(sparse_array.prev_present(i), prev, "prev_present({})", i);
+            }
+        }
+    }
+}
+
+#[test]
+fn dense_round_trip() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for (size, density) in [(0, 0.5), (1, 1.0), (100, 0.01), (100, 0.9), (5000, 0.001), (5000, 0.6)] {
+        let dense: Vec<Option<String>> = (0..size).map(|i| rng.gen_bool(density).then(|| format!("v{}", i))).collect();
+        let sparse_array = SparseArray::from_dense(dense.clone());
+        assert_eq!(sparse_array.size(), size);
+        assert_eq!(sparse_array.num_elem(), dense.iter().flatten().count() as u64);
+        assert_eq!(sparse_array.index_kind(), IndexKind::for_density(size, sparse_array.num_elem()));
+        assert_eq!(sparse_array.to_dense(), dense);
+
+        // Empty strings stand for the empty positions
+        let by = SparseArray::from_dense_by(dense.iter().map(|v| v.clone().unwrap_or_default()), |v| v.is_empty());
+        assert_eq!(by.to_dense(), dense);
+    }
+}
+
+#[test]
+fn collecting_pairs_keeps_the_last_value() {
+    let sparse_array: SparseArray = [(7, "a"), (2, "b"), (7, "c")].into_iter().map(|(i, v)| (i, v.to_string())).collect();
+    assert_eq!(sparse_array.size(), 8);
+    assert_eq!(sparse_array.to_dense().iter().filter(|v| v.is_some()).count(), 2);
+    assert_eq!(sparse_array.get(7).map(|v| v.as_str()), Some("c"));
+    assert_eq!(sparse_array.get(2).map(|v| v.as_str()), Some("b"));
+    assert_eq!(SparseArray::<String>::from_iter(Vec::new()).size(), 0);
+}
+
+#[test]
+fn density_picks_the_smaller_index() {
+    assert_eq!(IndexKind::for_density(1 << 20, 10), IndexKind::SortedList);
+    assert_eq!(IndexKind::for_density(1 << 20, 1 << 19), IndexKind::Plain);
+    assert_eq!(IndexKind::for_density(0, 0), IndexKind::SortedList);
+
+    // The switch happens where the sorted list stops being smaller
+    for size in [64, 1000, 1 << 16] {
+        let plain_bits = PlainIndex::bits_for(size);
+        let most_for_sorted_list = plain_bits / 64;
+        assert!(SortedListIndex::bits_for(most_for_sorted_list) <= plain_bits);
+        assert_eq!(IndexKind::for_density(size, most_for_sorted_list), IndexKind::SortedList);
+        assert_eq!(IndexKind::for_density(size, most_for_sorted_list + 1), IndexKind::Plain);
+    }
+}
+
+// The dense array and the sparse array agree on every position and rank
+fn check_against_dense(sparse_array: &SparseArray, dense: &[Option<String>]) {
+    assert_eq!(sparse_array.to_dense(), dense);
+    let mut rank = 0;
+    for (i, value) in dense.iter().enumerate() {
+        assert_eq!(sparse_array.get_rank1(i), rank, "rank1({})", i);
+        if value.is_some() {
+            assert_eq!(sparse_array.get_index_of(rank), i as u64);
+            rank += 1;
+        }
+    }
+    assert_eq!(sparse_array.num_elem(), rank);
+}
+
+#[test]
+fn out_of_range_edits_change_nothing() {
+    let mut sparse_array = sparse_array_at(10, &[3], IndexKind::Plain);
+    assert_eq!(sparse_array.set(10, "x".to_string()), Err("x".to_string()));
+    assert_eq!(sparse_array.set(u64::MAX, "y".to_string()), Err("y".to_string()));
+    assert!(!sparse_array.remove(10));
+    assert!(sparse_array.get_mut(10).is_none());
+    assert_eq!(sparse_array.num_pending(), 0);
+    assert_eq!(sparse_array.num_elem(), 1);
+}
+
+#[test]
+fn edits_are_merged_at_the_threshold() {
+    // 10000 positions give a plain index a threshold of sqrt(10000) = 100
+    // pending edits
+    let size = 10_000;
+    let positions: Vec<u64> = (0..size).step_by(10).collect();
+    let mut sparse_array = sparse_array_at(size, &positions, IndexKind::Plain);
+    let mut dense: Vec<Option<String>> = (0..size).map(|i| (i % 10 == 0).then(|| format!("v{}", i))).collect();
+
+    // Replacing a present element is done in place and never pending
+    assert_eq!(sparse_array.set(20, "twenty".to_string()), Ok(Some("v20".to_string())));
+    *sparse_array.get_mut(30).unwrap() = "thirty".to_string();
+    dense[20] = Some("twenty".to_string());
+    dense[30] = Some("thirty".to_string());
+    assert_eq!(sparse_array.num_pending(), 0);
+
+    // New positions wait in the pending edits until there are 100
+    for k in 0..99 {
+        let i = 10 * k + 1;
+        assert_eq!(sparse_array.set(i, format!("new{}", i)), Ok(None));
+        dense[i as usize] = Some(format!("new{}", i));
+    }
+    assert_eq!(sparse_array.num_pending(), 99);
+    check_against_dense(&sparse_array, &dense);
+
+    // Setting a pending position again replaces it without a new edit,
+    // and removing one just drops it
+    assert_eq!(sparse_array.set(1, "one".to_string()), Ok(Some("new1".to_string())));
+    assert!(sparse_array.remove(11));
+    dense[1] = Some("one".to_string());
+    dense[11] = None;
+    assert_eq!(sparse_array.num_pending(), 98);
+    // A removal is an edit too, and bringing the element back undoes it
+    assert!(sparse_array.remove(40));
+    assert!(!sparse_array.remove(40));
+    assert_eq!(sparse_array.num_pending(), 99);
+    assert_eq!(sparse_array.set(40, "forty".to_string()), Ok(None));
+    dense[40] = Some("forty".to_string());
+    assert_eq!(sparse_array.num_pending(), 98);
+    check_against_dense(&sparse_array, &dense);
+
+    assert_eq!(sparse_array.set(2, "two".to_string()), Ok(None));
+    assert!(sparse_array.remove(50));
+    dense[2] = Some("two".to_string());
+    dense[50] = None;
+    assert_eq!(sparse_array.num_pending(), 0);
+    check_against_dense(&sparse_array, &dense);
+
+    // After the merge the same edits start over from an empty buffer
+    assert!(sparse_array.remove(1));
+    assert!(sparse_array.remove(60));
+    assert_eq!(sparse_array.set(3, "three".to_string()), Ok(None));
+    assert_eq!(sparse_array.set(2, "deux".to_string()), Ok(Some("two".to_string())));
+    dense[1] = None;
+    dense[60] = None;
+    dense[3] = Some("three".to_string());
+    dense[2] = Some("deux".to_string());
+    assert_eq!(sparse_array.num_pending(), 3);
+    check_against_dense(&sparse_array, &dense);
+    sparse_array.merge_pending();
+    assert_eq!(sparse_array.num_pending(), 0);
+    check_against_dense(&sparse_array, &dense);
+}
+
+#[test]
+fn small_arrays_wait_for_the_minimum_number_of_edits() {
+    // sqrt(100) is below the minimum of 64 edits, and a sorted list index
+    // pays for its merge with the number of elements rather than the size
+    for kind in [IndexKind::Plain, IndexKind::SortedList] {
+        let mut sparse_array = sparse_array_at(100, &[], kind);
+        for i in 0..63 {
+            assert_eq!(sparse_array.set(i, format!("v{}", i)), Ok(None));
+        }
+        assert_eq!(sparse_array.num_pending(), 63);
+        assert_eq!(sparse_array.set(63, "v63".to_string()), Ok(None));
+        assert_eq!(sparse_array.num_pending(), 0);
+        assert_eq!(sparse_array.num_elem(), 64);
+        assert_eq!(sparse_array.index_kind(), kind);
+    }
+}
+
+#[test]
+fn corrupt_element_lengths_are_invalid_data() {
+    let sparse_array = sparse_array_at(100, &[1, 50, 99], IndexKind::Plain);
+    let file = temp_file("corrupt_length");
+    sparse_array.save(&file).unwrap();
+    let loaded = SparseArray::load(&file).unwrap();
+    assert_eq!(loaded.to_dense(), sparse_array.to_dense());
+
+    // The size and two words of the bit vector, then the number of
+    // elements and the length of the first one
+    let first_len = 8 + 2 * 8 + 8;
+    let saved = std::fs::read(&file).unwrap();
+    for len in [u64::MAX, 1 << 40, saved.len() as u64] {
+        let mut bytes = saved.clone();
+        bytes[first_len..first_len + 8].copy_from_slice(&len.to_le_bytes());
+        std::fs::write(&file, &bytes).unwrap();
+        let error = SparseArray::load(&file).err().expect("a corrupt length doesn't load");
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", error);
+    }
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn batch_lookups_need_no_clone() {
+    use hw2_code::batch::QueryOrder;
+
+    // Not Clone, so only the reference returning queries are available
+    #[derive(Debug, PartialEq)]
+    struct Label(u64);
+
+    let sparse_array: SparseArray<Label> = [(3, Label(30)), (9, Label(90))].into_iter().collect();
+    let indices = [9, 0, 3, 9];
+    let mut out = [None; 4];
+    sparse_array.get_at_index_batch(&indices, &mut out, QueryOrder::Sorted);
+    assert_eq!(out, [Some(&Label(90)), None, Some(&Label(30)), Some(&Label(90))]);
+}
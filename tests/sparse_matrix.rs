@@ -0,0 +1,147 @@
+// The CSR matrix against a dense model, its binary and Matrix Market
+// round trips, and the errors Matrix Market import reports.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::sparse_matrix::SparseMatrix;
+
+mod common;
+use common::temp_file;
+
+// Random triplets, some landing on the same place, and the dense matrix
+// they add up to
+fn random_matrix(seed: u64, num_rows: u64, num_cols: u64, num_triplets: usize) -> (SparseMatrix<i64>, Vec<Vec<i64>>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut dense = vec![vec![0i64; num_cols as usize]; num_rows as usize];
+    let mut triplets = Vec::new();
+    if num_rows > 0 && num_cols > 0 {
+        for _ in 0..num_triplets {
+            let (r, c, value) = (rng.gen_range(0..num_rows), rng.gen_range(0..num_cols), rng.gen_range(-3..=3));
+            triplets.push((r, c, value));
+            dense[r as usize][c as usize] += value;
+        }
+    }
+    (SparseMatrix::from_triplets(num_rows, num_cols, triplets), dense)
+}
+
+fn shapes() -> Vec<(u64, u64, usize)> {
+    vec![(0, 0, 0), (0, 5, 0), (5, 0, 0), (1, 1, 3), (3, 70, 40), (100, 7, 300), (64, 64, 1500), (200, 300, 2000)]
+}
+
+#[test]
+fn matches_the_dense_matrix() {
+    for (seed, (num_rows, num_cols, num_triplets)) in shapes().into_iter().enumerate() {
+        let (matrix, dense) = random_matrix(seed as u64, num_rows, num_cols, num_triplets);
+        matrix.verify().unwrap();
+        assert_eq!(matrix.to_dense(), dense);
+        let nonzero = dense.iter().flatten().filter(|&&v| v != 0).count() as u64;
+        assert_eq!(matrix.num_nonzero(), nonzero);
+        for r in 0..num_rows {
+            let expected: Vec<(u64, i64)> = dense[r as usize].iter().enumerate()
+                .filter(|(_, &v)| v != 0).map(|(c, &v)| (c as u64, v)).collect();
+            assert_eq!(matrix.row(r).collect::<Vec<_>>(), expected, "row {}", r);
+            assert_eq!(matrix.row_nonzero(r), expected.len() as u64);
+            for c in 0..num_cols + 1 {
+                let value = dense[r as usize].get(c as usize).copied().unwrap_or(0);
+                assert_eq!(matrix.get(r, c), value, "get({}, {})", r, c);
+            }
+        }
+        assert_eq!(matrix.get(num_rows, 0), 0);
+    }
+}
+
+#[test]
+fn matvec_and_transpose() {
+    let mut rng = StdRng::seed_from_u64(10);
+    for (seed, (num_rows, num_cols, num_triplets)) in shapes().into_iter().enumerate() {
+        let (matrix, dense) = random_matrix(seed as u64, num_rows, num_cols, num_triplets);
+        let x: Vec<i64> = (0..num_cols).map(|_| rng.gen_range(-5..=5)).collect();
+        let expected: Vec<i64> = dense.iter().map(|row| row.iter().zip(&x).map(|(a, b)| a * b).sum()).collect();
+        assert_eq!(matrix.matvec(&x), expected);
+
+        let transposed = matrix.transpose();
+        transposed.verify().unwrap();
+        assert_eq!((transposed.num_rows(), transposed.num_cols()), (num_cols, num_rows));
+        for (r, c, value) in matrix.iter() {
+            assert_eq!(transposed.get(c, r), value);
+        }
+        assert_eq!(transposed.num_nonzero(), matrix.num_nonzero());
+        assert_eq!(transposed.transpose().to_dense(), dense);
+    }
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    let (matrix, dense) = random_matrix(20, 150, 90, 1000);
+    let file = temp_file("binary");
+    matrix.save(&file).unwrap();
+    let loaded = SparseMatrix::<i64>::load(&file).unwrap();
+    assert_eq!(loaded.to_dense(), dense);
+
+    // The values were saved as i64, so they don't load as anything else
+    assert!(SparseMatrix::<f64>::load(&file).is_err());
+    assert!(SparseMatrix::<i32>::load(&file).is_err());
+
+    let mut bytes = std::fs::read(&file).unwrap();
+    bytes.push(0);
+    std::fs::write(&file, &bytes).unwrap();
+    assert!(SparseMatrix::<i64>::load(&file).is_err());
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn matrix_market_round_trip() {
+    let (matrix, dense) = random_matrix(21, 40, 60, 300);
+    let file = temp_file("matrix_market");
+    matrix.save_matrix_market(&file).unwrap();
+    let text = std::fs::read_to_string(&file).unwrap();
+    assert!(text.starts_with("%%MatrixMarket matrix coordinate integer general\n40 60 "));
+    assert_eq!(SparseMatrix::<i64>::load_matrix_market(&file).unwrap().to_dense(), dense);
+    std::fs::remove_file(&file).unwrap();
+
+    let floats = SparseMatrix::from_triplets(2, 3, [(0, 2, 0.1f64), (1, 0, -2.5e-7), (1, 1, 1e300)]);
+    let mut text = Vec::new();
+    floats.write_matrix_market(&mut text).unwrap();
+    let read = SparseMatrix::<f64>::read_matrix_market(text.as_slice()).unwrap();
+    assert_eq!(read.iter().collect::<Vec<_>>(), floats.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn matrix_market_symmetric_and_pattern() {
+    let text = "%%MatrixMarket matrix coordinate real symmetric\n% a comment\n\n3 3 3\n1 1 2.0\n3 1 -1.5\n\n3 2 4\n";
+    let matrix = SparseMatrix::<f64>::read_matrix_market(text.as_bytes()).unwrap();
+    assert_eq!(matrix.to_dense(), vec![vec![2.0, 0.0, -1.5], vec![0.0, 0.0, 4.0], vec![-1.5, 4.0, 0.0]]);
+
+    let text = "%%MatrixMarket matrix coordinate pattern general\n2 4 2\n1 4\n2 1\n";
+    let matrix = SparseMatrix::<u32>::read_matrix_market(text.as_bytes()).unwrap();
+    assert_eq!(matrix.iter().collect::<Vec<_>>(), vec![(0, 3, 1), (1, 0, 1)]);
+}
+
+#[test]
+fn matrix_market_errors_name_the_line() {
+    let cases = [
+        ("%%MatrixMarket matrix array real general\n2 2\n", "line 1"),
+        ("%%MatrixMarket matrix coordinate complex general\n", "line 1"),
+        ("%%MatrixMarket matrix coordinate real general\n% size next\n2 2\n", "line 3"),
+        ("%%MatrixMarket matrix coordinate real general\n2 2 2\n1 1 1.0\n3 1 1.0\n", "line 4"),
+        ("%%MatrixMarket matrix coordinate real general\n2 2 2\n1 1 1.0\n\n1 2 x\n", "line 5"),
+        ("%%MatrixMarket matrix coordinate real general\n2 2 1\n1 1 1.0\n2 2 1.0\n", "line 4"),
+        ("%%MatrixMarket matrix coordinate real general\n2 2 1\n1 1\n", "line 3"),
+        ("%%MatrixMarket matrix coordinate integer general\n2 2 1\n1 1 1.5\n", "line 3"),
+        ("%%MatrixMarket matrix coordinate real symmetric\n2 3 1\n1 1 1.0\n", "line 2"),
+    ];
+    for (text, line) in cases {
+        // Integer files are read as integers and everything else as floats
+        let result = if text.contains(" integer ") {
+            SparseMatrix::<i64>::read_matrix_market(text.as_bytes()).map(|_| ())
+        } else {
+            SparseMatrix::<f64>::read_matrix_market(text.as_bytes()).map(|_| ())
+        };
+        let err = result.unwrap_err().to_string();
+        assert!(err.starts_with(line), "{:?} gave {:?}, expected {}", text, err, line);
+    }
+
+    // Fewer entries than the size line promises
+    let text = "%%MatrixMarket matrix coordinate real general\n2 2 3\n1 1 1.0\n";
+    assert!(SparseMatrix::<f64>::read_matrix_market(text.as_bytes()).is_err());
+}
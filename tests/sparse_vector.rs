@@ -0,0 +1,120 @@
+// Every sparse vector operation against the same operation on the dense
+// vectors. Integer vectors are compared exactly, float ones within a
+// rounding error.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::position_index::IndexKind;
+use hw2_code::sparse_vector::SparseVector;
+
+const SIZES: [usize; 6] = [0, 1, 63, 64, 65, 3000];
+
+fn random_dense(rng: &mut StdRng, size: usize, density: f64) -> Vec<i64> {
+    (0..size).map(|_| if rng.gen_bool(density) { rng.gen_range(-50..=50) } else { 0 }).collect()
+}
+
+fn nonzero(dense: &[i64]) -> Vec<(u64, i64)> {
+    dense.iter().enumerate().filter(|(_, &v)| v != 0).map(|(i, &v)| (i as u64, v)).collect()
+}
+
+// Pairs of dense vectors of every size at a few densities, including
+// ones with nothing and with everything set
+fn cases(seed: u64) -> Vec<(Vec<i64>, Vec<i64>)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut cases = Vec::new();
+    for size in SIZES {
+        for (a, b) in [(0.0, 0.3), (0.05, 0.05), (0.3, 0.6), (1.0, 0.5), (1.0, 1.0)] {
+            cases.push((random_dense(&mut rng, size, a), random_dense(&mut rng, size, b)));
+        }
+    }
+    cases
+}
+
+#[test]
+fn holds_the_dense_entries() {
+    for (dense, _) in cases(1) {
+        let vector = SparseVector::from_dense(&dense);
+        assert_eq!(vector.size(), dense.len() as u64);
+        assert_eq!(vector.iter().collect::<Vec<_>>(), nonzero(&dense));
+        assert_eq!(vector.num_nonzero(), nonzero(&dense).len() as u64);
+        assert_eq!(vector.to_dense(), dense);
+        for i in 0..dense.len() as u64 + 2 {
+            assert_eq!(vector.get(i), dense.get(i as usize).copied().unwrap_or(0));
+        }
+
+        // Appending gives the same vector with either index
+        for kind in [IndexKind::Plain, IndexKind::SortedList] {
+            let mut appended = SparseVector::create_with_index(dense.len() as u64, kind);
+            for (i, &value) in dense.iter().enumerate() {
+                appended.append(value, i as u64);
+            }
+            appended.finalize();
+            assert_eq!(appended.to_dense(), dense, "{:?}", kind);
+        }
+    }
+}
+
+#[test]
+fn products_match_dense() {
+    for (x, y) in cases(2) {
+        let (sx, sy) = (SparseVector::from_dense(&x), SparseVector::from_dense(&y));
+        let dot: i64 = x.iter().zip(&y).map(|(a, b)| a * b).sum();
+        assert_eq!(sx.dot(&sy), dot);
+        assert_eq!(sy.dot(&sx), dot);
+        assert_eq!(sx.dot_dense(&y), dot);
+
+        let product: Vec<i64> = x.iter().zip(&y).map(|(a, b)| a * b).collect();
+        let sparse_product = sx.mul(&sy);
+        assert_eq!(sparse_product.to_dense(), product);
+        assert_eq!(sparse_product.iter().collect::<Vec<_>>(), nonzero(&product));
+    }
+}
+
+#[test]
+fn sums_match_dense() {
+    for (x, y) in cases(3) {
+        let (sx, sy) = (SparseVector::from_dense(&x), SparseVector::from_dense(&y));
+        let sum: Vec<i64> = x.iter().zip(&y).map(|(a, b)| a + b).collect();
+        assert_eq!(sx.add(&sy).iter().collect::<Vec<_>>(), nonzero(&sum));
+
+        let axpy: Vec<i64> = x.iter().zip(&y).map(|(a, b)| 3 * a + b).collect();
+        assert_eq!(sx.axpy(3, &sy).iter().collect::<Vec<_>>(), nonzero(&axpy));
+        let mut dense_y = y.clone();
+        sx.axpy_dense(3, &mut dense_y);
+        assert_eq!(dense_y, axpy);
+
+        // Adding the negation cancels everything out
+        let mut negated = SparseVector::from_dense(&x);
+        negated.scale(-1);
+        assert_eq!(sx.add(&negated).num_nonzero(), 0);
+    }
+}
+
+#[test]
+fn scaling_and_norms() {
+    let mut rng = StdRng::seed_from_u64(4);
+    for size in SIZES {
+        let dense: Vec<f64> = (0..size).map(|_| if rng.gen_bool(0.2) { rng.gen_range(-2.0..2.0) } else { 0.0 }).collect();
+        let mut vector = SparseVector::from_dense(&dense);
+        let l1: f64 = dense.iter().map(|v| v.abs()).sum();
+        let l2: f64 = dense.iter().map(|v| v * v).sum::<f64>().sqrt();
+        assert!((vector.l1_norm() - l1).abs() < 1e-9);
+        assert!((vector.l2_norm() - l2).abs() < 1e-9);
+
+        vector.scale(2.5);
+        assert!((vector.l2_norm() - 2.5 * l2).abs() < 1e-9);
+        assert_eq!(vector.to_dense(), dense.iter().map(|v| v * 2.5).collect::<Vec<_>>());
+
+        vector.scale(0.0);
+        assert_eq!(vector.num_nonzero(), 0);
+        assert_eq!(vector.size(), size as u64);
+    }
+}
+
+#[test]
+#[should_panic(expected = "different sizes")]
+fn sizes_have_to_match() {
+    let x = SparseVector::from_dense(&[1u32, 0, 2]);
+    let y = SparseVector::from_dense(&[1u32, 0]);
+    x.dot(&y);
+}
@@ -0,0 +1,171 @@
+// The packed integers and the string arena against plain Vecs, and the
+// sparse string array against the SparseArray<String> it was copied from.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::batch::QueryOrder;
+use hw2_code::packed_ints::{bits_needed, PackedInts};
+use hw2_code::position_index::IndexKind;
+use hw2_code::space_report::SpaceUsage;
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::sparse_str_array::SparseStrArray;
+use hw2_code::string_arena::StringArena;
+
+mod common;
+use common::temp_file;
+
+fn random_strings(rng: &mut StdRng, count: usize) -> Vec<String> {
+    let alphabet = ['a', 'b', 'z', 'é', 'ß', '中', '🧬'];
+    (0..count).map(|_| {
+        let len = rng.gen_range(0..12);
+        (0..len).map(|_| alphabet[rng.gen_range(0..alphabet.len())]).collect()
+    }).collect()
+}
+
+fn random_sparse_array(seed: u64, size: u64, density: f64, kind: IndexKind) -> SparseArray {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut sparse_array = SparseArray::create_with_index(size, kind);
+    for i in 0..size {
+        if rng.gen_bool(density) {
+            sparse_array.append(random_strings(&mut rng, 1).pop().unwrap(), i);
+        }
+    }
+    sparse_array.finalize();
+    sparse_array
+}
+
+#[test]
+fn packed_ints_hold_every_width() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for width in 0..=64 {
+        let values: Vec<u64> = (0..300).map(|_| rng.gen::<u64>() & hw2_code::broadword::low_mask(width)).collect();
+        let mut packed = PackedInts::new(width);
+        for &value in &values {
+            packed.push(value);
+        }
+        assert_eq!(packed.iter().collect::<Vec<_>>(), values, "width {}", width);
+
+        let fitted = PackedInts::from_values(&values);
+        assert_eq!(fitted.width(), bits_needed(values.iter().copied().max().unwrap()));
+        assert_eq!(fitted.iter().collect::<Vec<_>>(), values);
+
+        packed.widen(64);
+        assert_eq!(packed.iter().collect::<Vec<_>>(), values, "widened from {}", width);
+
+        let mut bytes = Vec::new();
+        fitted.write_to(&mut bytes).unwrap();
+        let read = PackedInts::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.iter().collect::<Vec<_>>(), values);
+    }
+}
+
+#[test]
+fn arena_gives_back_every_string() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for count in [0, 1, 2, 7, 1000] {
+        let strings = random_strings(&mut rng, count);
+        let arena = StringArena::from_strs(&strings);
+        assert_eq!(arena.len(), count);
+        assert_eq!(arena.num_bytes(), strings.iter().map(|s| s.len()).sum::<usize>());
+        assert_eq!(arena.iter().collect::<Vec<_>>(), strings);
+        arena.verify().unwrap();
+
+        let mut bytes = Vec::new();
+        arena.write_to(&mut bytes).unwrap();
+        let read = StringArena::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.iter().collect::<Vec<_>>(), strings);
+    }
+}
+
+#[test]
+fn arena_rejects_ends_inside_a_character() {
+    let arena = StringArena::from_strs(["中", "é"]);
+    let mut bytes = Vec::new();
+    arena.write_to(&mut bytes).unwrap();
+
+    // The ends are 3 and 5 packed into 3 bits right after the width and
+    // the count. Moving the first end to 1 splits the first character.
+    bytes[16] = (bytes[16] & !0b111) | 1;
+    assert!(StringArena::read_from(&mut bytes.as_slice()).is_err());
+
+    // A cut off buffer is not taken for a shorter arena either
+    let mut bytes = Vec::new();
+    arena.write_to(&mut bytes).unwrap();
+    bytes.pop();
+    assert!(StringArena::read_from(&mut bytes.as_slice()).is_err());
+}
+
+#[test]
+fn sparse_str_array_matches_the_sparse_array() {
+    for (seed, kind) in [(3, IndexKind::Plain), (4, IndexKind::SortedList)] {
+        let sparse_array = random_sparse_array(seed, 5000, 0.1, kind);
+        let str_array = SparseStrArray::from_sparse_array(&sparse_array);
+        assert_eq!(str_array.index_kind(), kind);
+        assert_eq!(str_array.num_elem(), sparse_array.num_elem());
+        for i in 0..str_array.size() + 1 {
+            assert_eq!(str_array.get(i), sparse_array.get(i).map(|s| s.as_str()), "get({})", i);
+            assert_eq!(str_array.num_elem_at(i), sparse_array.num_elem_at(i), "num_elem_at({})", i);
+        }
+        assert_eq!(str_array.num_elem_at(u64::MAX), str_array.num_elem());
+        for r in 0..=str_array.num_elem() {
+            assert_eq!(str_array.get_index_of(r), sparse_array.get_index_of(r));
+        }
+
+        let indices: Vec<u64> = (0..str_array.size()).rev().step_by(3).collect();
+        let expected: Vec<Option<&str>> = indices.iter().map(|&i| sparse_array.get(i).map(|s| s.as_str())).collect();
+        for order in [QueryOrder::AsGiven, QueryOrder::Sorted] {
+            let mut out = vec![None; indices.len()];
+            str_array.get_at_index_batch(&indices, &mut out, order);
+            assert_eq!(out, expected, "{:?}", order);
+        }
+    }
+}
+
+#[test]
+fn appending_builds_the_same_array() {
+    let sparse_array = random_sparse_array(5, 3000, 0.3, IndexKind::Plain);
+    let mut str_array = SparseStrArray::create(sparse_array.size());
+    for (i, elem) in sparse_array.iter() {
+        str_array.append(elem, i);
+    }
+    // Past the end, so it is ignored
+    str_array.append("ignored", sparse_array.size());
+    str_array.finalize();
+    str_array.verify().unwrap();
+    assert_eq!(str_array.iter().collect::<Vec<_>>(), sparse_array.iter().map(|(i, s)| (i, s.as_str())).collect::<Vec<_>>());
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    for (seed, kind) in [(6, IndexKind::Plain), (7, IndexKind::SortedList)] {
+        let str_array = SparseStrArray::from_sparse_array(&random_sparse_array(seed, 4000, 0.05, kind));
+        let file = temp_file(&format!("round_trip_{}", seed));
+        str_array.save(&file).unwrap();
+        let loaded = SparseStrArray::load(&file).unwrap();
+        loaded.verify().unwrap();
+        assert_eq!(loaded.index_kind(), IndexKind::Plain);
+        assert_eq!(loaded.size(), str_array.size());
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), str_array.iter().collect::<Vec<_>>());
+
+        // Anything after the strings means the file is not one save wrote
+        let mut bytes = std::fs::read(&file).unwrap();
+        bytes.push(0);
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(SparseStrArray::load(&file).is_err());
+        std::fs::remove_file(&file).unwrap();
+    }
+}
+
+#[test]
+fn arena_takes_less_space_than_strings() {
+    let sparse_array = random_sparse_array(8, 100_000, 0.5, IndexKind::Plain);
+    let str_array = SparseStrArray::from_sparse_array(&sparse_array);
+    assert!(str_array.get_overhead() < sparse_array.get_overhead());
+    assert!(str_array.space_report().bits < sparse_array.space_report().bits);
+
+    // The strings themselves are short, so a String header each would
+    // cost more than their bytes. The arena spends a few bits on each.
+    let values = &str_array.space_report().children[2];
+    let string_bytes: u64 = sparse_array.iter().map(|(_, s)| s.len() as u64).sum();
+    assert!(values.bits < (string_bytes + 4 * sparse_array.num_elem()) * 8 + 1024);
+}
@@ -0,0 +1,199 @@
+// The text formats read back what was written, report the line of a bad
+// entry, and build the same structures whether or not the input is sorted.
+use std::io::BufReader;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use hw2_code::bit_vector::BitVector;
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::sparse_vector::SparseVector;
+use hw2_code::text_formats::{
+    bit_vector_from_bits, bit_vector_from_positions, bit_vector_from_tsv, parse_bits, parse_positions, sparse_array_from_tsv,
+    sparse_vector_from_matrix_market, write_bits, write_matrix_market_vector, write_positions, write_tsv,
+    MatrixMarketVectorReader,
+};
+
+mod common;
+use common::random_bit_vector;
+
+fn ones(bit_vector: &BitVector) -> Vec<usize> {
+    (0..bit_vector.size()).filter(|&i| bit_vector.get(i)).collect()
+}
+
+fn error_message<T>(result: std::io::Result<T>) -> String {
+    match result {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn positions_and_bits_round_trip() {
+    for (seed, size) in [(1, 0), (2, 1), (3, 64), (4, 65), (5, 3000)] {
+        let bit_vector = random_bit_vector(seed, size, 0.2);
+
+        let mut text = Vec::new();
+        write_positions(&bit_vector, &mut text).unwrap();
+        let read = bit_vector_from_positions(text.as_slice(), Some(size as u64)).unwrap();
+        assert_eq!(read.size(), size);
+        assert_eq!(ones(&read), ones(&bit_vector), "positions of size {}", size);
+
+        let mut text = Vec::new();
+        write_bits(&bit_vector, &mut text).unwrap();
+        let read = bit_vector_from_bits(text.as_slice(), Some(size as u64)).unwrap();
+        read.verify().unwrap();
+        assert_eq!(read.size(), size);
+        assert_eq!(ones(&read), ones(&bit_vector), "bits of size {}", size);
+    }
+}
+
+#[test]
+fn size_defaults_to_past_the_largest_position() {
+    let text = "# comment\n5\n\n  2\n9\n";
+    assert_eq!(parse_positions(text.as_bytes()).collect::<std::io::Result<Vec<_>>>().unwrap(), vec![5, 2, 9]);
+    let bit_vector = bit_vector_from_positions(text.as_bytes(), None).unwrap();
+    assert_eq!(bit_vector.size(), 10);
+    assert_eq!(ones(&bit_vector), vec![2, 5, 9]);
+
+    let bit_vector = bit_vector_from_tsv("4\tx\n1\ty\n".as_bytes(), None).unwrap();
+    assert_eq!(bit_vector.size(), 5);
+    assert_eq!(ones(&bit_vector), vec![1, 4]);
+}
+
+#[test]
+fn errors_name_the_line() {
+    let message = error_message(bit_vector_from_positions("1\n# two\n\nthree\n".as_bytes(), None));
+    assert!(message.starts_with("line 4:"), "{}", message);
+    let message = error_message(bit_vector_from_positions("1\n2\n10\n".as_bytes(), Some(10)));
+    assert!(message.starts_with("line 3:"), "{}", message);
+    let message = error_message(bit_vector_from_bits("0101\n01x1\n".as_bytes(), None));
+    assert!(message.starts_with("line 2:"), "{}", message);
+    let message = error_message(sparse_array_from_tsv("1\ta\n2 b\n".as_bytes(), None, 1));
+    assert!(message.starts_with("line 2:"), "{}", message);
+    let message = error_message(sparse_array_from_tsv("1\ta\n0\tb\n7\tc\n".as_bytes(), Some(5), 1));
+    assert!(message.starts_with("line 3:"), "{}", message);
+}
+
+#[test]
+fn bits_have_to_match_the_size() {
+    assert!(bit_vector_from_bits("0110 1\n".as_bytes(), Some(5)).is_ok());
+    assert!(bit_vector_from_bits("0110 1\n".as_bytes(), Some(6)).is_err());
+}
+
+// Bits come out of a line as it is read, even one that never ends
+#[test]
+fn bits_stream_from_one_long_line() {
+    let endless = BufReader::with_capacity(16, std::io::repeat(b'1'));
+    assert!(parse_bits(endless).take(10_000).all(|bit| bit.unwrap()));
+
+    let bit_vector = random_bit_vector(7, 100_000, 0.5);
+    let line: String = (0..bit_vector.size()).map(|i| if bit_vector.get(i) { '1' } else { '0' }).collect();
+    let read = bit_vector_from_bits(BufReader::with_capacity(16, line.as_bytes()), None).unwrap();
+    assert_eq!(ones(&read), ones(&bit_vector));
+
+    // Line numbers carry over from one buffer to the next
+    let text = "0101 0101 0101\n\n1111 0000\n0101 01x1\n";
+    let message = error_message(bit_vector_from_bits(BufReader::with_capacity(3, text.as_bytes()), None));
+    assert!(message.starts_with("line 4: 'x'"), "{}", message);
+}
+
+#[test]
+fn sizes_past_the_largest_index_are_checked() {
+    let last = u64::MAX.to_string();
+    let message = error_message(bit_vector_from_positions(format!("{}\n", last).as_bytes(), None));
+    assert!(message.contains(&last), "{}", message);
+    assert!(sparse_array_from_tsv(format!("3\tx\n{}\ty\n", last).as_bytes(), None, 1).is_err());
+
+    // A far away index doesn't cost a bit for every position before it
+    let sparse_array = sparse_array_from_tsv("3\tx\n1000000000000\ty\n".as_bytes(), None, 1).unwrap();
+    assert_eq!(sparse_array.size(), 1_000_000_000_001);
+    assert_eq!(sparse_array.get(1_000_000_000_000).unwrap(), "y");
+}
+
+#[test]
+fn tsv_builds_the_same_array_sorted_or_not() {
+    let mut rng = StdRng::seed_from_u64(6);
+    let size = 2000;
+    let mut pairs: Vec<(u64, String)> = (0..size).filter(|_| rng.gen_bool(0.1)).map(|i| (i, format!("v{}", i))).collect();
+
+    let mut sorted = SparseArray::create(size);
+    for (i, value) in &pairs {
+        sorted.append(value.clone(), *i);
+    }
+    sorted.finalize();
+    let mut text = Vec::new();
+    write_tsv(&sorted, &mut text).unwrap();
+    let streamed = sparse_array_from_tsv(text.as_slice(), Some(size), 1).unwrap();
+    assert_eq!(streamed.iter().collect::<Vec<_>>(), sorted.iter().collect::<Vec<_>>());
+
+    // Shuffled with a repeated index, where the last value wins
+    let repeated = pairs[pairs.len() / 2].0;
+    pairs.push((repeated, "last".to_string()));
+    for i in (1..pairs.len() - 1).rev() {
+        pairs.swap(i, rng.gen_range(0..i));
+    }
+    let text: String = pairs.iter().map(|(i, value)| format!("{}\t{}\n", i, value)).collect();
+    for size_given in [Some(size), None] {
+        let collected = sparse_array_from_tsv(text.as_bytes(), size_given, 2).unwrap();
+        assert_eq!(collected.num_elem(), sorted.num_elem());
+        assert_eq!(collected.get(repeated).unwrap(), "last");
+        for (i, value) in sorted.iter().filter(|&(i, _)| i != repeated) {
+            assert_eq!(collected.get(i), Some(value));
+        }
+    }
+}
+
+#[test]
+fn tsv_values_keep_their_tabs_but_not_line_breaks() {
+    let sparse_array = sparse_array_from_tsv("3\ta\tb \n".as_bytes(), None, 1).unwrap();
+    assert_eq!(sparse_array.get(3).unwrap(), "a\tb ");
+
+    let mut sparse_array = SparseArray::create(4);
+    sparse_array.append("two\nlines".to_string(), 1);
+    sparse_array.finalize();
+    assert!(write_tsv(&sparse_array, &mut Vec::new()).is_err());
+}
+
+#[test]
+fn matrix_market_vectors_round_trip() {
+    let dense = [0.0, 1.5, 0.0, 0.0, -2.0, 0.0, 0.25];
+    let vector = SparseVector::from_dense(&dense);
+    let mut text = Vec::new();
+    write_matrix_market_vector(&vector, &mut text).unwrap();
+    let read: SparseVector<f64> = sparse_vector_from_matrix_market(text.as_slice()).unwrap();
+    assert_eq!(read.to_dense(), dense);
+
+    // Out of order and repeated entries are added up, pattern entries are 1
+    let text = "%%MatrixMarket vector coordinate integer general\n% c\n6 4\n5 2\n2 7\n5 3\n1 0\n";
+    let read: SparseVector<i64> = sparse_vector_from_matrix_market(text.as_bytes()).unwrap();
+    assert_eq!(read.to_dense(), vec![0, 7, 0, 0, 5, 0]);
+    let text = "%%MatrixMarket vector coordinate pattern general\n4 2\n2\n4\n";
+    let read: SparseVector<u8> = sparse_vector_from_matrix_market(text.as_bytes()).unwrap();
+    assert_eq!(read.to_dense(), vec![0, 1, 0, 1]);
+}
+
+#[test]
+fn matrix_market_vector_errors() {
+    let cases = [
+        ("%%MatrixMarket matrix coordinate real general\n", "line 1"),
+        ("%%MatrixMarket vector coordinate real symmetric\n", "line 1"),
+        ("%%MatrixMarket vector coordinate real general\n% size next\n4\n", "line 3"),
+        ("%%MatrixMarket vector coordinate real general\n4 2\n1 1.0\n5 1.0\n", "line 4"),
+        ("%%MatrixMarket vector coordinate real general\n4 1\n1 1.0\n2 1.0\n", "line 4"),
+        ("%%MatrixMarket vector coordinate real general\n4 1\n1 x\n", "line 3"),
+        ("%%MatrixMarket vector coordinate real general\n4 3\n1 1.0\n", "entries"),
+    ];
+    for (text, expected) in cases {
+        let message = error_message(sparse_vector_from_matrix_market::<f64, _>(text.as_bytes()));
+        assert!(message.contains(expected), "{:?} gave {}", text, message);
+    }
+
+    // The reader stops after the first error
+    let text = "%%MatrixMarket vector coordinate real general\n4 3\n1 1.0\n9 1.0\n2 1.0\n";
+    let mut entries = MatrixMarketVectorReader::<f64, _>::new(text.as_bytes()).unwrap();
+    assert_eq!((entries.size(), entries.num_entries()), (4, 3));
+    assert_eq!(entries.next().unwrap().unwrap(), (0, 1.0));
+    assert!(entries.next().unwrap().is_err());
+    assert!(entries.next().is_none());
+}
@@ -0,0 +1,162 @@
+// Pins down what the structures do for the smallest sizes, where the
+// chunk sizes come from the minimums instead of the log of the size
+use hw2_code::bit_vector::BitVector;
+use hw2_code::position_index::{IndexKind, PlainIndex};
+use hw2_code::rank_support::RankSupport;
+use hw2_code::select_support::SelectSupport;
+use hw2_code::sparse_array::SparseArray;
+use hw2_code::utils::{my_log, rank_block_sizes, MIN_CHUNK_SIZE, MIN_SUPERCHUNK_SIZE};
+
+mod common;
+use common::temp_file;
+
+#[test]
+fn my_log_is_defined_for_0_and_1() {
+    assert_eq!(my_log(0), 0);
+    assert_eq!(my_log(1), 0);
+    assert_eq!(my_log(2), 2);
+    assert_eq!(my_log(3), 2);
+    assert_eq!(my_log(4), 2);
+    assert_eq!(my_log(8), 4);
+    assert_eq!(my_log(16), 4);
+    assert_eq!(my_log(32), 6);
+}
+
+#[test]
+fn block_sizes_fall_back_to_the_minimums() {
+    for size in 0..8 {
+        assert_eq!(rank_block_sizes(size), (MIN_CHUNK_SIZE, MIN_SUPERCHUNK_SIZE), "size {}", size);
+    }
+    // From 8 bits on the log gives the sizes, as it always has
+    assert_eq!(rank_block_sizes(8), (2, 16));
+    assert_eq!(rank_block_sizes(16), (2, 16));
+    assert_eq!(rank_block_sizes(1000), (5, 100));
+    assert_eq!(rank_block_sizes(1 << 20), (10, 400));
+    for size in 0..5000 {
+        let (chunk_size, superchunk_size) = rank_block_sizes(size);
+        assert!(chunk_size >= 1 && superchunk_size % chunk_size == 0, "size {}", size);
+    }
+}
+
+#[test]
+fn empty_bit_vector() {
+    let bit_vector = BitVector::new(0);
+    let rank_support = RankSupport::new(&bit_vector);
+    let select_support = SelectSupport::new(&rank_support);
+    assert_eq!(rank_support.rank1(0), 0);
+    assert_eq!(rank_support.rank1(10), 0);
+    assert_eq!(select_support.select1(0), 0);
+    assert_eq!(select_support.select1(1), u64::MAX);
+    assert_eq!(rank_support.overhead(), 0);
+    rank_support.verify().unwrap();
+}
+
+#[test]
+fn single_bit() {
+    for value in [false, true] {
+        let mut bit_vector = BitVector::new(1);
+        bit_vector.set(0, value);
+        let rank_support = RankSupport::new(&bit_vector);
+        let select_support = SelectSupport::new(&rank_support);
+        assert_eq!(rank_support.rank1(0), 0);
+        assert_eq!(rank_support.rank1(1), value as u64);
+        assert_eq!(select_support.select1(0), 0);
+        assert_eq!(select_support.select1(1), if value { 1 } else { u64::MAX });
+        assert_eq!(select_support.select1(2), u64::MAX);
+    }
+}
+
+#[test]
+fn every_bit_vector_below_16_bits() {
+    for size in 0..16 {
+        for pattern in 0..1u32 << size {
+            let mut bit_vector = BitVector::new(size);
+            for i in 0..size {
+                bit_vector.set(i, pattern >> i & 1 == 1);
+            }
+            let rank_support = RankSupport::new(&bit_vector);
+            let select_support = SelectSupport::new(&rank_support);
+            let mut ones = 0;
+            for i in 0..size {
+                assert_eq!(rank_support.rank1(i), ones, "size {} pattern {:b} rank1({})", size, pattern, i);
+                if bit_vector.get(i) {
+                    ones += 1;
+                    assert_eq!(select_support.select1(ones), i as u64 + 1, "size {} pattern {:b}", size, pattern);
+                }
+            }
+            assert_eq!(rank_support.rank1(size), ones);
+            assert_eq!(select_support.select1(ones + 1), u64::MAX);
+        }
+    }
+}
+
+#[test]
+fn tiny_rank_support_saves_and_loads() {
+    for size in 0..4 {
+        let mut bit_vector = BitVector::new(size);
+        for i in (0..size).step_by(2) {
+            bit_vector.set(i, true);
+        }
+        let rank_support = RankSupport::new(&bit_vector);
+        let bit_vector_file = temp_file(&format!("bv_{}", size));
+        let rank_file = temp_file(&format!("rank_{}", size));
+        bit_vector.save(&bit_vector_file).unwrap();
+        rank_support.save(&rank_file).unwrap();
+
+        let loaded_bit_vector = BitVector::load(&bit_vector_file).unwrap();
+        let loaded = RankSupport::load(&loaded_bit_vector, &rank_file).unwrap();
+        loaded.verify().unwrap();
+        for i in 0..=size {
+            assert_eq!(loaded.rank1(i), rank_support.rank1(i));
+        }
+        std::fs::remove_file(bit_vector_file).unwrap();
+        std::fs::remove_file(rank_file).unwrap();
+    }
+}
+
+#[test]
+fn empty_sparse_array() {
+    for kind in [IndexKind::Plain, IndexKind::SortedList] {
+        let mut sparse_array: SparseArray = SparseArray::create_with_index(0, kind);
+        sparse_array.finalize();
+        assert_eq!(sparse_array.size(), 0);
+        assert_eq!(sparse_array.num_elem(), 0);
+        assert_eq!(sparse_array.get(0), None);
+        assert_eq!(sparse_array.get_rank1(0), 0);
+        assert_eq!(sparse_array.get_index_of(0), u64::MAX);
+        assert_eq!(sparse_array.next_present(0), None);
+        assert_eq!(sparse_array.prev_present(0), None);
+        assert_eq!(sparse_array.iter().count(), 0);
+        assert!(!sparse_array.remove(0));
+    }
+    let sparse_array: SparseArray = SparseArray::create(0);
+    let file = temp_file("sparse_0");
+    sparse_array.save(&file).unwrap();
+    let loaded = SparseArray::load(&file).unwrap();
+    loaded.verify().unwrap();
+    assert_eq!(loaded.size(), 0);
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn single_element_sparse_array() {
+    let mut sparse_array = SparseArray::create(1);
+    sparse_array.append("only".to_string(), 0);
+    sparse_array.finalize();
+    assert_eq!(sparse_array.num_elem_at(0), 1);
+    assert_eq!(sparse_array.get_index_of(0), 0);
+    let mut s = String::new();
+    assert!(sparse_array.get_at_rank(0, &mut s));
+    assert_eq!(s, "only");
+    assert!(sparse_array.remove(0));
+    assert_eq!(sparse_array.num_elem(), 0);
+    assert_eq!(sparse_array.set(0, "back".to_string()), Ok(None));
+    assert_eq!(sparse_array.get(0).map(|s| s.as_str()), Some("back"));
+}
+
+#[test]
+fn plain_index_size_estimate_is_finite_for_every_size() {
+    assert_eq!(PlainIndex::bits_for(0), 0);
+    assert_eq!(PlainIndex::bits_for(1), 64 + 64 + 16);
+    assert_eq!(IndexKind::for_density(0, 0), IndexKind::SortedList);
+}